@@ -0,0 +1,28 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+ROOT_DIR=$(cd "$(dirname "$0")/.." && pwd)
+INITFS_DIR="$ROOT_DIR/src/initfs"
+SRC="$INITFS_DIR/hello-musl.c"
+OUT="$INITFS_DIR/hello-musl"
+
+# musl-gcc が無い環境でも、rustup の x86_64-unknown-linux-musl ターゲットに同梱された
+# crt と libc.a を使って静的リンクする（rustup target add x86_64-unknown-linux-musl）
+CC=${CC:-gcc}
+MUSL_LIB=${1:-$(rustc --print sysroot)/lib/rustlib/x86_64-unknown-linux-musl/lib/self-contained}
+
+if [ ! -f "$MUSL_LIB/libc.a" ]; then
+    echo "Error: musl libc.a not found in $MUSL_LIB. Add the musl target or pass its lib dir as first arg."
+    exit 1
+fi
+
+echo "Compiling with: $CC; musl: $MUSL_LIB"
+
+TMPOBJ="$OUT.o"
+$CC -O2 -ffreestanding -fno-stack-protector -fno-pie -c -o "$TMPOBJ" "$SRC"
+$CC -static -nostdlib -no-pie -o "$OUT" \
+    "$MUSL_LIB/crt1.o" "$MUSL_LIB/crti.o" "$TMPOBJ" "$MUSL_LIB/libc.a" -lgcc "$MUSL_LIB/crtn.o"
+strip "$OUT"
+rm -f "$TMPOBJ"
+
+echo "Built: $OUT"
@@ -0,0 +1,153 @@
+//! システムコールのエラーコード
+//!
+//! エラーは`u64::MAX`から下向きに割り当てた値として戻り値（RAX）で返す。
+//! 既存の値は変えず、新しいエラーは下に追加する。
+
+/// システムコールのエラー
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Errno {
+    /// 未実装
+    NoSys = u64::MAX,
+    /// 無効な引数
+    Inval = u64::MAX - 1,
+    /// 受信/送信できない（キュー空/満杯）
+    Again = u64::MAX - 2,
+    /// ファイル/エントリが見つからない
+    NoEnt = u64::MAX - 3,
+    /// 入力が空
+    NoData = u64::MAX - 4,
+    /// メモリ不足
+    NoMem = u64::MAX - 5,
+    /// 不正なユーザー空間アドレス
+    Fault = u64::MAX - 6,
+    /// 待機が期限切れ
+    TimedOut = u64::MAX - 7,
+    /// 権限がない
+    Perm = u64::MAX - 8,
+    /// 対象の子プロセスがない
+    Child = u64::MAX - 9,
+    /// 不正なファイルディスクリプタ
+    BadF = u64::MAX - 10,
+    /// ディレクトリではない
+    NotDir = u64::MAX - 11,
+    /// ディレクトリである
+    IsDir = u64::MAX - 12,
+    /// パスや名前が長すぎる
+    NameTooLong = u64::MAX - 13,
+    /// 読み取り専用のファイルシステム
+    RoFs = u64::MAX - 14,
+    /// 開いているファイルが多すぎる
+    MFile = u64::MAX - 15,
+    /// シークできない
+    SPipe = u64::MAX - 16,
+    /// プロセスやスレッドが見つからない
+    Srch = u64::MAX - 17,
+    /// デバイスやリソースが使用中
+    Busy = u64::MAX - 18,
+    /// 入出力エラー（ハードウェアの異常や原因不明の失敗）
+    Io = u64::MAX - 19,
+    /// デバイスがない、または対応しない操作
+    NoDev = u64::MAX - 20,
+    /// 既に存在する
+    Exist = u64::MAX - 21,
+    /// 対応していない操作
+    NotSup = u64::MAX - 22,
+    /// 端末ではない（端末向けの操作）
+    NoTty = u64::MAX - 23,
+}
+
+/// エラーコードの最小値（これ以上の戻り値はエラー）
+const ERRNO_MIN: u64 = Errno::NoTty as u64;
+
+impl Errno {
+    /// 戻り値として返す値
+    pub const fn code(self) -> u64 {
+        self as u64
+    }
+
+    /// 戻り値がエラーならその種類を返す
+    pub fn from_code(value: u64) -> Option<Self> {
+        const ALL: [Errno; 24] = [
+            Errno::NoSys,
+            Errno::Inval,
+            Errno::Again,
+            Errno::NoEnt,
+            Errno::NoData,
+            Errno::NoMem,
+            Errno::Fault,
+            Errno::TimedOut,
+            Errno::Perm,
+            Errno::Child,
+            Errno::BadF,
+            Errno::NotDir,
+            Errno::IsDir,
+            Errno::NameTooLong,
+            Errno::RoFs,
+            Errno::MFile,
+            Errno::SPipe,
+            Errno::Srch,
+            Errno::Busy,
+            Errno::Io,
+            Errno::NoDev,
+            Errno::Exist,
+            Errno::NotSup,
+            Errno::NoTty,
+        ];
+        ALL.iter().copied().find(|e| e.code() == value)
+    }
+
+    /// 戻り値がエラーコードかどうか
+    pub const fn is_error(value: u64) -> bool {
+        value >= ERRNO_MIN
+    }
+}
+
+/// 未実装エラー
+pub const ENOSYS: u64 = Errno::NoSys.code();
+/// 無効な引数
+pub const EINVAL: u64 = Errno::Inval.code();
+/// 受信/送信できない（キュー空/満杯）
+pub const EAGAIN: u64 = Errno::Again.code();
+/// ファイル/エントリが見つからない
+pub const ENOENT: u64 = Errno::NoEnt.code();
+/// 入力が空
+pub const ENODATA: u64 = Errno::NoData.code();
+/// メモリ不足
+pub const ENOMEM: u64 = Errno::NoMem.code();
+/// 不正なユーザー空間アドレス
+pub const EFAULT: u64 = Errno::Fault.code();
+/// 待機が期限切れ
+pub const ETIMEDOUT: u64 = Errno::TimedOut.code();
+/// 権限がない
+pub const EPERM: u64 = Errno::Perm.code();
+/// 対象の子プロセスがない
+pub const ECHILD: u64 = Errno::Child.code();
+/// 不正なファイルディスクリプタ
+pub const EBADF: u64 = Errno::BadF.code();
+/// ディレクトリではない
+pub const ENOTDIR: u64 = Errno::NotDir.code();
+/// ディレクトリである
+pub const EISDIR: u64 = Errno::IsDir.code();
+/// パスや名前が長すぎる
+pub const ENAMETOOLONG: u64 = Errno::NameTooLong.code();
+/// 読み取り専用のファイルシステム
+pub const EROFS: u64 = Errno::RoFs.code();
+/// 開いているファイルが多すぎる
+pub const EMFILE: u64 = Errno::MFile.code();
+/// シークできない
+pub const ESPIPE: u64 = Errno::SPipe.code();
+/// プロセスやスレッドが見つからない
+pub const ESRCH: u64 = Errno::Srch.code();
+/// デバイスやリソースが使用中
+pub const EBUSY: u64 = Errno::Busy.code();
+/// 入出力エラー
+pub const EIO: u64 = Errno::Io.code();
+/// デバイスがない、または対応しない操作
+pub const ENODEV: u64 = Errno::NoDev.code();
+/// 既に存在する
+pub const EEXIST: u64 = Errno::Exist.code();
+/// 対応していない操作
+pub const ENOTSUP: u64 = Errno::NotSup.code();
+/// 端末ではない（端末向けの操作）
+pub const ENOTTY: u64 = Errno::NoTty.code();
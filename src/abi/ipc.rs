@@ -0,0 +1,6 @@
+//! IPCの引数
+
+/// タイムアウト指定: 待たずに返る（EAGAIN）
+pub const IPC_NONBLOCK: u64 = 0;
+/// タイムアウト指定: 無期限に待つ
+pub const IPC_WAIT_FOREVER: u64 = u64::MAX;
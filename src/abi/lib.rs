@@ -0,0 +1,22 @@
+//! カーネルとユーザー空間で共有するシステムコールABI
+//!
+//! システムコール番号、エラーコード、引数の構造体とフラグを定義する。
+//! カーネルのディスパッチとユーザー側のランタイムはどちらもこのクレートを参照し、
+//! 両者の定義が食い違わないようにする。
+
+#![no_std]
+
+pub mod errno;
+pub mod ipc;
+pub mod memory;
+pub mod number;
+pub mod process;
+pub mod signal;
+pub mod time;
+
+pub use errno::{
+    Errno, EAGAIN, EBADF, EBUSY, ECHILD, EEXIST, EFAULT, EINVAL, EIO, EISDIR, EMFILE, ENAMETOOLONG, ENODATA,
+    ENODEV, ENOENT, ENOMEM, ENOSYS, ENOTDIR, ENOTSUP, ENOTTY, EPERM, EROFS, ESPIPE, ESRCH, ETIMEDOUT,
+};
+pub use number::{SyscallNumber, NATIVE_SYSCALL_BASE};
+pub use time::TICKS_PER_SECOND;
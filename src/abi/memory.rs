@@ -0,0 +1,17 @@
+//! メモリ系システムコールのフラグ
+
+/// 読み取り可能
+pub const PROT_READ: u64 = 0x1;
+/// 書き込み可能
+pub const PROT_WRITE: u64 = 0x2;
+/// 実行可能
+pub const PROT_EXEC: u64 = 0x4;
+
+/// 共有マッピング（未対応）
+pub const MAP_SHARED: u64 = 0x01;
+/// プライベートマッピング
+pub const MAP_PRIVATE: u64 = 0x02;
+/// 指定アドレスに配置（既存のマッピングは置き換える）
+pub const MAP_FIXED: u64 = 0x10;
+/// 匿名マッピング
+pub const MAP_ANONYMOUS: u64 = 0x20;
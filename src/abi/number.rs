@@ -0,0 +1,104 @@
+//! システムコール番号
+
+/// ネイティブシステムコール番号の開始値
+///
+/// Linux互換の番号と重ならないよう、ネイティブの番号はこの値から割り当てる
+pub const NATIVE_SYSCALL_BASE: u64 = 0x1000;
+
+/// システムコール番号
+///
+/// 番号はRAX、引数はRDI, RSI, RDX, R10, R8, R9の順に渡す
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallNumber {
+    /// スケジューラへ譲る
+    Yield = NATIVE_SYSCALL_BASE + 1,
+    /// タイマーティック数を取得
+    GetTicks = NATIVE_SYSCALL_BASE + 2,
+    /// IPC送信 (arg0=dest_thread_id, arg1=value, arg2=timeout_ticks)
+    IpcSend = NATIVE_SYSCALL_BASE + 3,
+    /// IPC受信 (arg0=sender_ptr, arg1=timeout_ticks)
+    IpcRecv = NATIVE_SYSCALL_BASE + 4,
+    /// コンソールへ書き込み (arg0=buf_ptr, arg1=len)
+    ConsoleWrite = NATIVE_SYSCALL_BASE + 5,
+    /// initfs 読み込み (arg0=path_ptr, arg1=path_len, arg2=buf_ptr, arg3=buf_len)
+    InitfsRead = NATIVE_SYSCALL_BASE + 6,
+    /// 現在のプロセスを終了 (arg0=exit_code)
+    Exit = NATIVE_SYSCALL_BASE + 7,
+    /// キーボード1文字読み取り
+    KeyboardRead = NATIVE_SYSCALL_BASE + 8,
+    /// 現在のスレッドIDを取得
+    GetThreadId = NATIVE_SYSCALL_BASE + 9,
+    /// スレッド名からIDを取得 (arg0=name_ptr, arg1=name_len)
+    GetThreadIdByName = NATIVE_SYSCALL_BASE + 10,
+    /// 匿名メモリをマップ (arg0=addr, arg1=len, arg2=prot, arg3=flags)
+    Mmap = NATIVE_SYSCALL_BASE + 11,
+    /// メモリのマップを解除 (arg0=addr, arg1=len)
+    Munmap = NATIVE_SYSCALL_BASE + 12,
+    /// メモリの保護属性を変更 (arg0=addr, arg1=len, arg2=prot)
+    Mprotect = NATIVE_SYSCALL_BASE + 13,
+    /// プログラムブレークを設定 (arg0=addr、0なら現在値を返す)
+    Brk = NATIVE_SYSCALL_BASE + 14,
+    /// プログラムブレークを増減 (arg0=increment、符号付き)
+    Sbrk = NATIVE_SYSCALL_BASE + 15,
+    /// 指定ティック数スリープ (arg0=ticks)
+    Sleep = NATIVE_SYSCALL_BASE + 16,
+    /// 同じプロセスにスレッドを作成 (arg0=entry, arg1=stack_top, arg2=arg)
+    ThreadCreate = NATIVE_SYSCALL_BASE + 17,
+    /// スレッドの終了を待つ (arg0=thread_id)
+    ThreadJoin = NATIVE_SYSCALL_BASE + 18,
+    /// 現在のスレッドを終了 (arg0=exit_code)
+    ThreadExit = NATIVE_SYSCALL_BASE + 19,
+    /// initfsのELFからプロセスを起動 (arg0=path_ptr, arg1=path_len, arg2=argv_ptr, arg3=envp_ptr, arg4=privilege)
+    ///
+    /// argv/envpは`StrRef`の配列で、`ptr`が0の要素で終わる
+    Spawn = NATIVE_SYSCALL_BASE + 20,
+    /// 子プロセスの終了を待って回収 (arg0=pid, arg1=status_ptr, arg2=options)
+    Wait = NATIVE_SYSCALL_BASE + 21,
+    /// プロセスにシグナルを送る (arg0=pid, arg1=sig)
+    Kill = NATIVE_SYSCALL_BASE + 22,
+    /// シグナルの動作を設定 (arg0=sig, arg1=handler, arg2=restorer)
+    SigAction = NATIVE_SYSCALL_BASE + 23,
+    /// シグナルハンドラから復帰（restorerから呼ぶ）
+    SigReturn = NATIVE_SYSCALL_BASE + 24,
+    /// システムコールのトレースを切り替える (arg0=pid、0なら自プロセス, arg1=enable)
+    ///
+    /// 以前の設定（0/1）を返す。Userの権限では使えない
+    Trace = NATIVE_SYSCALL_BASE + 25,
+}
+
+impl SyscallNumber {
+    /// 定義されているすべての番号
+    pub const ALL: [SyscallNumber; 25] = [
+        Self::Yield,
+        Self::GetTicks,
+        Self::IpcSend,
+        Self::IpcRecv,
+        Self::ConsoleWrite,
+        Self::InitfsRead,
+        Self::Exit,
+        Self::KeyboardRead,
+        Self::GetThreadId,
+        Self::GetThreadIdByName,
+        Self::Mmap,
+        Self::Munmap,
+        Self::Mprotect,
+        Self::Brk,
+        Self::Sbrk,
+        Self::Sleep,
+        Self::ThreadCreate,
+        Self::ThreadJoin,
+        Self::ThreadExit,
+        Self::Spawn,
+        Self::Wait,
+        Self::Kill,
+        Self::SigAction,
+        Self::SigReturn,
+        Self::Trace,
+    ];
+
+    /// RAXの値から番号を得る（ネイティブの番号でなければNone）
+    pub fn from_u64(value: u64) -> Option<Self> {
+        Self::ALL.iter().copied().find(|n| *n as u64 == value)
+    }
+}
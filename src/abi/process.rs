@@ -0,0 +1,38 @@
+//! プロセス系システムコールの引数
+
+/// Spawnの権限指定: Core（ユーザー空間からは指定できない）
+pub const PRIVILEGE_CORE: u64 = 0;
+/// Spawnの権限指定: システムサービス
+pub const PRIVILEGE_SERVICE: u64 = 1;
+/// Spawnの権限指定: 一般アプリケーション
+pub const PRIVILEGE_USER: u64 = 2;
+
+/// Waitのオプション: 終了した子がなければ待たずに0を返す
+pub const WNOHANG: u64 = 1;
+
+/// Spawnのargv/envpの要素（ユーザー空間の文字列）
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StrRef {
+    /// 文字列のアドレス（0は配列の終端）
+    pub ptr: u64,
+    /// 文字列のバイト数
+    pub len: u64,
+}
+
+impl StrRef {
+    /// 配列の終端
+    pub const END: StrRef = StrRef { ptr: 0, len: 0 };
+
+    pub fn new(s: &str) -> Self {
+        Self {
+            ptr: s.as_ptr() as u64,
+            len: s.len() as u64,
+        }
+    }
+
+    /// 配列の終端かどうか
+    pub fn is_end(&self) -> bool {
+        self.ptr == 0
+    }
+}
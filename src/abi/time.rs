@@ -0,0 +1,4 @@
+//! 時刻の単位
+
+/// 1秒あたりのタイマーティック数
+pub const TICKS_PER_SECOND: u64 = 100;
@@ -3,12 +3,22 @@
 
 extern crate alloc;
 
+use swiftcore::mem::paging::PHYS_MAP_START;
 use swiftcore::{kernel_entry, BootInfo, MemoryRegion, MemoryType};
 use uefi::prelude::*;
 use uefi::proto::console::gop::GraphicsOutput;
+use uefi::table::boot::{AllocateType, BootServices};
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr3};
+use x86_64::registers::model_specific::{Efer, EferFlags};
+use x86_64::structures::paging::{PageTable, PageTableFlags};
+use x86_64::{PhysAddr, VirtAddr};
 
-#[global_allocator]
-static ALLOCATOR: uefi::allocator::Allocator = uefi::allocator::Allocator;
+/// ページテーブル1つのサイズ
+const TABLE_SIZE: u64 = 4096;
+/// 2MiBページのサイズ
+const HUGE_PAGE_SIZE: u64 = 0x20_0000;
+/// ページディレクトリ1つがマップする範囲（1GiB）
+const DIRECTORY_SPAN: u64 = HUGE_PAGE_SIZE * 512;
 
 static mut BOOT_INFO: BootInfo = BootInfo {
     physical_memory_offset: 0,
@@ -71,6 +81,10 @@ fn main(_image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
         )
     };
 
+    if map_physical_memory(system_table.boot_services()).is_err() {
+        return Status::OUT_OF_RESOURCES;
+    }
+
     // Boot Servicesを終了してメモリマップを取得
     let (_system_table, memory_map_iter) =
         unsafe { system_table.exit_boot_services(uefi::table::boot::MemoryType::LOADER_DATA) };
@@ -105,7 +119,7 @@ fn main(_image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
 
     #[allow(static_mut_refs)]
     unsafe {
-        BOOT_INFO.physical_memory_offset = 0;
+        BOOT_INFO.physical_memory_offset = PHYS_MAP_START;
         BOOT_INFO.framebuffer_addr = fb_addr;
         BOOT_INFO.framebuffer_size = fb_size;
         BOOT_INFO.screen_width = screen_w;
@@ -120,3 +134,56 @@ fn main(_image_handle: Handle, mut system_table: SystemTable<Boot>) -> Status {
         kernel_entry(&*core::ptr::addr_of!(BOOT_INFO));
     }
 }
+
+/// 物理メモリ全体を`PHYS_MAP_START`から2MiBページでマップする
+///
+/// UEFIのページテーブルに上位半分のエントリを追加する。テーブルはLOADER_DATAとして確保するため、
+/// カーネルのフレームアロケータには渡らない
+fn map_physical_memory(boot_services: &BootServices) -> uefi::Result<()> {
+    let end = boot_services
+        .memory_map(uefi::table::boot::MemoryType::LOADER_DATA)?
+        .entries()
+        .map(|desc| desc.phys_start + desc.page_count * 4096)
+        .max()
+        .unwrap_or(0);
+
+    // ページディレクトリポインタテーブルとページディレクトリをまとめて確保
+    let directories = end.div_ceil(DIRECTORY_SPAN);
+    let pointers = directories.div_ceil(512);
+    let tables = boot_services.allocate_pages(
+        AllocateType::AnyPages,
+        uefi::table::boot::MemoryType::LOADER_DATA,
+        (pointers + directories) as usize,
+    )?;
+    let table_at = |index: u64| unsafe { &mut *((tables + index * TABLE_SIZE) as *mut PageTable) };
+
+    let table_flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    let page_flags = table_flags | PageTableFlags::HUGE_PAGE | PageTableFlags::NO_EXECUTE;
+
+    unsafe {
+        core::ptr::write_bytes(tables as *mut u8, 0, ((pointers + directories) * TABLE_SIZE) as usize);
+
+        // NXビットを使うためEFER.NXEを有効化
+        Efer::update(|flags| flags.insert(EferFlags::NO_EXECUTE_ENABLE));
+        // ファームウェアがページテーブルを書き込み禁止にしている場合に備えてWPを外す
+        let cr0 = Cr0::read();
+        Cr0::write(cr0 - Cr0Flags::WRITE_PROTECT);
+
+        let pml4 = &mut *(Cr3::read().0.start_address().as_u64() as *mut PageTable);
+        let first = usize::from(VirtAddr::new(PHYS_MAP_START).p4_index());
+        for pointer in 0..pointers {
+            pml4[first + pointer as usize].set_addr(PhysAddr::new(tables + pointer * TABLE_SIZE), table_flags);
+        }
+        for directory in 0..directories {
+            let phys = tables + (pointers + directory) * TABLE_SIZE;
+            table_at(directory / 512)[(directory % 512) as usize].set_addr(PhysAddr::new(phys), table_flags);
+            for (i, entry) in table_at(pointers + directory).iter_mut().enumerate() {
+                entry.set_addr(PhysAddr::new(directory * DIRECTORY_SPAN + i as u64 * HUGE_PAGE_SIZE), page_flags);
+            }
+        }
+
+        Cr0::write(cr0);
+    }
+
+    Ok(())
+}
@@ -53,6 +53,7 @@ impl KeyBuffer {
 
 struct KeyboardState {
     shift: bool,
+    ctrl: bool,
     caps: bool,
     extended: bool,
     buffer: KeyBuffer,
@@ -62,6 +63,7 @@ impl KeyboardState {
     const fn new() -> Self {
         Self {
             shift: false,
+            ctrl: false,
             caps: false,
             extended: false,
             buffer: KeyBuffer::new(),
@@ -106,6 +108,12 @@ fn handle_scancode(scancode: u8) {
             kbd.extended = false;
             return;
         }
+        // 左Ctrl（右CtrlはE0に続けて同じコード）
+        0x1D => {
+            kbd.ctrl = !released;
+            kbd.extended = false;
+            return;
+        }
         0x3A if !released => {
             kbd.caps = !kbd.caps;
             kbd.extended = false;
@@ -125,7 +133,12 @@ fn handle_scancode(scancode: u8) {
     }
 
     if let Some(ch) = scancode_to_ascii(code, kbd.shift, kbd.caps) {
-        kbd.buffer.push(ch);
+        // Ctrl+英字は制御文字（Ctrl+Cは0x03）にする
+        if kbd.ctrl && ch.is_ascii_alphabetic() {
+            kbd.buffer.push(ch.to_ascii_uppercase() & 0x1F);
+        } else {
+            kbd.buffer.push(ch);
+        }
     }
 }
 
@@ -11,8 +11,12 @@ pub enum KernelError {
     Memory(MemoryError),
     /// プロセスエラー
     Process(ProcessError),
+    /// ファイルシステムエラー
+    Fs(FileSystemError),
     /// デバイスエラー
     Device(DeviceError),
+    /// プロセス間通信エラー
+    Ipc(IpcError),
     /// 無効なパラメータ
     InvalidParam,
     /// 未実装の機能
@@ -59,6 +63,8 @@ pub enum ProcessError {
     Timeout,
     /// 暴走プロセス検出
     RogueProcessDetected,
+    /// 待つ対象の子プロセスがない
+    NoChild,
     /// サービス関連
     Service(ServiceError),
     ///　未知のエラー
@@ -111,6 +117,52 @@ pub enum DeviceError {
     CommunicationLost,
     /// リソース不足
     ResourceUnavailable,
+    /// 読み取れるデータがない
+    NoData,
+    /// 未知のエラー
+    UnknownError,
+}
+
+/// ファイルシステム関連のエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSystemError {
+    /// ファイルが見つからない
+    NotFound,
+    /// ディレクトリではない
+    NotADirectory,
+    /// ディレクトリである
+    IsADirectory,
+    /// 読み取り専用
+    ReadOnly,
+    /// 不正なファイルディスクリプタ
+    BadDescriptor,
+    /// 開いているファイルが多すぎる
+    TooManyOpenFiles,
+    /// パスが長すぎる
+    NameTooLong,
+    /// シークできないファイル
+    NotSeekable,
+    /// マップできないファイル
+    NotMappable,
+    /// 端末ではない（端末の操作に対応しないファイル）
+    NotATerminal,
+    /// イメージが壊れている
+    Corrupted,
+    /// 未知のエラー
+    UnknownError,
+}
+
+/// プロセス間通信関連のエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpcError {
+    /// 宛先のキューが満杯
+    BufferFull,
+    /// 受信するメッセージがない
+    Empty,
+    /// 待機が期限切れ
+    Timeout,
+    /// 宛先のスレッドが存在しない
+    InvalidTarget,
     /// 未知のエラー
     UnknownError,
 }
@@ -132,10 +184,12 @@ impl KernelError {
     /// このエラーがリトライ可能かどうか
     ///
     /// リトライ可能なエラーは、一時的な問題であり、再試行によって成功する可能性がある
+    /// - `IpcError::BufferFull`
     /// - `DeviceError::Busy`
     /// - `DeviceError::Timeout`
     pub fn is_retryable(&self) -> bool {
         match self {
+            KernelError::Ipc(IpcError::BufferFull) => true,
             KernelError::Device(DeviceError::Busy) => true,
             KernelError::Device(DeviceError::Timeout) => true,
             _ => false,
@@ -149,7 +203,9 @@ impl fmt::Display for KernelError {
         match self {
             KernelError::Memory(e) => write!(f, "Memory error: {:?}", e),
             KernelError::Process(e) => write!(f, "Process error: {:?}", e),
+            KernelError::Fs(e) => write!(f, "File system error: {:?}", e),
             KernelError::Device(e) => write!(f, "Device error: {:?}", e),
+            KernelError::Ipc(e) => write!(f, "IPC error: {:?}", e),
             KernelError::InvalidParam => write!(f, "Invalid parameter"),
             KernelError::NotImplemented => write!(f, "Not implemented"),
             KernelError::UnknownError => write!(f, "Unknown error"),
@@ -185,9 +241,15 @@ pub fn handle_kernel_error(error: KernelError) {
         KernelError::Process(proc_err) => {
             crate::error!("Process error: {:?}", proc_err);
         }
+        KernelError::Fs(fs_err) => {
+            crate::error!("File system error: {:?}", fs_err);
+        }
         KernelError::Device(dev_err) => {
             crate::error!("Device error: {:?}", dev_err);
         }
+        KernelError::Ipc(ipc_err) => {
+            crate::error!("IPC error: {:?}", ipc_err);
+        }
         _ => {
             crate::error!("Unknown error: {:?}", error);
         }
@@ -212,6 +274,8 @@ mod tests {
     #[test]
     fn test_error_is_retryable() {
         assert!(KernelError::Device(DeviceError::Busy).is_retryable());
+        assert!(KernelError::Ipc(IpcError::BufferFull).is_retryable());
+        assert!(!KernelError::Ipc(IpcError::InvalidTarget).is_retryable());
         assert!(!KernelError::Memory(MemoryError::OutOfMemory).is_retryable());
     }
 }
@@ -1,7 +1,10 @@
 //! 起動時にメモリへ展開済みのext2 (read-only)
 
+use alloc::vec::Vec;
 use core::str;
 
+use crate::error::{FileSystemError, KernelError, Result};
+
 const EXT2_MAGIC: u16 = 0xEF53;
 const EXT2_IMAGE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/initfs.ext2"));
 
@@ -24,10 +27,21 @@ struct Inode {
 	blocks: [u32; 15],
 }
 
+/// ファイルの情報
 #[derive(Debug, Clone, Copy)]
+pub struct Metadata {
+	/// inode番号
+	pub inode: u32,
+	/// サイズ（バイト）
+	pub size: u64,
+	/// ディレクトリかどうか
+	pub is_dir: bool,
+}
+
+#[derive(Debug, Clone)]
 pub struct FsEntry<'a> {
 	pub name: &'a str,
-	pub data: &'a [u8],
+	pub data: Vec<u8>,
 }
 
 pub struct FsEntries<'a> {
@@ -132,23 +146,15 @@ fn data_block_number(image: &[u8], sb: Superblock, inode: Inode, block_index: us
 	read_u32(block, idx * 4)
 }
 
-const READ_BUFFER_SIZE: usize = 4 * 1024 * 1024;
-static mut READ_BUFFER: [u8; READ_BUFFER_SIZE] = [0; READ_BUFFER_SIZE];
-
-fn read_inode_data(image: &[u8], sb: Superblock, inode_num: u32) -> Option<&'static [u8]> {
+fn read_inode_data(image: &[u8], sb: Superblock, inode_num: u32) -> Option<Vec<u8>> {
 	let inode = inode(image, sb, inode_num)?;
-	if is_dir(inode.mode) {
-		return Some(&[]);
-	}
-	if inode.size == 0 {
-		return Some(&[]);
+	if is_dir(inode.mode) || inode.size == 0 {
+		return Some(Vec::new());
 	}
 	let size = inode.size as usize;
-	if size > READ_BUFFER_SIZE {
-		return None;
-	}
-	let blocks_needed = (size + sb.block_size as usize - 1) / sb.block_size as usize;
-	let mut written = 0usize;
+	let blocks_needed = size.div_ceil(sb.block_size as usize);
+	let mut data = Vec::new();
+	data.try_reserve_exact(size).ok()?;
 
 	for block_idx in 0..blocks_needed {
 		let block_num = data_block_number(image, sb, inode, block_idx)?;
@@ -156,17 +162,14 @@ fn read_inode_data(image: &[u8], sb: Superblock, inode_num: u32) -> Option<&'sta
 			return None;
 		}
 		let block = block_slice(image, sb.block_size, block_num)?;
-		let to_copy = core::cmp::min(block.len(), size - written);
-		unsafe {
-			READ_BUFFER[written..written + to_copy].copy_from_slice(&block[..to_copy]);
-		}
-		written += to_copy;
-		if written >= size {
+		let to_copy = core::cmp::min(block.len(), size - data.len());
+		data.extend_from_slice(&block[..to_copy]);
+		if data.len() >= size {
 			break;
 		}
 	}
 
-	unsafe { Some(&READ_BUFFER[..size]) }
+	Some(data)
 }
 
 impl<'a> FsEntries<'a> {
@@ -220,7 +223,7 @@ impl<'a> Iterator for FsEntries<'a> {
 
 			let name_bytes = data.get(base + 8..base + 8 + name_len)?;
 			let name = str::from_utf8(name_bytes).ok()?;
-			let data = read_inode_data(self.image, self.sb, inode).unwrap_or(&[]);
+			let data = read_inode_data(self.image, self.sb, inode).unwrap_or_default();
 			return Some(FsEntry { name, data });
 		}
 	}
@@ -265,31 +268,34 @@ fn find_inode_in_dir(image: &[u8], sb: Superblock, dir_inode: Inode, name: &str)
 	None
 }
 
-fn read_path(path: &str) -> Option<&'static [u8]> {
-	let sb = superblock(EXT2_IMAGE)?;
-	let mut current = inode(EXT2_IMAGE, sb, 2)?; // root
+/// パスをたどり、最後の要素のinode番号とinodeを返す（ディレクトリも含む。空のパスはroot）
+fn walk(path: &str) -> core::result::Result<(Superblock, u32, Inode), FileSystemError> {
+	let sb = superblock(EXT2_IMAGE).ok_or(FileSystemError::Corrupted)?;
+	let mut current_num = 2; // root
+	let mut current = inode(EXT2_IMAGE, sb, current_num).ok_or(FileSystemError::Corrupted)?;
 
-	let mut parts = path.split('/').filter(|p| !p.is_empty()).peekable();
-	if parts.peek().is_none() {
-		return None;
+	for part in path.split('/').filter(|p| !p.is_empty()) {
+		if !is_dir(current.mode) {
+			return Err(FileSystemError::NotADirectory);
+		}
+		current_num = find_inode_in_dir(EXT2_IMAGE, sb, current, part).ok_or(FileSystemError::NotFound)?;
+		current = inode(EXT2_IMAGE, sb, current_num).ok_or(FileSystemError::Corrupted)?;
 	}
+	Ok((sb, current_num, current))
+}
 
-	while let Some(part) = parts.next() {
-		let is_last = parts.peek().is_none();
-		let inode_num = find_inode_in_dir(EXT2_IMAGE, sb, current, part)?;
-		let next_inode = inode(EXT2_IMAGE, sb, inode_num)?;
-		if is_last {
-			if is_dir(next_inode.mode) {
-				return None;
-			}
-			return read_inode_data(EXT2_IMAGE, sb, inode_num);
-		}
-		if !is_dir(next_inode.mode) {
-			return None;
-		}
-		current = next_inode;
+/// パスのファイルのinode番号を探す（ディレクトリや存在しないパスはエラー）
+fn find_file(path: &str) -> core::result::Result<(Superblock, u32), FileSystemError> {
+	let (sb, inode_num, inode) = walk(path)?;
+	if is_dir(inode.mode) {
+		return Err(FileSystemError::IsADirectory);
 	}
-	None
+	Ok((sb, inode_num))
+}
+
+fn read_path(path: &str) -> core::result::Result<Vec<u8>, FileSystemError> {
+	let (sb, inode_num) = find_file(path)?;
+	read_inode_data(EXT2_IMAGE, sb, inode_num).ok_or(FileSystemError::Corrupted)
 }
 
 /// 初期FSを初期化して情報を出力
@@ -321,8 +327,27 @@ pub fn init() {
 }
 
 /// ファイルを取得
-pub fn read(name: &str) -> Option<&'static [u8]> {
-	read_path(name)
+///
+/// 内容はヒープ上にコピーして返す
+pub fn read(name: &str) -> Result<Vec<u8>> {
+	read_path(name).map_err(KernelError::Fs)
+}
+
+/// ファイルが存在するか確認する
+///
+/// 内容はコピーしない。存在しない場合やディレクトリの場合はその原因を返す
+pub fn lookup(name: &str) -> Result<()> {
+	find_file(name).map(|_| ()).map_err(KernelError::Fs)
+}
+
+/// パスの情報を取得（ディレクトリも対象）
+pub fn metadata(name: &str) -> Result<Metadata> {
+	let (_, inode_num, inode) = walk(name).map_err(KernelError::Fs)?;
+	Ok(Metadata {
+		inode: inode_num,
+		size: inode.size as u64,
+		is_dir: is_dir(inode.mode),
+	})
 }
 
 /// ファイル一覧を取得（root直下）
@@ -35,7 +35,13 @@ pub fn kinit(boot_info: &'static BootInfo) -> Result<&'static [MemoryRegion]> {
     driver::ps2_keyboard::init();
 
     mem::init(boot_info.physical_memory_offset);
+    mem::paging::reserve_identity_ranges(
+        memory_map,
+        boot_info.framebuffer_addr,
+        boot_info.framebuffer_size as u64,
+    );
     mem::init_frame_allocator(memory_map)?;
+    task::fpu::init();
 
     unsafe {
         x86_64::instructions::interrupts::enable();
@@ -2,10 +2,11 @@
 //!
 //! IDTの初期化と例外ハンドラの定義
 
-use crate::{debug, error, mem::gdt, syscall, warn};
+use super::trap;
+use crate::{debug, error, mem::gdt, warn};
 use spin::Once;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
-use x86_64::PrivilegeLevel;
+use x86_64::{PrivilegeLevel, VirtAddr};
 
 static IDT: Once<InterruptDescriptorTable> = Once::new();
 
@@ -52,7 +53,10 @@ pub fn init() {
         idt.virtualization.set_handler_fn(virtualization_handler);
 
         // ハードウェア割り込みハンドラ（32-47番）
-        idt[32].set_handler_fn(super::timer::timer_interrupt_handler); // Timer
+        // タイマーはトラップフレームを積む入口を使う（プリエンプション用）
+        unsafe {
+            idt[trap::TIMER_VECTOR].set_handler_addr(VirtAddr::new(trap::timer_entry())); // Timer
+        }
         idt[33].set_handler_fn(keyboard_interrupt_handler); // Keyboard
 
         // それ以外のハードウェア割り込みはとりあえずスタブ
@@ -61,13 +65,17 @@ pub fn init() {
         }
 
         // システムコール割り込み (0x80)
-        idt[0x80]
-            .set_handler_fn(syscall::syscall_interrupt_handler)
-            .set_privilege_level(PrivilegeLevel::Ring3);
+        // スレッド切り替え要求 (0x81) はカーネル内からのみ
+        unsafe {
+            idt[trap::SYSCALL_VECTOR]
+                .set_handler_addr(VirtAddr::new(trap::syscall_entry()))
+                .set_privilege_level(PrivilegeLevel::Ring3);
+            idt[trap::YIELD_VECTOR].set_handler_addr(VirtAddr::new(trap::yield_entry()));
+        }
 
         // 48-255番も念のため設定（未使用の割り込みベクタ）
         for i in 48..=255 {
-            if i == 0x80 {
+            if i == trap::SYSCALL_VECTOR || i == trap::YIELD_VECTOR {
                 continue;
             }
             idt[i].set_handler_fn(generic_interrupt_handler);
@@ -133,9 +141,12 @@ extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: InterruptStackFram
 }
 
 extern "x86-interrupt" fn device_not_available_handler(stack_frame: InterruptStackFrame) {
-    error!("EXCEPTION: DEVICE NOT AVAILABLE");
-    debug!("{:#?}", stack_frame);
-    halt_cpu();
+    // CR0.TSによる遅延切り替え：現在のスレッドのFPU状態を復元して命令を再実行
+    if let Err(e) = crate::task::fpu::handle_device_not_available() {
+        error!("EXCEPTION: DEVICE NOT AVAILABLE ({:?})", e);
+        debug!("{:#?}", stack_frame);
+        halt_cpu();
+    }
 }
 
 extern "x86-interrupt" fn double_fault_handler(
@@ -190,8 +201,32 @@ extern "x86-interrupt" fn page_fault_handler(
     error_code: x86_64::structures::idt::PageFaultErrorCode,
 ) {
     use x86_64::registers::control::Cr2;
+    use x86_64::structures::idt::PageFaultErrorCode;
+
+    let addr = Cr2::read_raw();
+    let write = error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE);
+    let exec = error_code.contains(PageFaultErrorCode::INSTRUCTION_FETCH);
+
+    // プロセスの仮想メモリ領域内ならページを割り当てて再実行
+    let result = crate::mem::user::handle_page_fault(addr, write, exec);
+    if result.is_ok() {
+        return;
+    }
+
+    let from_user = stack_frame.code_segment.rpl() == PrivilegeLevel::Ring3;
+    if from_user {
+        warn!(
+            "Invalid user memory access at {:#x} (rip={:#x}, error={:?}, cause={:?})",
+            addr,
+            stack_frame.instruction_pointer.as_u64(),
+            error_code,
+            result
+        );
+        crate::task::kill_current_process_from_isr();
+    }
+
     error!("EXCEPTION: PAGE FAULT");
-    error!("Accessed address: {:?}", Cr2::read());
+    error!("Accessed address: {:#x}", addr);
     error!("Error code: {:?}", error_code);
     debug!("{:#?}", stack_frame);
     halt_cpu();
@@ -7,6 +7,7 @@ pub mod pic;
 pub mod timer;
 pub mod spinlock;
 pub mod syscall;
+pub mod trap;
 
 pub use idt::init as init_idt;
 pub use pic::{init as init_pic, send_eoi};
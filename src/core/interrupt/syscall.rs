@@ -1,11 +1,22 @@
 use core::arch::asm;
-use crate::mem::gdt;
+use crate::mem::address_space::USER_SPACE_END;
+use crate::mem::{gdt, percpu};
+use crate::task::Context;
+
+use super::trap::SYSCALL_VECTOR;
 
 // MSR addresses
+const IA32_EFER: u32 = 0xC000_0080;
 const IA32_STAR: u32 = 0xC000_0081;
 const IA32_LSTAR: u32 = 0xC000_0082;
 const IA32_FMASK: u32 = 0xC000_0084;
 
+/// EFER.SCE (enables SYSCALL/SYSRET)
+const EFER_SCE: u64 = 1 << 0;
+
+/// RFLAGS bits cleared on SYSCALL: TF, IF, DF, AC
+const SYSCALL_FLAG_MASK: u64 = (1 << 8) | (1 << 9) | (1 << 10) | (1 << 18);
+
 /// Write MSR with given index and value (rdx:rax)
 unsafe fn wrmsr(msr: u32, value: u64) {
     let low = value as u32;
@@ -19,13 +30,33 @@ unsafe fn wrmsr(msr: u32, value: u64) {
     );
 }
 
-/// Initialize syscall MSRs (STAR/LSTAR/FMASK)
+/// Read MSR with given index (rdx:rax)
+unsafe fn rdmsr(msr: u32) -> u64 {
+    let low: u32;
+    let high: u32;
+    asm!(
+        "rdmsr",
+        in("ecx") msr,
+        out("eax") low,
+        out("edx") high,
+        options(nostack, preserves_flags),
+    );
+    ((high as u64) << 32) | low as u64
+}
+
+/// Initialize syscall MSRs (EFER.SCE/STAR/LSTAR/FMASK)
+///
+/// Requires the GDT layout kernel code, kernel data, user data, user code
+/// (see `gdt::init`) and the per-CPU area in IA32_KERNEL_GS_BASE.
 pub fn init_syscall() {
     unsafe {
+        wrmsr(IA32_EFER, rdmsr(IA32_EFER) | EFER_SCE);
+
+        // SYSCALL: CS=STAR[47:32], SS=STAR[47:32]+8
+        // SYSRET:  SS=STAR[63:48]+8, CS=STAR[63:48]+16 (RPL forced to 3)
         let kernel_cs = gdt::kernel_code_selector() as u64;
-        let user_cs = gdt::user_code_selector() as u64;
-        // STAR: [63:48]=user_cs, [47:32]=kernel_cs
-        let star = (user_cs << 48) | (kernel_cs << 32);
+        let sysret_base = (gdt::user_data_selector() as u64 & !0x7) - 8;
+        let star = (sysret_base << 48) | (kernel_cs << 32);
         wrmsr(IA32_STAR, star);
 
         // LSTAR: address of syscall entry point
@@ -35,20 +66,99 @@ pub fn init_syscall() {
         let addr = syscall_entry as *const () as usize as u64;
         wrmsr(IA32_LSTAR, addr);
 
-        // FMASK: mask of flags to clear on syscall (clear interrupt flag)
-        let fmask: u64 = (1 << 9); // clear IF
-        wrmsr(IA32_FMASK, fmask);
+        // FMASK: run the handler with interrupts disabled, like the int 0x80 gate
+        wrmsr(IA32_FMASK, SYSCALL_FLAG_MASK);
     }
 }
 
-// Minimal syscall entry stub. This label is referenced by IA32_LSTAR.
-// Implementation: swapgs -> call existing int80 handler via interrupt instruction
-// (This is a pragmatic bridge until a full, register-preserving syscall path is implemented.)
-core::arch::global_asm!(r#"
+// SYSCALL entry. This label is referenced by IA32_LSTAR.
+//
+// Switches to the current thread's kernel stack through the per-CPU area and
+// builds the same trap frame as `trap_common`, with the user RIP/RFLAGS taken
+// from RCX/R11. GS is swapped back before leaving the stub, so kernel code
+// always runs with the user's GS base. If the handler left a frame that SYSRET
+// cannot restore (another thread, a rewritten signal context), the frame is
+// returned through `trap_restore` with IRETQ instead.
+core::arch::global_asm!(
+    r#"
     .global syscall_entry
 syscall_entry:
     swapgs
-    int 0x80
+    mov qword ptr gs:[{user_rsp}], rsp
+    mov rsp, qword ptr gs:[{kernel_rsp}]
+    push 0
+    push qword ptr gs:[{user_rsp}]
     swapgs
+    push r11
+    push 0
+    push rcx
+    push 0
+    push {vector}
+    push rax
+    push rbx
+    push rcx
+    push rdx
+    push rsi
+    push rdi
+    push rbp
+    push r8
+    push r9
+    push r10
+    push r11
+    push r12
+    push r13
+    push r14
+    push r15
+    cld
+    mov rdi, rsp
+    call {dispatch}
+    test al, al
+    jz trap_restore
+
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop r11
+    pop r10
+    pop r9
+    pop r8
+    pop rbp
+    pop rdi
+    pop rsi
+    pop rdx
+    pop rcx
+    pop rbx
+    pop rax
+    add rsp, 16
+    mov rsp, [rsp + 24]
     sysretq
-"#);
+"#,
+    user_rsp = const percpu::USER_RSP_OFFSET,
+    kernel_rsp = const percpu::KERNEL_RSP_OFFSET,
+    vector = const SYSCALL_VECTOR,
+    dispatch = sym syscall_dispatch,
+);
+
+/// Called from `syscall_entry` with the trap frame.
+///
+/// Returns true if the frame can be resumed with SYSRET.
+extern "C" fn syscall_dispatch(frame: &mut Context) -> bool {
+    frame.cs = gdt::user_code_selector() as u64;
+    frame.ss = gdt::user_data_selector() as u64;
+
+    crate::syscall::dispatch_frame(frame);
+    crate::task::signal::before_user_return(frame);
+
+    can_sysret(frame)
+}
+
+/// SYSRET restores RIP from RCX and RFLAGS from R11, so the frame must still
+/// hold them there. A non-canonical RIP would fault in ring 0 after SYSRET.
+fn can_sysret(frame: &Context) -> bool {
+    frame.cs == gdt::user_code_selector() as u64
+        && frame.ss == gdt::user_data_selector() as u64
+        && frame.rcx == frame.rip
+        && frame.r11 == frame.rflags
+        && frame.rip < USER_SPACE_END
+}
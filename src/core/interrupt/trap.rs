@@ -0,0 +1,137 @@
+//! 割り込み入口（トラップフレーム）
+//!
+//! タイマー割り込み、システムコール（int 0x80）、スレッド切り替え要求（int 0x81）は
+//! アセンブリの入口で全汎用レジスタをスタックに積み、`task::Context`として
+//! `trap_dispatch`に渡す。ハンドラ内でフレームを書き換えると、
+//! 入口に戻った時点で書き換え後のレジスタが`iretq`で復元される。
+//! SYSCALL命令の入口（`interrupt::syscall`）も同じ形のフレームを作り、
+//! SYSRETで戻れないフレームは`trap_restore`から復元する。
+
+use core::arch::global_asm;
+
+use crate::task::Context;
+
+/// タイマー割り込みのベクタ番号
+pub const TIMER_VECTOR: u8 = 32;
+/// システムコールのベクタ番号
+pub const SYSCALL_VECTOR: u8 = 0x80;
+/// スレッド切り替え要求のベクタ番号（カーネル内からのみ使用）
+pub const YIELD_VECTOR: u8 = 0x81;
+
+global_asm!(
+    r#"
+    .global trap_timer
+trap_timer:
+    push 0
+    push {timer}
+    jmp trap_common
+
+    .global trap_syscall
+trap_syscall:
+    push 0
+    push {syscall}
+    jmp trap_common
+
+    .global trap_yield
+trap_yield:
+    push 0
+    push {yield_}
+    jmp trap_common
+
+trap_common:
+    push rax
+    push rbx
+    push rcx
+    push rdx
+    push rsi
+    push rdi
+    push rbp
+    push r8
+    push r9
+    push r10
+    push r11
+    push r12
+    push r13
+    push r14
+    push r15
+    cld
+    mov rdi, rsp
+    call {dispatch}
+
+    .global trap_restore
+trap_restore:
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop r11
+    pop r10
+    pop r9
+    pop r8
+    pop rbp
+    pop rdi
+    pop rsi
+    pop rdx
+    pop rcx
+    pop rbx
+    pop rax
+    add rsp, 16
+    iretq
+"#,
+    timer = const TIMER_VECTOR,
+    syscall = const SYSCALL_VECTOR,
+    yield_ = const YIELD_VECTOR,
+    dispatch = sym trap_dispatch,
+);
+
+unsafe extern "C" {
+    fn trap_timer();
+    fn trap_syscall();
+    fn trap_yield();
+}
+
+/// タイマー割り込みの入口アドレス
+pub fn timer_entry() -> u64 {
+    trap_timer as *const () as u64
+}
+
+/// システムコールの入口アドレス
+pub fn syscall_entry() -> u64 {
+    trap_syscall as *const () as u64
+}
+
+/// スレッド切り替え要求の入口アドレス
+pub fn yield_entry() -> u64 {
+    trap_yield as *const () as u64
+}
+
+/// 割り込み入口から呼ばれるディスパッチャ
+extern "C" fn trap_dispatch(frame: &mut Context) {
+    match frame.vector as u8 {
+        TIMER_VECTOR => super::timer::handle_timer(frame),
+        SYSCALL_VECTOR => crate::syscall::dispatch_frame(frame),
+        YIELD_VECTOR => crate::task::reschedule(frame),
+        _ => crate::warn!("trap_dispatch: unexpected vector {}", frame.vector),
+    }
+
+    // Ring3へ戻る前に、終了・停止したスレッドの切り替えとシグナルの配送を行う
+    if frame.is_user() {
+        crate::task::signal::before_user_return(frame);
+    }
+}
+
+/// コンテキストを復元して実行を再開する
+///
+/// `ctx`を現在のスタックに置いたフレームとして`trap_restore`へ飛ぶ
+pub fn restore(ctx: &Context) -> ! {
+    let frame = *ctx;
+    unsafe {
+        core::arch::asm!(
+            "cli",
+            "mov rsp, {frame}",
+            "jmp trap_restore",
+            frame = in(reg) &frame as *const Context,
+            options(noreturn)
+        );
+    }
+}
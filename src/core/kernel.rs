@@ -36,12 +36,14 @@ pub extern "C" fn kernel_entry(boot_info: &'static BootInfo) -> ! {
 
 /// カーネルメイン処理
 fn kernel_main(boot_info: &'static BootInfo, memory_map: &'static [MemoryRegion]) -> Result<()> {
-    let kernel_process = task::Process::new("swiftcore", task::PrivilegeLevel::Core, None, 0);
+    let kernel_process = task::Process::new("swiftcore", task::PrivilegeLevel::Core, None, task::IDLE_PRIORITY);
     let kernel_pid = kernel_process.id();
 
     if task::add_process(kernel_process).is_none() {
         return Err(KernelError::Process(ProcessError::MaxProcessesReached));
     }
+    // カーネルプロセスを親を失ったプロセスの引き取り先にする
+    task::set_init_process(kernel_pid);
 
     let stack_addr = unsafe { core::ptr::addr_of!(KERNEL_THREAD_STACK.0) as *const u8 as u64 };
     let kernel_thread = task::Thread::new(
@@ -56,10 +58,9 @@ fn kernel_main(boot_info: &'static BootInfo, memory_map: &'static [MemoryRegion]
         return Err(KernelError::Process(ProcessError::MaxProcessesReached));
     }
 
-    if let Err(e) = task::spawn_service("hello", "init.hello") {
-        info!("failed to spawn initfs hello: {:?}", e);
-    } else {
-        info!("spawned initfs/hello");
+    // サービスは/etc/servicesに従ってサービスマネージャが起動する
+    if let Err(e) = crate::service::start() {
+        info!("failed to start service manager: {:?}", e);
     }
 
     info!("Process list:");
@@ -72,22 +73,7 @@ fn kernel_main(boot_info: &'static BootInfo, memory_map: &'static [MemoryRegion]
         info!("  thread: {} id={:?} pid={} state={:?}", t.name(), t.id().as_u64(), t.process_id().as_u64(), t.state());
     });
 
-    // Start the user service `init.hello` first: mark the kernel `core` thread as Sleeping
-    // so the scheduler picks the service as the initial thread and we enter it via iret.
-    let mut core_id = None;
-    task::for_each_thread(|t| {
-        if t.name() == "core" {
-            core_id = Some(t.id());
-        }
-    });
-
-    if let Some(id) = core_id {
-        crate::task::with_thread_mut(id, |thr| {
-            use crate::task::ThreadState;
-            thr.set_state(ThreadState::Sleeping);
-        });
-    }
-
+    // coreスレッドはアイドル優先度のため、実行可能なサービスがあればそちらが先に動く
     task::start_scheduling();
 
     #[allow(unreachable_code)]
@@ -27,6 +27,9 @@ pub mod task;
 /// システムコール
 pub mod syscall;
 
+/// サービス管理
+pub mod service;
+
 /// デバイスドライバ
 pub mod driver;
 
@@ -39,6 +42,9 @@ pub mod util;
 pub use error::{KernelError, Result};
 pub use kernel::kernel_entry;
 
+#[global_allocator]
+static ALLOCATOR: mem::heap::KernelHeapAllocator = mem::heap::KernelHeapAllocator;
+
 #[repr(C)]
 pub struct BootInfo {
     /// 物理メモリオフセット
@@ -0,0 +1,666 @@
+//! プロセスごとのアドレス空間
+//!
+//! Service/Userプロセスは専用のPML4を持つ。作成時にカーネルのPML4エントリを
+//! そのままコピーするため、カーネル側のマッピングはすべてのアドレス空間で共有される。
+//!
+//! ユーザーページをマップする際、経路上のページテーブルがカーネルと共有されている場合は
+//! そのテーブルを複製（大ページは分割）してからエントリを書き換える。
+//! 複製したテーブルと、アドレス空間が所有するフレームを指すリーフエントリには
+//! `OWNED`ビット（OS用予約ビット）を立てて区別する。
+//!
+//! ユーザーがアクセスできる範囲は`VmRegion`の一覧で管理し、
+//! 領域内の未マップページはページフォルト時に割り当てる（デマンドページング）。
+
+use alloc::vec::Vec;
+use x86_64::structures::paging::{
+    page_table::PageTableEntry, Page, PageTable, PageTableFlags, PhysFrame, Size4KiB,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::error::{KernelError, MemoryError, Result};
+use crate::mem::{frame, paging};
+use crate::mem::vma::{RegionKind, VmRegion};
+
+/// このアドレス空間が所有するテーブル/フレームであることを示すビット
+pub const OWNED: PageTableFlags = PageTableFlags::BIT_9;
+
+/// ユーザー空間の上限（下位カノニカル領域の終端）
+pub const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+const PAGE_SIZE: u64 = 4096;
+const ENTRY_COUNT: usize = 512;
+
+/// 中間テーブルのエントリに付与するフラグ
+fn table_flags() -> PageTableFlags {
+    PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE | OWNED
+}
+
+/// 物理フレーム上のページテーブルへの参照を取得
+///
+/// # Safety
+/// `frame`はページテーブルとして使用されているフレームでなければならない
+unsafe fn table_at(frame: PhysFrame) -> &'static mut PageTable {
+    let virt = frame.start_address().as_u64() + paging::physical_memory_offset();
+    &mut *(virt as *mut PageTable)
+}
+
+/// ゼロ初期化済みのフレームを割り当て
+fn allocate_zeroed_frame() -> Result<PhysFrame> {
+    let frame = frame::allocate_frame()?;
+    unsafe {
+        let virt = frame.start_address().as_u64() + paging::physical_memory_offset();
+        core::ptr::write_bytes(virt as *mut u8, 0, PAGE_SIZE as usize);
+    }
+    Ok(frame)
+}
+
+/// プロセスのアドレス空間
+pub struct AddressSpace {
+    /// レベル4ページテーブルのフレーム
+    pml4: PhysFrame,
+    /// 次に確保するユーザースタックの上端
+    next_stack_top: u64,
+    /// 仮想メモリ領域の一覧（開始アドレス順）
+    regions: Vec<VmRegion>,
+    /// プログラムブレークの初期値（ヒープの先頭）
+    brk_base: u64,
+    /// 現在のプログラムブレーク
+    brk: u64,
+}
+
+impl AddressSpace {
+    /// カーネルのマッピングを共有する新しいアドレス空間を作成
+    pub fn new() -> Result<Self> {
+        let pml4 = allocate_zeroed_frame()?;
+        let kernel = unsafe { table_at(paging::kernel_pml4_frame()) };
+        let table = unsafe { table_at(pml4) };
+        for (dst, src) in table.iter_mut().zip(kernel.iter()) {
+            *dst = src.clone();
+        }
+        Ok(Self {
+            pml4,
+            next_stack_top: super::user::USER_STACK_TOP,
+            regions: Vec::new(),
+            brk_base: 0,
+            brk: 0,
+        })
+    }
+
+    /// CR3に設定する値（PML4の物理アドレス）
+    pub fn cr3(&self) -> u64 {
+        self.pml4.start_address().as_u64()
+    }
+
+    /// 次に確保するユーザースタックの上端を取得
+    pub fn next_stack_top(&self) -> u64 {
+        self.next_stack_top
+    }
+
+    /// 次に確保するユーザースタックの上端を設定
+    pub fn set_next_stack_top(&mut self, top: u64) {
+        self.next_stack_top = top;
+    }
+
+    /// プログラムブレークの初期値を設定（ヒープは空の状態から始まる）
+    pub fn init_program_break(&mut self, base: u64) {
+        let base = align_up(base);
+        self.brk_base = base;
+        self.brk = base;
+    }
+
+    /// 現在のプログラムブレークを取得
+    pub fn program_break(&self) -> u64 {
+        self.brk
+    }
+
+    /// プログラムブレークを移動
+    ///
+    /// ヒープ領域を伸縮し、縮めた範囲のフレームは解放する。成功時は新しいブレークを返す
+    pub fn set_program_break(&mut self, new: u64) -> Result<u64> {
+        if self.brk_base == 0 || new < self.brk_base || new > USER_SPACE_END {
+            return Err(KernelError::Memory(MemoryError::InvalidAddress));
+        }
+
+        let old_end = align_up(self.brk);
+        let new_end = align_up(new);
+        if new_end > old_end {
+            if !self.is_range_free(old_end, new_end - old_end) {
+                return Err(KernelError::Memory(MemoryError::AlreadyMapped));
+            }
+            let flags = PageTableFlags::PRESENT
+                | PageTableFlags::WRITABLE
+                | PageTableFlags::USER_ACCESSIBLE
+                | PageTableFlags::NO_EXECUTE;
+            self.add_region(old_end, new_end - old_end, flags, RegionKind::Heap)?;
+            self.merge_regions();
+        } else if new_end < old_end {
+            self.unmap_range(new_end, old_end - new_end)?;
+        }
+
+        self.brk = new;
+        Ok(new)
+    }
+
+    /// 仮想メモリ領域の一覧を取得
+    pub fn regions(&self) -> &[VmRegion] {
+        &self.regions
+    }
+
+    /// アドレスを含む仮想メモリ領域を取得
+    pub fn find_region(&self, addr: u64) -> Option<&VmRegion> {
+        self.regions.iter().find(|r| r.contains(addr))
+    }
+
+    /// 仮想メモリ領域を登録
+    ///
+    /// 範囲はページ境界へ広げる。既存の領域と端のページだけが重なる場合
+    /// （隣接セグメントがページを共有する場合）は、そのページを独立した領域に分けて
+    /// 属性を両者の和集合にし、残りを新しい領域として登録する。
+    /// 和集合が書き込み可能かつ実行可能になる場合は`PermissionDenied`（W^X）。
+    /// それ以外の重なりは`AlreadyMapped`。カーネルが恒等マッピングで使う範囲と重なる場合は`InvalidAddress`
+    pub fn add_region(&mut self, start: u64, size: u64, flags: PageTableFlags, kind: RegionKind) -> Result<()> {
+        if size == 0 {
+            return Ok(());
+        }
+        let end = start
+            .checked_add(size)
+            .ok_or(KernelError::Memory(MemoryError::InvalidAddress))?;
+        if end > USER_SPACE_END {
+            return Err(KernelError::Memory(MemoryError::InvalidAddress));
+        }
+        let start = align_down(start);
+        let end = align_up(end);
+        if paging::overlaps_identity_range(start, end) {
+            return Err(KernelError::Memory(MemoryError::InvalidAddress));
+        }
+
+        let first = start;
+        let last = end - PAGE_SIZE;
+        let share_first = self.find_region(first).is_some();
+        let share_last = self.find_region(last).is_some();
+        let inner_start = if share_first { first + PAGE_SIZE } else { start };
+        let inner_end = if share_last { last } else { end };
+
+        if inner_start < inner_end && self.regions.iter().any(|r| r.overlaps(inner_start, inner_end)) {
+            return Err(KernelError::Memory(MemoryError::AlreadyMapped));
+        }
+        let shared = [(share_first, first), (share_last, last)];
+        let violates_wx = shared.iter().filter(|&&(share, _)| share).any(|&(_, addr)| {
+            self.find_region(addr)
+                .is_some_and(|r| is_writable_executable(union_flags(r.flags, flags)))
+        });
+        if violates_wx {
+            return Err(KernelError::Memory(MemoryError::PermissionDenied));
+        }
+
+        if share_first {
+            self.share_page(first, flags);
+        }
+        if share_last && last != first {
+            self.share_page(last, flags);
+        }
+        if inner_start < inner_end {
+            let index = self.regions.partition_point(|r| r.start < inner_start);
+            self.regions
+                .insert(index, VmRegion::new(inner_start, inner_end, flags, kind));
+        }
+        Ok(())
+    }
+
+    /// 既存領域内のページを1ページの領域に分け、属性に`flags`を合成する
+    fn share_page(&mut self, addr: u64, flags: PageTableFlags) {
+        self.split_region_at(addr);
+        self.split_region_at(addr + PAGE_SIZE);
+        let merged = match self.regions.iter_mut().find(|r| r.start == addr) {
+            Some(region) => {
+                region.flags = union_flags(region.flags, flags);
+                region.flags
+            }
+            None => return,
+        };
+
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(addr));
+        if let Some(entry) = self.owned_leaf_mut(page) {
+            entry.set_flags(merged | PageTableFlags::PRESENT | OWNED);
+            flush(page);
+        }
+    }
+
+    /// ページフォルトを処理
+    ///
+    /// `addr`が領域内で、アクセス種別が領域の属性で許可されていれば
+    /// ゼロ初期化したページを割り当ててマップする
+    pub fn handle_fault(&mut self, addr: u64, write: bool, exec: bool) -> Result<()> {
+        let region = *self
+            .find_region(addr)
+            .ok_or(KernelError::Memory(MemoryError::NotMapped))?;
+        if !region.is_accessible() || (write && !region.is_writable()) || (exec && !region.is_executable()) {
+            return Err(KernelError::Memory(MemoryError::PermissionDenied));
+        }
+
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(addr));
+        if let Some((_, flags)) = self.translate_owned(page.start_address()) {
+            // 既にマップ済み: ページの属性が許すなら古いTLBによるフォルトなので再実行させる
+            let allowed = flags.contains(OWNED | PageTableFlags::USER_ACCESSIBLE)
+                && (!write || flags.contains(PageTableFlags::WRITABLE))
+                && (!exec || !flags.contains(PageTableFlags::NO_EXECUTE));
+            if allowed {
+                flush(page);
+                return Ok(());
+            }
+            return Err(KernelError::Memory(MemoryError::PermissionDenied));
+        }
+        self.populate(page, region.flags)
+    }
+
+    /// ゼロ初期化したフレームを割り当ててページをマップ
+    fn populate(&mut self, page: Page<Size4KiB>, flags: PageTableFlags) -> Result<()> {
+        let frame = allocate_zeroed_frame()?;
+        if let Err(e) = self.map_page(page, frame, flags) {
+            unsafe { frame::deallocate_frame(frame) };
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// ページをマップ
+    ///
+    /// 既にこのアドレス空間が所有するページがマップされている場合は`AlreadyMapped`
+    pub fn map_page(&mut self, page: Page<Size4KiB>, frame: PhysFrame, flags: PageTableFlags) -> Result<()> {
+        let entry = self.leaf_entry_mut(page)?;
+        if entry.flags().contains(PageTableFlags::PRESENT | OWNED) {
+            return Err(KernelError::Memory(MemoryError::AlreadyMapped));
+        }
+        entry.set_addr(frame.start_address(), flags | PageTableFlags::PRESENT | OWNED);
+        flush(page);
+        Ok(())
+    }
+
+    /// 仮想アドレスを物理アドレスとリーフのフラグに変換
+    pub fn translate(&self, addr: VirtAddr) -> Option<(PhysAddr, PageTableFlags)> {
+        let mut table = unsafe { table_at(self.pml4) };
+        let indices = [
+            addr.p4_index(),
+            addr.p3_index(),
+            addr.p2_index(),
+            addr.p1_index(),
+        ];
+        for (level, index) in indices.iter().enumerate() {
+            let entry = &table[*index];
+            let flags = entry.flags();
+            if !flags.contains(PageTableFlags::PRESENT) {
+                return None;
+            }
+            let is_leaf = level == 3 || (level > 0 && flags.contains(PageTableFlags::HUGE_PAGE));
+            if is_leaf {
+                let page_size = 1u64 << (12 + 9 * (3 - level));
+                let base = entry.addr().as_u64() & !(page_size - 1);
+                return Some((PhysAddr::new(base + (addr.as_u64() & (page_size - 1))), flags));
+            }
+            table = unsafe { table_at(PhysFrame::containing_address(entry.addr())) };
+        }
+        None
+    }
+
+    /// このアドレス空間が所有するページに限って仮想アドレスを変換
+    ///
+    /// カーネルから引き継いだ恒等マッピングなどはユーザー領域としては未マップ扱い
+    pub fn translate_owned(&self, addr: VirtAddr) -> Option<(PhysAddr, PageTableFlags)> {
+        self.translate(addr).filter(|(_, flags)| flags.contains(OWNED))
+    }
+
+    /// 範囲`[start, start + len)`がどの領域ともカーネルの恒等マッピングとも重ならないか
+    pub fn is_range_free(&self, start: u64, len: u64) -> bool {
+        match start.checked_add(len) {
+            Some(end) if end <= USER_SPACE_END => {
+                !self.regions.iter().any(|r| r.overlaps(start, end)) && !paging::overlaps_identity_range(start, end)
+            }
+            _ => false,
+        }
+    }
+
+    /// `lower`以上で長さ`len`の空き範囲を探す（最初に見つかった範囲の先頭を返す）
+    pub fn find_free_range(&self, lower: u64, len: u64) -> Option<u64> {
+        let mut start = align_up(lower);
+        for region in &self.regions {
+            if region.end <= start {
+                continue;
+            }
+            if start.checked_add(len)? <= region.start {
+                return Some(start);
+            }
+            start = region.end;
+        }
+        (start.checked_add(len)? <= USER_SPACE_END).then_some(start)
+    }
+
+    /// 範囲の領域とマッピングを取り除き、所有していたフレームを解放
+    ///
+    /// `start`と`len`はページ境界に揃っていること
+    pub fn unmap_range(&mut self, start: u64, len: u64) -> Result<()> {
+        let end = checked_user_end(start, len)?;
+        self.split_region_at(start);
+        self.split_region_at(end);
+        self.regions.retain(|r| !(start <= r.start && r.end <= end));
+
+        for addr in (start..end).step_by(PAGE_SIZE as usize) {
+            let page = Page::<Size4KiB>::containing_address(VirtAddr::new(addr));
+            if let Some(entry) = self.owned_leaf_mut(page) {
+                let frame = PhysFrame::containing_address(entry.addr());
+                entry.set_unused();
+                flush(page);
+                unsafe { frame::deallocate_frame(frame) };
+            }
+        }
+        Ok(())
+    }
+
+    /// 範囲の属性を変更
+    ///
+    /// 範囲全体が領域で覆われていない場合は`NotMapped`。
+    /// `start`と`len`はページ境界に揃っていること
+    pub fn protect_range(&mut self, start: u64, len: u64, flags: PageTableFlags) -> Result<()> {
+        let end = checked_user_end(start, len)?;
+
+        // 範囲に隙間がないことを確認
+        let mut cursor = start;
+        for region in self.regions.iter().filter(|r| r.overlaps(start, end)) {
+            if region.start > cursor {
+                break;
+            }
+            cursor = region.end;
+        }
+        if cursor < end {
+            return Err(KernelError::Memory(MemoryError::NotMapped));
+        }
+
+        self.split_region_at(start);
+        self.split_region_at(end);
+        for region in self.regions.iter_mut().filter(|r| start <= r.start && r.end <= end) {
+            region.flags = flags;
+        }
+
+        for addr in (start..end).step_by(PAGE_SIZE as usize) {
+            let page = Page::<Size4KiB>::containing_address(VirtAddr::new(addr));
+            if let Some(entry) = self.owned_leaf_mut(page) {
+                entry.set_flags(flags | PageTableFlags::PRESENT | OWNED);
+                flush(page);
+            }
+        }
+
+        self.merge_regions();
+        Ok(())
+    }
+
+    /// `addr`を内部に含む領域を`addr`で2つに分割
+    fn split_region_at(&mut self, addr: u64) {
+        if let Some(index) = self.regions.iter().position(|r| r.start < addr && addr < r.end) {
+            let region = self.regions[index];
+            self.regions[index].end = addr;
+            self.regions
+                .insert(index + 1, VmRegion::new(addr, region.end, region.flags, region.kind));
+        }
+    }
+
+    /// 隣接していて属性と用途が同じ領域を結合
+    fn merge_regions(&mut self) {
+        let mut i = 0;
+        while i + 1 < self.regions.len() {
+            let (cur, next) = (self.regions[i], self.regions[i + 1]);
+            if cur.end == next.start && cur.flags == next.flags && cur.kind == next.kind {
+                self.regions[i].end = next.end;
+                self.regions.remove(i + 1);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// このアドレス空間が所有するリーフエントリを取得（テーブルは作成しない）
+    fn owned_leaf_mut(&mut self, page: Page<Size4KiB>) -> Option<&'static mut PageTableEntry> {
+        let addr = page.start_address();
+        let mut table = unsafe { table_at(self.pml4) };
+        for index in [addr.p4_index(), addr.p3_index(), addr.p2_index()] {
+            let flags = table[index].flags();
+            if !flags.contains(PageTableFlags::PRESENT | OWNED) || flags.contains(PageTableFlags::HUGE_PAGE) {
+                return None;
+            }
+            table = unsafe { table_at(PhysFrame::containing_address(table[index].addr())) };
+        }
+        let entry = &mut table[addr.p1_index()];
+        entry
+            .flags()
+            .contains(PageTableFlags::PRESENT | OWNED)
+            .then_some(entry)
+    }
+
+    /// アドレス空間内の仮想アドレスへバイト列を書き込む
+    ///
+    /// 書き込み先は物理メモリのカーネル側エイリアスを経由するため、
+    /// このアドレス空間がアクティブでなくても使用できる。
+    /// 領域内の未マップページはその場で割り当てる
+    pub fn write_bytes(&mut self, vaddr: u64, data: &[u8]) -> Result<()> {
+        self.for_each_chunk(vaddr, data.len(), |dst, offset, len| unsafe {
+            core::ptr::copy_nonoverlapping(data.as_ptr().add(offset), dst, len);
+        })
+    }
+
+    /// アドレス空間内の仮想アドレスからバイト列を読み出す
+    ///
+    /// `write_bytes`と同様に物理メモリのカーネル側エイリアスを経由する
+    pub fn read_bytes(&mut self, vaddr: u64, buf: &mut [u8]) -> Result<()> {
+        let dst = buf.as_mut_ptr();
+        self.for_each_chunk(vaddr, buf.len(), |src, offset, len| unsafe {
+            core::ptr::copy_nonoverlapping(src, dst.add(offset), len);
+        })
+    }
+
+    /// 範囲がユーザーからアクセス可能な領域で隙間なく覆われているか検証
+    ///
+    /// `write`がtrueの場合は書き込み可能であることも要求する
+    pub fn check_user_range(&self, vaddr: u64, len: usize, write: bool) -> Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        let end = vaddr
+            .checked_add(len as u64)
+            .filter(|&end| end <= USER_SPACE_END)
+            .ok_or(KernelError::Memory(MemoryError::InvalidAddress))?;
+
+        let mut cursor = vaddr;
+        while cursor < end {
+            let region = self
+                .find_region(cursor)
+                .ok_or(KernelError::Memory(MemoryError::NotMapped))?;
+            if !region.is_accessible() || (write && !region.is_writable()) {
+                return Err(KernelError::Memory(MemoryError::PermissionDenied));
+            }
+            cursor = region.end;
+        }
+        Ok(())
+    }
+
+    /// アドレス空間内の仮想アドレス範囲をゼロクリア
+    pub fn zero(&mut self, vaddr: u64, len: usize) -> Result<()> {
+        self.for_each_chunk(vaddr, len, |dst, _, len| unsafe {
+            core::ptr::write_bytes(dst, 0, len);
+        })
+    }
+
+    /// アドレス空間内の仮想アドレスへu64を書き込む
+    pub fn write_u64(&mut self, vaddr: u64, value: u64) -> Result<()> {
+        self.write_bytes(vaddr, &value.to_le_bytes())
+    }
+
+    /// 範囲をページ境界で分割し、各断片のカーネル側ポインタを渡す
+    fn for_each_chunk<F>(&mut self, vaddr: u64, len: usize, mut f: F) -> Result<()>
+    where
+        F: FnMut(*mut u8, usize, usize),
+    {
+        let offset = paging::physical_memory_offset();
+        let mut done = 0usize;
+        while done < len {
+            let addr = vaddr
+                .checked_add(done as u64)
+                .ok_or(KernelError::Memory(MemoryError::InvalidAddress))?;
+            let virt = VirtAddr::try_new(addr).map_err(|_| KernelError::Memory(MemoryError::InvalidAddress))?;
+            let phys = match self.translate_owned(virt) {
+                Some((phys, _)) => phys,
+                None => {
+                    let region = *self
+                        .find_region(addr)
+                        .ok_or(KernelError::Memory(MemoryError::NotMapped))?;
+                    self.populate(Page::containing_address(virt), region.flags)?;
+                    self.translate_owned(virt)
+                        .ok_or(KernelError::Memory(MemoryError::NotMapped))?
+                        .0
+                }
+            };
+            let in_page = (PAGE_SIZE - (addr % PAGE_SIZE)) as usize;
+            let chunk = core::cmp::min(in_page, len - done);
+            f((phys.as_u64() + offset) as *mut u8, done, chunk);
+            done += chunk;
+        }
+        Ok(())
+    }
+
+    /// ページに対応するリーフエントリを取得（必要に応じて経路上のテーブルを作成・複製）
+    fn leaf_entry_mut(&mut self, page: Page<Size4KiB>) -> Result<&'static mut PageTableEntry> {
+        let addr = page.start_address();
+        if addr.as_u64() >= USER_SPACE_END {
+            return Err(KernelError::Memory(MemoryError::InvalidAddress));
+        }
+
+        let mut table = unsafe { table_at(self.pml4) };
+        for (level, index) in [addr.p4_index(), addr.p3_index(), addr.p2_index()].into_iter().enumerate() {
+            let entry = &mut table[index];
+            let next = private_table(entry, level)?;
+            table = unsafe { table_at(next) };
+        }
+        Ok(&mut table[addr.p1_index()])
+    }
+
+    /// このアドレス空間をアクティブにする
+    pub fn activate(&self) {
+        paging::switch_address_space(Some(self.cr3()));
+    }
+}
+
+impl Drop for AddressSpace {
+    /// 所有するフレームとページテーブルをすべて解放する
+    ///
+    /// アクティブなまま破棄された場合はカーネルのアドレス空間へ切り替えてから解放する
+    fn drop(&mut self) {
+        if x86_64::registers::control::Cr3::read().0 == self.pml4 {
+            paging::switch_address_space(None);
+        }
+        unsafe {
+            free_owned_entries(self.pml4, 0);
+            frame::deallocate_frame(self.pml4);
+        }
+    }
+}
+
+/// テーブルから辿れる所有テーブルとフレームを解放
+///
+/// `level`はPML4を0とした深さ。カーネルと共有するエントリは`OWNED`を持たないため辿らない
+///
+/// # Safety
+/// `table`は破棄するアドレス空間のページテーブルでなければならない
+unsafe fn free_owned_entries(table: PhysFrame, level: usize) {
+    for entry in table_at(table).iter_mut() {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT | OWNED) {
+            continue;
+        }
+        let frame = PhysFrame::containing_address(entry.addr());
+        if level < 3 && !flags.contains(PageTableFlags::HUGE_PAGE) {
+            free_owned_entries(frame, level + 1);
+        }
+        entry.set_unused();
+        frame::deallocate_frame(frame);
+    }
+}
+
+/// エントリが指す下位テーブルをこのアドレス空間専用にして返す
+///
+/// `level`はPML4を0とした深さ。未割り当てなら新規作成し、共有テーブルなら複製、
+/// 大ページなら分割する。
+fn private_table(entry: &mut PageTableEntry, level: usize) -> Result<PhysFrame> {
+    let flags = entry.flags();
+
+    if !flags.contains(PageTableFlags::PRESENT) {
+        let frame = allocate_zeroed_frame()?;
+        entry.set_addr(frame.start_address(), table_flags());
+        return Ok(frame);
+    }
+
+    if flags.contains(OWNED) {
+        return Ok(PhysFrame::containing_address(entry.addr()));
+    }
+
+    let frame = allocate_zeroed_frame()?;
+    let new_table = unsafe { table_at(frame) };
+
+    if level > 0 && flags.contains(PageTableFlags::HUGE_PAGE) {
+        // 大ページを1段小さいページに分割
+        let child_size = 1u64 << (12 + 9 * (2 - level));
+        let base = entry.addr().as_u64() & !(child_size * ENTRY_COUNT as u64 - 1);
+        let child_flags = if level == 2 {
+            flags - PageTableFlags::HUGE_PAGE
+        } else {
+            flags
+        };
+        for (i, child) in new_table.iter_mut().enumerate() {
+            child.set_addr(PhysAddr::new(base + i as u64 * child_size), child_flags);
+        }
+    } else {
+        let shared = unsafe { table_at(PhysFrame::containing_address(entry.addr())) };
+        for (dst, src) in new_table.iter_mut().zip(shared.iter()) {
+            *dst = src.clone();
+        }
+    }
+
+    entry.set_addr(frame.start_address(), table_flags());
+    Ok(frame)
+}
+
+/// 2つの属性の和集合（NO_EXECUTEは両方に付いている場合のみ残す）
+fn union_flags(a: PageTableFlags, b: PageTableFlags) -> PageTableFlags {
+    let no_execute = a.contains(PageTableFlags::NO_EXECUTE) && b.contains(PageTableFlags::NO_EXECUTE);
+    let mut flags = (a | b) - PageTableFlags::NO_EXECUTE;
+    if no_execute {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+    flags
+}
+
+/// 書き込み可能かつ実行可能な属性か
+fn is_writable_executable(flags: PageTableFlags) -> bool {
+    flags.contains(PageTableFlags::WRITABLE) && !flags.contains(PageTableFlags::NO_EXECUTE)
+}
+
+/// ユーザー範囲の終端を計算（ページ境界とユーザー空間の上限を検証）
+fn checked_user_end(start: u64, len: u64) -> Result<u64> {
+    let end = start
+        .checked_add(len)
+        .filter(|&end| end <= USER_SPACE_END)
+        .ok_or(KernelError::Memory(MemoryError::InvalidAddress))?;
+    if !start.is_multiple_of(PAGE_SIZE) || !end.is_multiple_of(PAGE_SIZE) {
+        return Err(KernelError::Memory(MemoryError::AlignmentError));
+    }
+    Ok(end)
+}
+
+fn align_down(addr: u64) -> u64 {
+    addr & !(PAGE_SIZE - 1)
+}
+
+fn align_up(addr: u64) -> u64 {
+    (addr + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+/// TLBから該当ページを破棄（別のアドレス空間を操作中でも無害）
+fn flush(page: Page<Size4KiB>) {
+    x86_64::instructions::tlb::flush(page.start_address());
+}
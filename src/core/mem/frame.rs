@@ -1,32 +1,131 @@
 //! 物理フレームアロケータ
 //!
 //! 4KBページ単位で物理メモリを管理
+//!
+//! メモリマップ上の使用可能領域を1フレーム1ビットのビットマップで管理する。
+//! ビットマップ自体は最初に見つかった十分な大きさの使用可能領域に配置し、
+//! その領域のフレームは使用済みとしてマークする。
 
 use crate::{MemoryRegion, MemoryType, error::{KernelError, MemoryError, Result}};
-use spin::Mutex;
+use crate::interrupt::spinlock::SpinLock;
 use x86_64::{
-    structures::paging::{FrameAllocator, PhysFrame, Size4KiB},
+    structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB},
     PhysAddr,
 };
 
+/// フレームサイズ（バイト）
+pub const FRAME_SIZE: u64 = 4096;
+
+/// ビットマップ1ワードあたりのフレーム数
+const BITS_PER_WORD: usize = 64;
+
 /// グローバルフレームアロケータ
-pub static FRAME_ALLOCATOR: Mutex<Option<BitmapFrameAllocator>> = Mutex::new(None);
+///
+/// ヒープ経由で割込みハンドラからも呼ばれるため、割込み安全なロックを使う
+pub static FRAME_ALLOCATOR: SpinLock<Option<BitmapFrameAllocator>> = SpinLock::new(None);
+
+/// 物理メモリの使用状況
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryInfo {
+    /// 使用可能な物理メモリの総量（バイト）
+    pub total_bytes: u64,
+    /// 管理対象のフレーム総数
+    pub total_frames: usize,
+    /// 空きフレーム数
+    pub free_frames: usize,
+    /// 使用中フレーム数
+    pub used_frames: usize,
+}
 
 /// ビットマップベースのフレームアロケータ
+///
+/// ビットが1のフレームは使用中（または使用不可）、0のフレームは空き。
 pub struct BitmapFrameAllocator {
     /// メモリマップ
     memory_map: &'static [MemoryRegion],
-    /// 次に割り当てるフレーム
-    next_frame: usize,
+    /// フレームビットマップ
+    bitmap: &'static mut [u64],
+    /// ビットマップが管理するフレーム数
+    frame_count: usize,
+    /// 使用可能なフレーム数（メモリマップ上のUsable領域）
+    usable_frames: usize,
+    /// 空きフレーム数
+    free_frames: usize,
+    /// 次に探索を開始するワード位置
+    next_word: usize,
 }
 
 impl BitmapFrameAllocator {
     /// 新しいフレームアロケータを作成
-    pub fn new(memory_map: &'static [MemoryRegion]) -> Self {
-        Self {
+    ///
+    /// ビットマップを配置できる使用可能領域がない場合はNone
+    pub fn new(memory_map: &'static [MemoryRegion]) -> Option<Self> {
+        let usable = || {
+            memory_map
+                .iter()
+                .filter(|r| r.region_type == MemoryType::Usable)
+        };
+
+        let highest = usable().map(|r| r.start + r.len).max()?;
+        let frame_count = (highest / FRAME_SIZE) as usize;
+        let words = frame_count.div_ceil(BITS_PER_WORD);
+        let bitmap_bytes = (words * 8) as u64;
+        let bitmap_frames = bitmap_bytes.div_ceil(FRAME_SIZE);
+
+        // ビットマップを置く領域を探す（フレーム0は避ける）
+        let bitmap_phys = usable().find_map(|r| {
+            let start = align_up(r.start.max(FRAME_SIZE), FRAME_SIZE);
+            let end = r.start + r.len;
+            if start + bitmap_frames * FRAME_SIZE <= end {
+                Some(start)
+            } else {
+                None
+            }
+        })?;
+
+        let offset = super::paging::physical_memory_offset();
+        let bitmap = unsafe {
+            core::slice::from_raw_parts_mut((bitmap_phys + offset) as *mut u64, words)
+        };
+
+        // 一旦すべて使用中にしてから、Usable領域だけを空きにする
+        bitmap.fill(u64::MAX);
+
+        let mut allocator = Self {
             memory_map,
-            next_frame: 0,
+            bitmap,
+            frame_count,
+            usable_frames: 0,
+            free_frames: 0,
+            next_word: 0,
+        };
+
+        for region in usable() {
+            let start = align_up(region.start, FRAME_SIZE) / FRAME_SIZE;
+            let end = (region.start + region.len) / FRAME_SIZE;
+            for index in start..end {
+                allocator.clear_bit(index as usize);
+                allocator.usable_frames += 1;
+                allocator.free_frames += 1;
+            }
+        }
+
+        // フレーム0はnullと区別できないため使わない
+        if !allocator.test_bit(0) {
+            allocator.set_bit(0);
+            allocator.free_frames -= 1;
         }
+
+        // ビットマップ自身が使っているフレームを予約
+        let first = (bitmap_phys / FRAME_SIZE) as usize;
+        for index in first..first + bitmap_frames as usize {
+            if !allocator.test_bit(index) {
+                allocator.set_bit(index);
+                allocator.free_frames -= 1;
+            }
+        }
+
+        Some(allocator)
     }
 
     /// 使用可能な物理メモリの総量を計算（バイト）
@@ -40,37 +139,147 @@ impl BitmapFrameAllocator {
 
     /// 使用可能なフレーム数を計算
     pub fn usable_frames(&self) -> usize {
-        (self.usable_memory() / 4096) as usize
+        self.usable_frames
     }
 
-    /// 使用可能なフレームのイテレータを返す
-    fn usable_frames_iter(&self) -> impl Iterator<Item = PhysFrame> + '_ {
-        self.memory_map
-            .iter()
-            .filter(|r| r.region_type == MemoryType::Usable)
-            .flat_map(|r| {
-                let start_addr = r.start;
-                let end_addr = r.start + r.len;
-                let start_frame = start_addr / 4096;
-                let end_frame = end_addr / 4096;
-                (start_frame..end_frame)
-                    .map(|f| PhysFrame::containing_address(PhysAddr::new(f * 4096)))
-            })
+    /// 空きフレーム数
+    pub fn free_frames(&self) -> usize {
+        self.free_frames
+    }
+
+    /// 使用中フレーム数
+    pub fn used_frames(&self) -> usize {
+        self.usable_frames - self.free_frames
+    }
+
+    /// 連続した物理フレームを割り当て
+    ///
+    /// 先頭フレームを返す。`count`が0の場合や連続領域が見つからない場合はNone
+    pub fn allocate_contiguous(&mut self, count: usize) -> Option<PhysFrame> {
+        if count == 0 || count > self.free_frames {
+            return None;
+        }
+        if count == 1 {
+            return self.allocate_frame();
+        }
+
+        let mut run_start = 0usize;
+        let mut run_len = 0usize;
+        let mut index = 0usize;
+        while index < self.frame_count {
+            // 64フレームすべて使用中のワードは読み飛ばす
+            if index.is_multiple_of(BITS_PER_WORD) && self.bitmap[index / BITS_PER_WORD] == u64::MAX {
+                run_len = 0;
+                index += BITS_PER_WORD;
+                continue;
+            }
+
+            if self.test_bit(index) {
+                run_len = 0;
+            } else {
+                if run_len == 0 {
+                    run_start = index;
+                }
+                run_len += 1;
+                if run_len == count {
+                    for i in run_start..run_start + count {
+                        self.set_bit(i);
+                    }
+                    self.free_frames -= count;
+                    return Some(frame_from_index(run_start));
+                }
+            }
+            index += 1;
+        }
+
+        None
+    }
+
+    /// 連続した物理フレームを解放
+    ///
+    /// # Safety
+    /// 呼び出し側は、解放するフレームがもう参照されていないことを保証する必要がある
+    pub unsafe fn deallocate_contiguous(&mut self, start: PhysFrame, count: usize) {
+        let first = (start.start_address().as_u64() / FRAME_SIZE) as usize;
+        for index in first..first + count {
+            self.release(index);
+        }
+    }
+
+    /// フレームを解放済みにする（二重解放は無視）
+    fn release(&mut self, index: usize) {
+        if index == 0 || index >= self.frame_count || !self.test_bit(index) {
+            crate::warn!("frame: ignoring invalid free of frame {:#x}", index as u64 * FRAME_SIZE);
+            return;
+        }
+        self.clear_bit(index);
+        self.free_frames += 1;
+        let word = index / BITS_PER_WORD;
+        if word < self.next_word {
+            self.next_word = word;
+        }
+    }
+
+    fn test_bit(&self, index: usize) -> bool {
+        self.bitmap[index / BITS_PER_WORD] & (1 << (index % BITS_PER_WORD)) != 0
+    }
+
+    fn set_bit(&mut self, index: usize) {
+        self.bitmap[index / BITS_PER_WORD] |= 1 << (index % BITS_PER_WORD);
+    }
+
+    fn clear_bit(&mut self, index: usize) {
+        self.bitmap[index / BITS_PER_WORD] &= !(1 << (index % BITS_PER_WORD));
     }
 }
 
 unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        let frame = self.usable_frames_iter().nth(self.next_frame);
-        self.next_frame += 1;
-        frame
+        if self.free_frames == 0 {
+            return None;
+        }
+
+        let words = self.bitmap.len();
+        for i in 0..words {
+            let word = (self.next_word + i) % words;
+            let bits = self.bitmap[word];
+            if bits == u64::MAX {
+                continue;
+            }
+            let index = word * BITS_PER_WORD + (!bits).trailing_zeros() as usize;
+            if index >= self.frame_count {
+                continue;
+            }
+            self.set_bit(index);
+            self.free_frames -= 1;
+            self.next_word = word;
+            return Some(frame_from_index(index));
+        }
+
+        None
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        self.release((frame.start_address().as_u64() / FRAME_SIZE) as usize);
     }
 }
 
+fn frame_from_index(index: usize) -> PhysFrame {
+    PhysFrame::containing_address(PhysAddr::new(index as u64 * FRAME_SIZE))
+}
+
+fn align_up(value: u64, align: u64) -> u64 {
+    (value + align - 1) & !(align - 1)
+}
+
 /// フレームアロケータを初期化
-pub fn init(memory_map: &'static [MemoryRegion]) {
-    let allocator = BitmapFrameAllocator::new(memory_map);
+pub fn init(memory_map: &'static [MemoryRegion]) -> Result<()> {
+    let allocator = BitmapFrameAllocator::new(memory_map)
+        .ok_or(KernelError::Memory(MemoryError::OutOfMemory))?;
     *FRAME_ALLOCATOR.lock() = Some(allocator);
+    Ok(())
 }
 
 /// フレームを割り当て
@@ -82,10 +291,41 @@ pub fn allocate_frame() -> Result<PhysFrame> {
         .ok_or(KernelError::Memory(MemoryError::OutOfMemory))
 }
 
-/// 使用可能なメモリ情報を取得
-pub fn get_memory_info() -> Option<(u64, usize)> {
+/// 連続した複数フレームを割り当て
+pub fn allocate_contiguous_frames(count: usize) -> Result<PhysFrame> {
     FRAME_ALLOCATOR
         .lock()
-        .as_ref()
-        .map(|a| (a.usable_memory(), a.usable_frames()))
+        .as_mut()
+        .and_then(|a| a.allocate_contiguous(count))
+        .ok_or(KernelError::Memory(MemoryError::OutOfMemory))
+}
+
+/// フレームを解放
+///
+/// # Safety
+/// 呼び出し側は、解放するフレームがどこからもマップ・参照されていないことを保証する必要がある
+pub unsafe fn deallocate_frame(frame: PhysFrame) {
+    if let Some(allocator) = FRAME_ALLOCATOR.lock().as_mut() {
+        allocator.deallocate_frame(frame);
+    }
+}
+
+/// 連続した複数フレームを解放
+///
+/// # Safety
+/// `deallocate_frame`と同じ
+pub unsafe fn deallocate_contiguous_frames(start: PhysFrame, count: usize) {
+    if let Some(allocator) = FRAME_ALLOCATOR.lock().as_mut() {
+        allocator.deallocate_contiguous(start, count);
+    }
+}
+
+/// 使用可能なメモリ情報を取得
+pub fn get_memory_info() -> Option<MemoryInfo> {
+    FRAME_ALLOCATOR.lock().as_ref().map(|a| MemoryInfo {
+        total_bytes: a.usable_memory(),
+        total_frames: a.usable_frames(),
+        free_frames: a.free_frames(),
+        used_frames: a.used_frames(),
+    })
 }
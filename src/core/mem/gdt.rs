@@ -34,12 +34,15 @@ pub fn init() {
     // GDTを初期化
     let (gdt, selectors) = GDT.call_once(|| {
         let mut gdt = GlobalDescriptorTable::new();
+        // SYSCALL/SYSRETはSTARの値からの相対位置でセレクタを決めるため、
+        // カーネルのコード→データ、ユーザーのデータ→コードの順に並べる
         let code_selector = gdt.append(Descriptor::kernel_code_segment());
         let data_selector = gdt.append(Descriptor::kernel_data_segment());
-        let tss_selector = gdt.append(Descriptor::tss_segment(tss));
         // user segments (RPL=3)
-        let user_code_selector = gdt.append(Descriptor::user_code_segment());
         let user_data_selector = gdt.append(Descriptor::user_data_segment());
+        let user_code_selector = gdt.append(Descriptor::user_code_segment());
+        // TSSはstatic領域にあり、以後も移動しない
+        let tss_selector = gdt.append(unsafe { Descriptor::tss_segment_unchecked(tss) });
 
         sprintln!("GDT entries created:");
         sprintln!("  Code selector: {:?}", code_selector);
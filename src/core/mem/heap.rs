@@ -0,0 +1,286 @@
+//! カーネルヒープ
+//!
+//! 上位アドレスの専用領域に物理フレームをマップして使うヒープ。
+//! 2048バイト以下の要求はサイズクラスごとのスラブから、
+//! それより大きい要求はページ単位の大オブジェクトとして割り当てる。
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::NonNull;
+
+use x86_64::structures::paging::{Page, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+use crate::error::{KernelError, MemoryError, Result};
+use crate::interrupt::spinlock::SpinLock;
+use crate::mem::{frame, paging};
+
+/// ヒープ領域の開始アドレス
+pub const HEAP_START: u64 = 0xFFFF_C000_0000_0000;
+/// ヒープ領域の最大サイズ（64GB）
+pub const HEAP_MAX_SIZE: u64 = 64 * 1024 * 1024 * 1024;
+
+const PAGE_SIZE: usize = 4096;
+
+/// スラブのサイズクラス（バイト）
+const SIZE_CLASSES: [usize; 8] = [16, 32, 64, 128, 256, 512, 1024, 2048];
+
+/// 再利用のために保持する解放済み大オブジェクト領域の数
+const MAX_FREE_RANGES: usize = 64;
+
+/// スラブ内の空きオブジェクト（空き領域自体にリンクを埋め込む）
+struct FreeObject {
+    next: Option<NonNull<FreeObject>>,
+}
+
+/// 1つのサイズクラスのスラブキャッシュ
+struct SlabCache {
+    /// オブジェクトサイズ
+    object_size: usize,
+    /// 空きオブジェクトのリスト
+    free_list: Option<NonNull<FreeObject>>,
+}
+
+impl SlabCache {
+    const fn new(object_size: usize) -> Self {
+        Self {
+            object_size,
+            free_list: None,
+        }
+    }
+
+    /// ページを分割して空きリストに追加
+    fn refill(&mut self, page: u64) {
+        let count = PAGE_SIZE / self.object_size;
+        for i in (0..count).rev() {
+            let obj = (page + (i * self.object_size) as u64) as *mut FreeObject;
+            unsafe {
+                obj.write(FreeObject { next: self.free_list });
+                self.free_list = Some(NonNull::new_unchecked(obj));
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Option<*mut u8> {
+        let obj = self.free_list?;
+        unsafe {
+            self.free_list = obj.as_ref().next;
+        }
+        Some(obj.as_ptr() as *mut u8)
+    }
+
+    fn push(&mut self, ptr: *mut u8) {
+        let obj = ptr as *mut FreeObject;
+        unsafe {
+            obj.write(FreeObject { next: self.free_list });
+            self.free_list = Some(NonNull::new_unchecked(obj));
+        }
+    }
+}
+
+/// 解放済みの大オブジェクト用仮想アドレス範囲
+#[derive(Clone, Copy)]
+struct FreeRange {
+    start: u64,
+    pages: usize,
+}
+
+/// スラブアロケータ本体
+pub struct SlabHeap {
+    caches: [SlabCache; SIZE_CLASSES.len()],
+    /// 次に払い出す仮想アドレス
+    next_virt: u64,
+    /// 再利用可能な仮想アドレス範囲
+    free_ranges: [Option<FreeRange>; MAX_FREE_RANGES],
+    /// マップ済みページ数
+    mapped_pages: usize,
+}
+
+// 生ポインタを含むが、SpinLock越しにのみアクセスする
+unsafe impl Send for SlabHeap {}
+
+impl SlabHeap {
+    const fn new() -> Self {
+        Self {
+            caches: [
+                SlabCache::new(SIZE_CLASSES[0]),
+                SlabCache::new(SIZE_CLASSES[1]),
+                SlabCache::new(SIZE_CLASSES[2]),
+                SlabCache::new(SIZE_CLASSES[3]),
+                SlabCache::new(SIZE_CLASSES[4]),
+                SlabCache::new(SIZE_CLASSES[5]),
+                SlabCache::new(SIZE_CLASSES[6]),
+                SlabCache::new(SIZE_CLASSES[7]),
+            ],
+            next_virt: HEAP_START,
+            free_ranges: [None; MAX_FREE_RANGES],
+            mapped_pages: 0,
+        }
+    }
+
+    /// レイアウトに対応するサイズクラスのインデックス
+    fn class_index(layout: &Layout) -> Option<usize> {
+        let size = layout.size().max(layout.align());
+        SIZE_CLASSES.iter().position(|&class| class >= size)
+    }
+
+    /// 仮想アドレス範囲を予約（解放済み範囲を優先して再利用）
+    fn reserve_virt(&mut self, pages: usize) -> Result<u64> {
+        for slot in self.free_ranges.iter_mut() {
+            if let Some(range) = slot {
+                if range.pages >= pages {
+                    let start = range.start;
+                    range.start += (pages * PAGE_SIZE) as u64;
+                    range.pages -= pages;
+                    if range.pages == 0 {
+                        *slot = None;
+                    }
+                    return Ok(start);
+                }
+            }
+        }
+
+        let size = (pages * PAGE_SIZE) as u64;
+        if self.next_virt + size > HEAP_START + HEAP_MAX_SIZE {
+            return Err(KernelError::Memory(MemoryError::OutOfMemory));
+        }
+        let start = self.next_virt;
+        self.next_virt += size;
+        Ok(start)
+    }
+
+    /// 仮想アドレス範囲に物理フレームをマップ
+    fn map_pages(&mut self, start: u64, pages: usize) -> Result<()> {
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        for i in 0..pages {
+            let page = Page::<Size4KiB>::containing_address(VirtAddr::new(start + (i * PAGE_SIZE) as u64));
+            let frame = frame::allocate_frame()?;
+            if let Err(e) = paging::map_page(page, frame, flags) {
+                unsafe { frame::deallocate_frame(frame) };
+                self.unmap_pages(start, i);
+                return Err(e);
+            }
+            self.mapped_pages += 1;
+        }
+        Ok(())
+    }
+
+    /// 仮想アドレス範囲のマップを解除してフレームを返却
+    fn unmap_pages(&mut self, start: u64, pages: usize) {
+        for i in 0..pages {
+            let page = Page::<Size4KiB>::containing_address(VirtAddr::new(start + (i * PAGE_SIZE) as u64));
+            if let Ok(frame) = paging::unmap_page(page) {
+                unsafe { frame::deallocate_frame(frame) };
+                self.mapped_pages -= 1;
+            }
+        }
+    }
+
+    /// 解放した仮想アドレス範囲を記録
+    ///
+    /// 隣接する解放済み範囲や未使用の末尾とは結合する。
+    /// 結合できず記録する場所もない場合は警告して破棄する（その範囲は再利用されない）
+    fn release_virt(&mut self, start: u64, pages: usize) {
+        let mut start = start;
+        let mut end = start + (pages * PAGE_SIZE) as u64;
+
+        // 隣接する範囲を取り込む
+        for slot in self.free_ranges.iter_mut() {
+            if let Some(range) = *slot {
+                let range_end = range.start + (range.pages * PAGE_SIZE) as u64;
+                if range_end == start {
+                    start = range.start;
+                    *slot = None;
+                } else if range.start == end {
+                    end = range_end;
+                    *slot = None;
+                }
+            }
+        }
+
+        if end == self.next_virt {
+            self.next_virt = start;
+            return;
+        }
+
+        let pages = ((end - start) / PAGE_SIZE as u64) as usize;
+        match self.free_ranges.iter_mut().find(|r| r.is_none()) {
+            Some(slot) => *slot = Some(FreeRange { start, pages }),
+            None => crate::warn!(
+                "heap: free range table full; discarding {:#x}..{:#x} ({} pages)",
+                start,
+                end,
+                pages
+            ),
+        }
+    }
+
+    fn alloc(&mut self, layout: Layout) -> Result<*mut u8> {
+        if let Some(index) = Self::class_index(&layout) {
+            if let Some(ptr) = self.caches[index].pop() {
+                return Ok(ptr);
+            }
+            let page = self.reserve_virt(1)?;
+            self.map_pages(page, 1)?;
+            self.caches[index].refill(page);
+            return self.caches[index]
+                .pop()
+                .ok_or(KernelError::Memory(MemoryError::OutOfMemory));
+        }
+
+        if layout.align() > PAGE_SIZE {
+            return Err(KernelError::Memory(MemoryError::AlignmentError));
+        }
+
+        let pages = layout.size().div_ceil(PAGE_SIZE);
+        let start = self.reserve_virt(pages)?;
+        if let Err(e) = self.map_pages(start, pages) {
+            self.release_virt(start, pages);
+            return Err(e);
+        }
+        Ok(start as *mut u8)
+    }
+
+    fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        if let Some(index) = Self::class_index(&layout) {
+            self.caches[index].push(ptr);
+            return;
+        }
+
+        let pages = layout.size().div_ceil(PAGE_SIZE);
+        self.unmap_pages(ptr as u64, pages);
+        self.release_virt(ptr as u64, pages);
+    }
+}
+
+/// グローバルヒープ
+static HEAP: SpinLock<SlabHeap> = SpinLock::new(SlabHeap::new());
+
+/// `#[global_allocator]`として登録するアロケータ
+pub struct KernelHeapAllocator;
+
+unsafe impl GlobalAlloc for KernelHeapAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        HEAP.lock().alloc(layout).unwrap_or_default()
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        HEAP.lock().dealloc(ptr, layout);
+    }
+}
+
+/// ヒープを初期化
+///
+/// 最初のスラブページをマップしておき、ヒープ領域用のPML4エントリを
+/// プロセスのアドレス空間が作られる前にカーネル側で確定させる
+pub fn init() -> Result<()> {
+    let mut heap = HEAP.lock();
+    let page = heap.reserve_virt(1)?;
+    heap.map_pages(page, 1)?;
+    heap.caches[0].refill(page);
+    Ok(())
+}
+
+/// ヒープにマップされているバイト数
+pub fn mapped_bytes() -> usize {
+    HEAP.lock().mapped_pages * PAGE_SIZE
+}
@@ -0,0 +1,141 @@
+//! スレッドごとのカーネルスタック
+//!
+//! 上位アドレスの専用領域を固定サイズのスロットに区切って割り当てる。
+//! 各スロットの先頭1ページはマップしないガードページとし、
+//! スタックがあふれた場合は隣のスタックを壊さずにページフォルトになる。
+
+use alloc::vec::Vec;
+
+use x86_64::structures::paging::{Page, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+use crate::error::{KernelError, MemoryError, Result};
+use crate::interrupt::spinlock::SpinLock;
+use crate::mem::{frame, paging};
+
+/// カーネルスタック領域の開始アドレス
+pub const KSTACK_START: u64 = 0xFFFF_C800_0000_0000;
+/// カーネルスタック領域の最大サイズ（1GB）
+pub const KSTACK_MAX_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// 1スタックあたりのページ数（ガードページを除く）
+pub const KSTACK_PAGES: usize = 8;
+
+const PAGE_SIZE: u64 = 4096;
+/// ガードページを含むスロットのサイズ
+const SLOT_SIZE: u64 = (KSTACK_PAGES as u64 + 1) * PAGE_SIZE;
+
+/// 割り当て済みのカーネルスタック
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelStack {
+    /// スタックの最下位アドレス（ガードページの直上）
+    base: u64,
+}
+
+impl KernelStack {
+    /// スタックの最下位アドレス
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// スタックのサイズ（バイト）
+    pub fn size(&self) -> usize {
+        KSTACK_PAGES * PAGE_SIZE as usize
+    }
+
+    /// スタックの上端（初期RSP）
+    pub fn top(&self) -> u64 {
+        self.base + self.size() as u64
+    }
+
+    /// アドレスがこのスタック（ガードページを含む）の範囲内かどうか
+    pub fn contains(&self, addr: u64) -> bool {
+        (self.base - PAGE_SIZE..self.top()).contains(&addr)
+    }
+}
+
+struct KernelStackAllocator {
+    /// 次に払い出すスロット
+    next_slot: u64,
+    /// 解放済みスロット（ガードページの先頭アドレス）
+    free_slots: Vec<u64>,
+}
+
+impl KernelStackAllocator {
+    const fn new() -> Self {
+        Self {
+            next_slot: KSTACK_START,
+            free_slots: Vec::new(),
+        }
+    }
+
+    fn reserve_slot(&mut self) -> Result<u64> {
+        if let Some(slot) = self.free_slots.pop() {
+            return Ok(slot);
+        }
+        if self.next_slot + SLOT_SIZE > KSTACK_START + KSTACK_MAX_SIZE {
+            return Err(KernelError::Memory(MemoryError::OutOfMemory));
+        }
+        let slot = self.next_slot;
+        self.next_slot += SLOT_SIZE;
+        Ok(slot)
+    }
+
+    fn release_slot(&mut self, slot: u64) {
+        if self.free_slots.try_reserve(1).is_ok() {
+            self.free_slots.push(slot);
+        }
+    }
+}
+
+static ALLOCATOR: SpinLock<KernelStackAllocator> = SpinLock::new(KernelStackAllocator::new());
+
+/// 指定範囲のページをマップ解除してフレームを返却
+fn unmap_pages(base: u64, pages: usize) {
+    for i in 0..pages {
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(base + i as u64 * PAGE_SIZE));
+        if let Ok(frame) = paging::unmap_page(page) {
+            unsafe { frame::deallocate_frame(frame) };
+        }
+    }
+}
+
+/// カーネルスタックを割り当てる
+pub fn alloc() -> Result<KernelStack> {
+    let slot = ALLOCATOR.lock().reserve_slot()?;
+    let base = slot + PAGE_SIZE;
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+
+    for i in 0..KSTACK_PAGES {
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(base + i as u64 * PAGE_SIZE));
+        let mapped = frame::allocate_frame().and_then(|f| {
+            paging::map_page(page, f, flags).inspect_err(|_| unsafe { frame::deallocate_frame(f) })
+        });
+        if let Err(e) = mapped {
+            unmap_pages(base, i);
+            ALLOCATOR.lock().release_slot(slot);
+            return Err(e);
+        }
+    }
+
+    Ok(KernelStack { base })
+}
+
+/// カーネルスタックを解放する
+///
+/// # Safety
+/// スタックを使用中のスレッドがあってはならない
+pub unsafe fn free(stack: KernelStack) {
+    unmap_pages(stack.base, KSTACK_PAGES);
+    ALLOCATOR.lock().release_slot(stack.base - PAGE_SIZE);
+}
+
+/// カーネルスタック領域を初期化
+///
+/// 最初のスタックを割り当てて解放しておき、領域用のPML4エントリを
+/// プロセスのアドレス空間が作られる前にカーネル側で確定させる
+pub fn init() -> Result<()> {
+    let stack = alloc()?;
+    unsafe { free(stack) };
+    Ok(())
+}
@@ -1,14 +1,21 @@
 //! メモリ管理モジュール
 //!
-//! GDT、TSS、ページング、フレームアロケータ
+//! GDT、TSS、CPUごとのデータ、ページング、フレームアロケータ
 
 use crate::{interrupt, sprintln, MemoryRegion, Result};
 
+pub use address_space::AddressSpace;
+
+pub mod address_space;
 pub mod frame;
 pub mod gdt;
+pub mod heap;
+pub mod kstack;
 pub mod paging;
+pub mod percpu;
 pub mod tss;
 pub mod user;
+pub mod vma;
 
 pub fn init(physical_memory_offset: u64) {
     sprintln!("Initializing memory...");
@@ -18,6 +25,7 @@ pub fn init(physical_memory_offset: u64) {
 
     paging::init(physical_memory_offset);
     gdt::init();
+    percpu::init();
     interrupt::init_idt();
 
     // PITを停止してからPICを初期化
@@ -27,15 +35,19 @@ pub fn init(physical_memory_offset: u64) {
     sprintln!("Memory initialized");
 }
 
-/// メモリマップを設定してフレームアロケータを初期化
+/// メモリマップを設定してフレームアロケータとカーネルヒープを初期化
 pub fn init_frame_allocator(memory_map: &'static [MemoryRegion]) -> Result<()> {
-    frame::init(memory_map);
+    frame::init(memory_map)?;
+    heap::init()?;
+    kstack::init()?;
 
-    if let Some((total, frames)) = frame::get_memory_info() {
+    if let Some(info) = frame::get_memory_info() {
         sprintln!(
-            "Physical memory: {} MB ({} frames)",
-            total / 1024 / 1024,
-            frames
+            "Physical memory: {} MB ({} frames, {} free, {} used)",
+            info.total_bytes / 1024 / 1024,
+            info.total_frames,
+            info.free_frames,
+            info.used_frames
         );
     }
 
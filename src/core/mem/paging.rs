@@ -3,26 +3,49 @@
 //! 仮想メモリとページテーブル管理
 
 use crate::error::{KernelError, MemoryError, Result};
-use crate::sprintln;
-use spin::Mutex;
+use crate::interrupt::spinlock::SpinLock;
+use crate::{sprintln, MemoryRegion, MemoryType};
 use x86_64::{
-    registers::control::{Cr0, Cr0Flags},
+    registers::control::{Cr0, Cr0Flags, Cr3, Cr3Flags},
+    registers::model_specific::{Efer, EferFlags},
     structures::paging::{
-        mapper::MapToError, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags, PhysFrame,
+        mapper::{MapToError, UnmapError}, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags, PhysFrame,
         Size4KiB,
     },
     VirtAddr,
 };
 use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Once;
 
-static PAGE_TABLE: Mutex<Option<OffsetPageTable<'static>>> = Mutex::new(None);
+/// 物理メモリ全体をマップする仮想アドレス（ブートローダーが上位半分に作成する）
+///
+/// 下位半分の恒等マッピングはユーザー空間と重なるため、物理フレームへのアクセスにはこちらを使う
+pub const PHYS_MAP_START: u64 = 0xFFFF_8000_0000_0000;
+
+static PAGE_TABLE: SpinLock<Option<OffsetPageTable<'static>>> = SpinLock::new(None);
 static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
+/// カーネルのレベル4ページテーブルの物理アドレス
+static KERNEL_PML4: AtomicU64 = AtomicU64::new(0);
+/// カーネルが恒等マッピングのまま使う範囲
+static IDENTITY_RANGES: Once<IdentityRanges> = Once::new();
+
+/// カーネルが恒等マッピングのまま使う物理範囲
+///
+/// カーネルイメージやファームウェアの領域（メモリマップのUsable以外）とフレームバッファ
+struct IdentityRanges {
+    memory_map: &'static [MemoryRegion],
+    framebuffer: (u64, u64),
+}
 
 /// ページングシステムを初期化
 pub fn init(physical_memory_offset: u64) {
     sprintln!("Initializing paging...");
 
     unsafe {
+        // NXビットを使用するためEFER.NXEを有効化
+        Efer::update(|flags| flags.insert(EferFlags::NO_EXECUTE_ENABLE));
+
+        KERNEL_PML4.store(Cr3::read().0.start_address().as_u64(), Ordering::Relaxed);
         let level_4_table = active_level_4_table(physical_memory_offset);
         let page_table = OffsetPageTable::new(level_4_table, VirtAddr::new(physical_memory_offset));
         *PAGE_TABLE.lock() = Some(page_table);
@@ -32,11 +55,55 @@ pub fn init(physical_memory_offset: u64) {
     sprintln!("Paging initialized");
 }
 
+/// カーネルが恒等マッピングのまま使う範囲を登録
+pub fn reserve_identity_ranges(memory_map: &'static [MemoryRegion], framebuffer_addr: u64, framebuffer_size: u64) {
+    IDENTITY_RANGES.call_once(|| IdentityRanges {
+        memory_map,
+        framebuffer: (framebuffer_addr, framebuffer_addr.saturating_add(framebuffer_size)),
+    });
+}
+
+/// 仮想範囲`[start, end)`がカーネルの恒等マッピングで使う範囲と重なるか
+///
+/// ユーザー空間の同じアドレスにページをマップすると、そのアドレス空間ではカーネルのアクセスが
+/// ユーザーのページに向いてしまうため、ユーザー領域はこれらと重ねない
+pub fn overlaps_identity_range(start: u64, end: u64) -> bool {
+    let Some(ranges) = IDENTITY_RANGES.get() else {
+        return false;
+    };
+    let overlaps = |(s, e): (u64, u64)| s < end && start < e;
+    overlaps(ranges.framebuffer)
+        || ranges
+            .memory_map
+            .iter()
+            .filter(|r| r.region_type != MemoryType::Usable)
+            .any(|r| overlaps((r.start, r.start.saturating_add(r.len))))
+}
+
 /// 現在設定されている物理メモリオフセットを返す
 pub fn physical_memory_offset() -> u64 {
     PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed)
 }
 
+/// カーネルのレベル4ページテーブルのフレームを取得
+pub fn kernel_pml4_frame() -> PhysFrame {
+    PhysFrame::containing_address(PhysAddr::new(KERNEL_PML4.load(Ordering::Relaxed)))
+}
+
+/// アドレス空間を切り替える
+///
+/// `None`の場合はカーネルのページテーブルに戻す。既に同じCR3がロードされていれば何もしない。
+pub fn switch_address_space(cr3: Option<u64>) {
+    let target = cr3.unwrap_or_else(|| KERNEL_PML4.load(Ordering::Relaxed));
+    if target == 0 || Cr3::read().0.start_address().as_u64() == target {
+        return;
+    }
+    let frame = PhysFrame::containing_address(PhysAddr::new(target));
+    unsafe {
+        Cr3::write(frame, Cr3Flags::empty());
+    }
+}
+
 /// アクティブなレベル4ページテーブルへの参照を取得
 unsafe fn active_level_4_table(physical_memory_offset: u64) -> &'static mut PageTable {
     use x86_64::registers::control::Cr3;
@@ -85,6 +152,26 @@ pub fn map_page(page: Page, frame: PhysFrame, flags: PageTableFlags) -> Result<(
     Ok(())
 }
 
+/// ページのマップを解除し、マップされていたフレームを返す
+///
+/// フレームの解放は呼び出し側で行う
+pub fn unmap_page(page: Page) -> Result<PhysFrame> {
+    let mut page_table_lock = PAGE_TABLE.lock();
+    let page_table = page_table_lock
+        .as_mut()
+        .ok_or(KernelError::Memory(MemoryError::NotMapped))?;
+
+    match page_table.unmap(page) {
+        Ok((frame, flush)) => {
+            flush.flush();
+            Ok(frame)
+        }
+        Err(UnmapError::PageNotMapped) => Err(KernelError::Memory(MemoryError::NotMapped)),
+        Err(UnmapError::ParentEntryHugePage) => Err(KernelError::Memory(MemoryError::AlignmentError)),
+        Err(UnmapError::InvalidFrameAddress(_)) => Err(KernelError::Memory(MemoryError::InvalidAddress)),
+    }
+}
+
 /// 仮想アドレスを物理アドレスに変換
 pub fn translate_addr(addr: VirtAddr) -> Option<PhysAddr> {
     use x86_64::structures::paging::mapper::Translate;
@@ -0,0 +1,55 @@
+//! CPUごとのデータ
+//!
+//! SYSCALL命令の入口は`swapgs`でGSベースをこの領域に切り替え、ユーザーのRSPの退避と
+//! カーネルスタックの取得に使う。領域のアドレスはIA32_KERNEL_GS_BASEに置き、
+//! GSベースを入れ替えるのは入口の中だけにする（カーネル実行中のGSベースはユーザーのまま）。
+
+use core::cell::UnsafeCell;
+use core::mem::offset_of;
+
+use x86_64::registers::model_specific::KernelGsBase;
+use x86_64::VirtAddr;
+
+/// CPUごとのデータ（アセンブリからオフセットで参照する）
+#[repr(C)]
+pub struct PerCpu {
+    /// SYSCALL時に切り替えるカーネルスタックの上端（TSSのRSP0と同じ値）
+    kernel_rsp: u64,
+    /// SYSCALL時に退避したユーザーのRSP
+    user_rsp: u64,
+}
+
+/// `PerCpu::kernel_rsp`のオフセット
+pub const KERNEL_RSP_OFFSET: usize = offset_of!(PerCpu, kernel_rsp);
+/// `PerCpu::user_rsp`のオフセット
+pub const USER_RSP_OFFSET: usize = offset_of!(PerCpu, user_rsp);
+
+struct CpuLocal(UnsafeCell<PerCpu>);
+
+// 各CPUは自分の領域にしか書き込まない
+unsafe impl Sync for CpuLocal {}
+
+/// BSPの領域（SMP未対応のため1つのみ）
+static PER_CPU: CpuLocal = CpuLocal(UnsafeCell::new(PerCpu {
+    kernel_rsp: 0,
+    user_rsp: 0,
+}));
+
+/// 現在のCPUの領域
+fn current() -> *mut PerCpu {
+    PER_CPU.0.get()
+}
+
+/// 現在のCPUの領域をIA32_KERNEL_GS_BASEに設定
+pub fn init() {
+    KernelGsBase::write(VirtAddr::from_ptr(current()));
+}
+
+/// SYSCALL時に使うカーネルスタックの上端を設定
+///
+/// TSSのRSP0と同じく、割り込み禁止の状態でスレッドを切り替える前に呼ぶ
+pub fn set_kernel_stack(top: u64) {
+    unsafe {
+        (*current()).kernel_rsp = top;
+    }
+}
@@ -1,8 +1,10 @@
 //! TSS管理モジュール
 //!
-//! TSSを管理
+//! TSSを管理。RSP0（Ring3からの割り込み時に使うカーネルスタック）は
+//! スレッド切り替えのたびに実行するスレッドのカーネルスタックへ書き換える。
 
 use crate::sprintln;
+use core::cell::UnsafeCell;
 use spin::Once;
 use x86_64::structures::tss::TaskStateSegment;
 use x86_64::VirtAddr;
@@ -10,15 +12,31 @@ use x86_64::VirtAddr;
 /// ダブルフォルト用ISTインデックス
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
 
-static TSS: Once<TaskStateSegment> = Once::new();
+/// CPUごとのTSS
+///
+/// GDTのディスクリプタが指すため移動せず、RSP0の更新は自CPUからのみ行う
+struct CpuTss(UnsafeCell<TaskStateSegment>);
+
+// 各CPUは自分のTSSにしか書き込まない
+unsafe impl Sync for CpuTss {}
+
+/// BSPのTSS（SMP未対応のため1つのみ）
+static TSS: CpuTss = CpuTss(UnsafeCell::new(TaskStateSegment::new()));
+
+static TSS_INIT: Once<()> = Once::new();
+
+/// 現在のCPUのTSS
+fn current() -> *mut TaskStateSegment {
+    TSS.0.get()
+}
 
 /// TSSを初期化して返す
 #[allow(unused_unsafe)]
-pub fn init() -> &'static TaskStateSegment {
+pub fn init() -> *const TaskStateSegment {
     sprintln!("Initializing TSS...");
 
-    TSS.call_once(|| {
-        let mut tss = TaskStateSegment::new();
+    TSS_INIT.call_once(|| {
+        let tss = unsafe { &mut *current() };
 
         // ダブルフォルト用の専用スタックを設定
         tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
@@ -31,6 +49,21 @@ pub fn init() -> &'static TaskStateSegment {
         };
 
         sprintln!("TSS configured with IST[{}] stack", DOUBLE_FAULT_IST_INDEX);
-        tss
-    })
+    });
+
+    current()
+}
+
+/// Ring3からの割り込み時に使うカーネルスタックの上端を設定
+///
+/// 割り込み禁止の状態で、切り替え先スレッドへ制御を移す前に呼ぶ
+pub fn set_kernel_stack(top: u64) {
+    unsafe {
+        (*current()).privilege_stack_table[0] = VirtAddr::new(top);
+    }
+}
+
+/// 現在設定されているRSP0
+pub fn kernel_stack() -> u64 {
+    unsafe { (*current()).privilege_stack_table[0].as_u64() }
 }
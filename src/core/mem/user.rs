@@ -1,48 +1,78 @@
 //! ユーザー空間メモリ管理
 
-use spin::Mutex;
 use x86_64::structures::paging::{Page, PageTableFlags, Size4KiB};
 use x86_64::VirtAddr;
 
 use crate::error::{KernelError, MemoryError, Result};
-use crate::mem::{frame, paging};
+use crate::mem::address_space::USER_SPACE_END;
+use crate::mem::{frame, AddressSpace};
+use crate::mem::vma::RegionKind;
 
 const PAGE_SIZE: u64 = 4096;
-const USER_STACK_TOP: u64 = 0x0000_8000_0000; // 2GB
+/// ユーザースタックを配置する領域の上端
+pub const USER_STACK_TOP: u64 = 0x0000_8000_0000; // 2GB
 const USER_STACK_GUARD_PAGES: u64 = 1;
-
-static NEXT_STACK_TOP: Mutex<u64> = Mutex::new(USER_STACK_TOP);
+/// ユーザースタックの既定サイズ（ページ数）。ページは使用時に割り当てる
+pub const USER_STACK_PAGES: u64 = 256;
 
 pub struct UserStack {
     pub bottom: u64,
     pub top: u64,
 }
 
-/// 任意のユーザ空間レンジをマップ
-pub fn map_user_range(start: u64, size: u64, flags: PageTableFlags) -> Result<()> {
+/// ユーザ空間レンジを領域として予約（ページはフォルト時に割り当てる）
+pub fn reserve_user_range(
+    space: &mut AddressSpace,
+    start: u64,
+    size: u64,
+    flags: PageTableFlags,
+    kind: RegionKind,
+) -> Result<()> {
+    space.add_region(start, size, flags, kind)
+}
+
+/// 任意のユーザ空間レンジを予約し、すべてのページを即座にマップ
+pub fn map_user_range(
+    space: &mut AddressSpace,
+    start: u64,
+    size: u64,
+    flags: PageTableFlags,
+    kind: RegionKind,
+) -> Result<()> {
     if size == 0 {
         return Ok(());
     }
+    reserve_user_range(space, start, size, flags, kind)?;
 
     let start_page = Page::<Size4KiB>::containing_address(VirtAddr::new(start));
     let end = start.checked_add(size - 1).ok_or(KernelError::Memory(MemoryError::InvalidAddress))?;
     let end_page = Page::<Size4KiB>::containing_address(VirtAddr::new(end));
 
     for page in Page::range_inclusive(start_page, end_page) {
+        if space.translate(page.start_address()).is_some_and(|(_, f)| f.contains(super::address_space::OWNED)) {
+            // 隣接セグメントと共有するページは既にマップ済み
+            continue;
+        }
         let frame = frame::allocate_frame()?;
-        paging::map_page(page, frame, flags)?;
+        if let Err(e) = space.map_page(page, frame, flags) {
+            unsafe { frame::deallocate_frame(frame) };
+            return Err(e);
+        }
+        space.zero(page.start_address().as_u64(), PAGE_SIZE as usize)?;
     }
 
     Ok(())
 }
 
 /// ユーザスタックを確保
-pub fn alloc_user_stack(pages: u64) -> Result<UserStack> {
+///
+/// 領域を予約するだけで、ページは使用時に割り当てる
+pub fn alloc_user_stack(space: &mut AddressSpace, pages: u64) -> Result<UserStack> {
     if pages == 0 {
         return Err(KernelError::Memory(MemoryError::InvalidAddress));
     }
 
-    let mut top = NEXT_STACK_TOP.lock();
+    let top = space.next_stack_top();
 
     let stack_size = pages * PAGE_SIZE;
     let total = stack_size + USER_STACK_GUARD_PAGES * PAGE_SIZE;
@@ -56,14 +86,35 @@ pub fn alloc_user_stack(pages: u64) -> Result<UserStack> {
 
     let flags = PageTableFlags::PRESENT
         | PageTableFlags::WRITABLE
-        | PageTableFlags::USER_ACCESSIBLE;
+        | PageTableFlags::USER_ACCESSIBLE
+        | PageTableFlags::NO_EXECUTE;
 
-    map_user_range(stack_bottom, stack_size, flags)?;
+    reserve_user_range(space, stack_bottom, stack_size, flags, RegionKind::Stack)?;
 
-    *top = new_top;
+    space.set_next_stack_top(new_top);
 
     Ok(UserStack {
         bottom: stack_bottom,
         top: stack_top,
     })
 }
+
+/// 現在のプロセスのアドレス空間でページフォルトを解決
+///
+/// 解決できない場合（領域外や属性違反）はエラーを返す
+pub fn handle_page_fault(addr: u64, write: bool, exec: bool) -> Result<()> {
+    if addr >= USER_SPACE_END {
+        return Err(KernelError::Memory(MemoryError::InvalidAddress));
+    }
+
+    let pid = crate::task::current_thread_id()
+        .and_then(|id| crate::task::with_thread(id, |t| t.process_id()))
+        .ok_or(KernelError::Memory(MemoryError::NotMapped))?;
+
+    crate::task::with_process_mut(pid, |p| {
+        p.address_space_mut()
+            .map(|space| space.handle_fault(addr, write, exec))
+    })
+    .flatten()
+    .unwrap_or(Err(KernelError::Memory(MemoryError::NotMapped)))
+}
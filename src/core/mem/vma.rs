@@ -0,0 +1,83 @@
+//! 仮想メモリ領域（VMA）
+//!
+//! アドレス空間内でアクセスが許可されている範囲と、その範囲のページに付与する属性を表す。
+//! 領域内でまだマップされていないページは、ページフォルト時にゼロ初期化して割り当てる。
+
+use x86_64::structures::paging::PageTableFlags;
+
+/// 領域の用途
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    /// ELFセグメント（ファイル内容を持つ部分）
+    Elf,
+    /// ゼロ初期化されるデータ（BSS）
+    Bss,
+    /// ユーザースタック
+    Stack,
+    /// ヒープ
+    Heap,
+    /// 匿名マッピング
+    Anon,
+}
+
+/// 仮想メモリ領域
+///
+/// `start`と`end`はページ境界に揃えた半開区間`[start, end)`
+#[derive(Debug, Clone, Copy)]
+pub struct VmRegion {
+    /// 開始アドレス
+    pub start: u64,
+    /// 終了アドレス（この値を含まない）
+    pub end: u64,
+    /// 領域内のページに付与するフラグ
+    pub flags: PageTableFlags,
+    /// 領域の用途
+    pub kind: RegionKind,
+}
+
+impl VmRegion {
+    /// 新しい領域を作成
+    pub const fn new(start: u64, end: u64, flags: PageTableFlags, kind: RegionKind) -> Self {
+        Self {
+            start,
+            end,
+            flags,
+            kind,
+        }
+    }
+
+    /// アドレスが領域内かどうか
+    pub fn contains(&self, addr: u64) -> bool {
+        self.start <= addr && addr < self.end
+    }
+
+    /// 範囲`[start, end)`と重なるかどうか
+    pub fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start < end && start < self.end
+    }
+
+    /// 領域のサイズ（バイト）
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    /// 領域が空かどうか
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+
+    /// ユーザーからアクセスできるか（PROT_NONEの領域はfalse）
+    pub fn is_accessible(&self) -> bool {
+        self.flags.contains(PageTableFlags::USER_ACCESSIBLE)
+    }
+
+    /// 書き込みが許可されているか
+    pub fn is_writable(&self) -> bool {
+        self.flags.contains(PageTableFlags::WRITABLE)
+    }
+
+    /// 実行が許可されているか
+    pub fn is_executable(&self) -> bool {
+        !self.flags.contains(PageTableFlags::NO_EXECUTE)
+    }
+}
@@ -0,0 +1,130 @@
+//! サービス定義ファイル（initfsの`/etc/services`）の解析
+//!
+//! 1行に1サービスを「名前 キー=値 ...」の形で書く。`#`以降はコメント。
+//!
+//! | キー        | 値                                   | 既定値       |
+//! |-------------|--------------------------------------|--------------|
+//! | `path`      | initfs上のELFのパス（必須）           |              |
+//! | `privilege` | `service` / `user`                   | `service`    |
+//! | `restart`   | `never` / `on-failure` / `always`    | `on-failure` |
+//! | `after`     | 先に起動するサービス名（カンマ区切り） | なし         |
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::error::{KernelError, ProcessError, Result, ServiceError};
+use crate::task::PrivilegeLevel;
+
+/// サービス定義ファイルのパス
+pub const MANIFEST_PATH: &str = "/etc/services";
+
+/// 終了したサービスを再起動する条件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// 再起動しない
+    Never,
+    /// 0以外の終了コードで終了した（クラッシュした）場合のみ再起動する
+    OnFailure,
+    /// 常に再起動する
+    Always,
+}
+
+/// サービスの定義
+#[derive(Debug, Clone)]
+pub struct ServiceSpec {
+    /// サービス名
+    pub name: String,
+    /// initfs上のELFのパス
+    pub path: String,
+    /// 権限レベル
+    pub privilege: PrivilegeLevel,
+    /// 再起動の条件
+    pub restart: RestartPolicy,
+    /// 先に起動しておくサービス
+    pub after: Vec<String>,
+}
+
+/// 定義ファイルを解析する
+///
+/// 書式の誤った行と名前が重複する行は警告を出して読み飛ばす
+pub fn parse(text: &str) -> Vec<ServiceSpec> {
+    let mut specs: Vec<ServiceSpec> = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let spec = match parse_line(line) {
+            Ok(spec) => spec,
+            Err(e) => {
+                crate::warn!("{}:{}: {:?}", MANIFEST_PATH, number + 1, e);
+                continue;
+            }
+        };
+        if specs.iter().any(|s| s.name == spec.name) {
+            crate::warn!(
+                "{}:{}: service '{}' is already defined ({:?})",
+                MANIFEST_PATH,
+                number + 1,
+                spec.name,
+                ServiceError::Conflict
+            );
+            continue;
+        }
+        specs.push(spec);
+    }
+
+    specs
+}
+
+/// 1行分の定義を解析する
+fn parse_line(line: &str) -> Result<ServiceSpec> {
+    let mut fields = line.split_whitespace();
+    let name = fields.next().ok_or(KernelError::InvalidParam)?;
+
+    let mut path = None;
+    let mut privilege = PrivilegeLevel::Service;
+    let mut restart = RestartPolicy::OnFailure;
+    let mut after = Vec::new();
+
+    for field in fields {
+        let (key, value) = field.split_once('=').ok_or(KernelError::InvalidParam)?;
+        match key {
+            "path" => path = Some(value.to_string()),
+            "privilege" => privilege = parse_privilege(value)?,
+            "restart" => restart = parse_restart(value)?,
+            "after" => after.extend(value.split(',').filter(|s| !s.is_empty()).map(String::from)),
+            _ => return Err(KernelError::InvalidParam),
+        }
+    }
+
+    let path = path.ok_or(KernelError::InvalidParam)?;
+    Ok(ServiceSpec {
+        name: name.to_string(),
+        path,
+        privilege,
+        restart,
+        after,
+    })
+}
+
+fn parse_privilege(value: &str) -> Result<PrivilegeLevel> {
+    match value {
+        "service" => Ok(PrivilegeLevel::Service),
+        "user" => Ok(PrivilegeLevel::User),
+        // Coreの権限でユーザー空間のプログラムは動かさない
+        "core" => Err(KernelError::Process(ProcessError::Service(ServiceError::InsufficientPrivilege))),
+        _ => Err(KernelError::InvalidParam),
+    }
+}
+
+fn parse_restart(value: &str) -> Result<RestartPolicy> {
+    match value {
+        "never" => Ok(RestartPolicy::Never),
+        "on-failure" => Ok(RestartPolicy::OnFailure),
+        "always" => Ok(RestartPolicy::Always),
+        _ => Err(KernelError::InvalidParam),
+    }
+}
@@ -0,0 +1,313 @@
+//! サービスマネージャ
+//!
+//! initfsの`/etc/services`に定義されたサービスを依存関係の順に起動し、
+//! 終了したサービスを再起動の条件に従って起動し直す。
+//! サービスはマネージャのプロセスの子として起動し、終了は`wait_child_until`で受け取る。
+//! 再起動は待ち時間の後に行い、その間も他のサービスの終了を受け取れるよう
+//! 待ち合わせの期限として扱う。
+//! 起動や再起動に失敗したサービスは`ServiceError`を状態として記録する。
+
+pub mod manifest;
+
+use alloc::format;
+use alloc::vec::Vec;
+
+use crate::error::{KernelError, ProcessError, Result, ServiceError};
+use crate::interrupt::spinlock::SpinLock;
+use crate::interrupt::timer::{get_ticks, TICKS_PER_SECOND};
+use crate::task::{self, PrivilegeLevel, ProcessId};
+
+pub use manifest::{RestartPolicy, ServiceSpec, MANIFEST_PATH};
+
+/// マネージャのプロセスの優先度（サービスと同じ）
+const MANAGER_PRIORITY: u8 = 1;
+/// 続けて再起動する回数の上限
+const MAX_RESTARTS: u32 = 5;
+/// この時間以上動いてから終了した場合は再起動の回数を数え直す
+const STABLE_TICKS: u64 = 10 * TICKS_PER_SECOND;
+/// 再起動までの待ち時間（続けて再起動した回数に比例させる）
+const RESTART_DELAY_TICKS: u64 = TICKS_PER_SECOND / 2;
+
+/// サービスの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceState {
+    /// 起動待ち
+    Pending,
+    /// 実行中
+    Running(ProcessId),
+    /// 終了した（終了コード）
+    Exited(u64),
+    /// 起動できない、または再起動をあきらめた
+    Failed(ServiceError),
+}
+
+struct Service {
+    spec: ServiceSpec,
+    state: ServiceState,
+    /// 続けて再起動した回数
+    restarts: u32,
+    /// 最後に起動した時刻（ティック）
+    started_at: u64,
+    /// 再起動する時刻（ティック）。再起動を待っていない場合はNone
+    restart_at: Option<u64>,
+}
+
+/// 定義ファイルに書かれたサービス（定義順）
+static SERVICES: SpinLock<Vec<Service>> = SpinLock::new(Vec::new());
+
+fn service_error(e: ServiceError) -> KernelError {
+    KernelError::Process(ProcessError::Service(e))
+}
+
+/// サービスマネージャのプロセスとスレッドを作成する
+///
+/// サービスの起動はスケジューラの開始後にマネージャのスレッドが行う
+pub fn start() -> Result<ProcessId> {
+    let process = task::Process::new("core.services", PrivilegeLevel::Core, task::init_process_id(), MANAGER_PRIORITY);
+    let pid = process.id();
+    task::add_process(process).ok_or(KernelError::Process(ProcessError::MaxProcessesReached))?;
+
+    if let Err(e) = task::add_kernel_thread(pid, "core.services", manager_main) {
+        task::remove_process(pid);
+        return Err(e);
+    }
+    Ok(pid)
+}
+
+/// サービスの状態を取得
+pub fn status(name: &str) -> Result<ServiceState> {
+    SERVICES
+        .lock()
+        .iter()
+        .find(|s| s.spec.name == name)
+        .map(|s| s.state)
+        .ok_or(service_error(ServiceError::Unregistered))
+}
+
+fn manager_main() -> ! {
+    if let Some(manager) = task::current_process_id() {
+        match load() {
+            Ok(specs) => start_all(manager, specs),
+            Err(e) => crate::warn!("service manager: cannot load {}: {:?}", MANIFEST_PATH, e),
+        }
+        supervise(manager);
+    }
+
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// 定義ファイルを読み込む
+fn load() -> Result<Vec<ServiceSpec>> {
+    let data = crate::init::fs::read(MANIFEST_PATH).map_err(|_| service_error(ServiceError::NotFound))?;
+    let text = core::str::from_utf8(&data).map_err(|_| KernelError::InvalidParam)?;
+    Ok(manifest::parse(text))
+}
+
+/// 起動する順（依存先が先）に並べる
+///
+/// 未定義のサービスに依存するものは`Unregistered`、依存関係が循環するものは
+/// `Conflict`として順序から除く
+fn start_order(specs: &[ServiceSpec]) -> (Vec<usize>, Vec<(usize, ServiceError)>) {
+    let index_of = |name: &str| specs.iter().position(|s| s.name == name);
+
+    let mut rejected = Vec::new();
+    let mut done = alloc::vec![false; specs.len()];
+    for (i, spec) in specs.iter().enumerate() {
+        if spec.after.iter().any(|dep| index_of(dep).is_none()) {
+            rejected.push((i, ServiceError::Unregistered));
+            done[i] = true;
+        }
+    }
+
+    // 除いたサービスへの依存は起動時に失敗させるため、ここでは満たされたものとして扱う
+    let mut order = Vec::new();
+    loop {
+        let next = (0..specs.len()).find(|&i| {
+            !done[i] && specs[i].after.iter().filter_map(|dep| index_of(dep)).all(|j| done[j])
+        });
+        match next {
+            Some(i) => {
+                done[i] = true;
+                order.push(i);
+            }
+            None => break,
+        }
+    }
+
+    for (i, _) in done.iter().enumerate().filter(|(_, done)| !**done) {
+        rejected.push((i, ServiceError::Conflict));
+    }
+    (order, rejected)
+}
+
+/// すべてのサービスを依存関係の順に起動する
+fn start_all(manager: ProcessId, specs: Vec<ServiceSpec>) {
+    let (order, rejected) = start_order(&specs);
+    {
+        let mut services = SERVICES.lock();
+        services.extend(specs.into_iter().map(|spec| Service {
+            spec,
+            state: ServiceState::Pending,
+            restarts: 0,
+            started_at: 0,
+            restart_at: None,
+        }));
+        for (i, e) in rejected {
+            crate::warn!("service '{}' has invalid dependencies ({:?})", services[i].spec.name, e);
+            services[i].state = ServiceState::Failed(e);
+        }
+    }
+
+    for i in order {
+        if !dependencies_ready(i) {
+            let mut services = SERVICES.lock();
+            crate::warn!(
+                "service '{}' not started: a dependency is not running ({:?})",
+                services[i].spec.name,
+                ServiceError::StartFailure
+            );
+            services[i].state = ServiceState::Failed(ServiceError::StartFailure);
+            continue;
+        }
+        launch(manager, i);
+    }
+}
+
+/// 依存先がすべて実行中（または正常に終了済み）か
+fn dependencies_ready(index: usize) -> bool {
+    let services = SERVICES.lock();
+    services[index].spec.after.iter().all(|dep| {
+        services
+            .iter()
+            .find(|s| s.spec.name == *dep)
+            .is_some_and(|s| matches!(s.state, ServiceState::Running(_) | ServiceState::Exited(0)))
+    })
+}
+
+/// サービスをマネージャの子プロセスとして起動する
+fn launch(manager: ProcessId, index: usize) {
+    let (name, path, privilege) = {
+        let services = SERVICES.lock();
+        let spec = &services[index].spec;
+        (spec.name.clone(), spec.path.clone(), spec.privilege)
+    };
+
+    let process_name = format!("core.service.{}", name);
+    let result = task::spawn_process(&path, &process_name, &[path.as_str()], &[], privilege, Some(manager));
+
+    let mut services = SERVICES.lock();
+    let service = &mut services[index];
+    match result {
+        Ok(pid) => {
+            crate::info!("service '{}' started (pid {})", name, pid.as_u64());
+            service.state = ServiceState::Running(pid);
+            service.started_at = get_ticks();
+        }
+        Err(e) => {
+            crate::warn!("service '{}' failed to start: {:?} ({:?})", name, e, ServiceError::StartFailure);
+            service.state = ServiceState::Failed(ServiceError::StartFailure);
+        }
+    }
+}
+
+/// 子プロセス（サービス）の終了を待ち、再起動の条件に従って起動し直す
+///
+/// 再起動を待つサービスがある場合は、最も早い再起動の時刻を期限として待つ
+fn supervise(manager: ProcessId) {
+    loop {
+        restart_due(manager);
+        let deadline = next_restart();
+
+        // 待ち合わせへの登録からブロックまでの間に子の終了が割り込まないようにする
+        let exited = x86_64::instructions::interrupts::without_interrupts(|| {
+            task::wait_child_until(manager, None, deadline)
+        });
+        match exited {
+            Ok(Some((pid, code))) => on_exit(pid, code),
+            Ok(None) => {}
+            // 実行中のサービスがなくても再起動を待つものがあればその時刻まで待つ
+            Err(_) => match deadline {
+                Some(deadline) => task::sleep_until(deadline),
+                None => {
+                    crate::info!("service manager: no services running");
+                    return;
+                }
+            },
+        }
+    }
+}
+
+/// 最も早い再起動の時刻
+fn next_restart() -> Option<u64> {
+    SERVICES.lock().iter().filter_map(|s| s.restart_at).min()
+}
+
+/// 再起動の時刻を過ぎたサービスを起動する
+fn restart_due(manager: ProcessId) {
+    let now = get_ticks();
+    let due: Vec<usize> = {
+        let mut services = SERVICES.lock();
+        services
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, s)| s.restart_at.is_some_and(|at| at <= now))
+            .map(|(i, s)| {
+                s.restart_at = None;
+                i
+            })
+            .collect()
+    };
+    for index in due {
+        launch(manager, index);
+    }
+}
+
+/// サービスの終了を記録し、必要なら再起動を予約する
+///
+/// 再起動までの待ち時間は続けて再起動した回数に比例させる
+fn on_exit(pid: ProcessId, code: u64) {
+    let mut services = SERVICES.lock();
+    let service = match services.iter_mut().find(|s| s.state == ServiceState::Running(pid)) {
+        Some(service) => service,
+        None => return,
+    };
+    service.state = ServiceState::Exited(code);
+
+    let wanted = match service.spec.restart {
+        RestartPolicy::Never => false,
+        RestartPolicy::OnFailure => code != 0,
+        RestartPolicy::Always => true,
+    };
+    if !wanted {
+        crate::info!("service '{}' exited with code {:#x}", service.spec.name, code);
+        return;
+    }
+
+    let now = get_ticks();
+    if now.saturating_sub(service.started_at) >= STABLE_TICKS {
+        service.restarts = 0;
+    }
+    if service.restarts >= MAX_RESTARTS {
+        // 起動はできるがすぐに終了し続ける（クラッシュループ）
+        crate::warn!(
+            "service '{}' keeps exiting (code {:#x}); giving up ({:?})",
+            service.spec.name,
+            code,
+            ServiceError::StartFailure
+        );
+        service.state = ServiceState::Failed(ServiceError::StartFailure);
+        return;
+    }
+
+    service.restarts += 1;
+    service.restart_at = Some(now + RESTART_DELAY_TICKS * service.restarts as u64);
+    crate::info!(
+        "service '{}' exited with code {:#x}; restarting ({}/{})",
+        service.spec.name,
+        code,
+        service.restarts,
+        MAX_RESTARTS
+    );
+}
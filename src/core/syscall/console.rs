@@ -1,22 +1,22 @@
-use crate::{syscall::EINVAL, util};
+use crate::error::{KernelError, Result};
+use crate::util;
+
+use super::user_ptr::UserSlice;
 
 /// コンソール書き込み (buf_ptr, len)
-pub fn write(buf_ptr: u64, len: u64) -> u64 {
+pub fn write(buf_ptr: u64, len: u64) -> Result<u64> {
     if buf_ptr == 0 {
-        return EINVAL;
+        return Err(KernelError::InvalidParam);
     }
     let len = len as usize;
     if len == 0 {
-        return 0;
+        return Ok(0);
     }
 
-    let bytes = unsafe { core::slice::from_raw_parts(buf_ptr as *const u8, len) };
-    let text = match core::str::from_utf8(bytes) {
-        Ok(s) => s,
-        Err(_) => return EINVAL,
-    };
+    let bytes = UserSlice::new(buf_ptr, len).read_to_vec()?;
+    let text = core::str::from_utf8(&bytes).map_err(|_| KernelError::InvalidParam)?;
 
     util::console::print(format_args!("{}", text));
     util::vga::print(format_args!("{}", text));
-    len as u64
+    Ok(len as u64)
 }
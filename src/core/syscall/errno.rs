@@ -0,0 +1,123 @@
+//! カーネルエラーからシステムコールのエラーコードへの変換
+//!
+//! システムコールの処理は`crate::Result<u64>`を返し、ディスパッチで
+//! `Errno`に変換してRAXに書き込む。どのエラーがどのコードになるかはここでのみ決める。
+//! 種類の追加を見落とさないよう、ワイルドカードを使わずにすべて列挙する。
+
+use crate::error::{DeviceError, FileSystemError, IpcError, KernelError, MemoryError, ProcessError, ServiceError};
+
+use super::Errno;
+
+impl From<KernelError> for Errno {
+	fn from(error: KernelError) -> Self {
+		match error {
+			KernelError::Memory(e) => e.into(),
+			KernelError::Process(e) => e.into(),
+			KernelError::Fs(e) => e.into(),
+			KernelError::Device(e) => e.into(),
+			KernelError::Ipc(e) => e.into(),
+			KernelError::InvalidParam => Errno::Inval,
+			KernelError::NotImplemented => Errno::NoSys,
+			KernelError::UnknownError => Errno::Io,
+		}
+	}
+}
+
+impl From<MemoryError> for Errno {
+	fn from(error: MemoryError) -> Self {
+		match error {
+			MemoryError::OutOfMemory => Errno::NoMem,
+			// ユーザー空間のアドレスが範囲外、またはアクセス権がない
+			MemoryError::InvalidAddress | MemoryError::PermissionDenied => Errno::Fault,
+			MemoryError::AlreadyMapped => Errno::Exist,
+			// Linuxと同様、マップされていない範囲への操作はENOMEM
+			MemoryError::NotMapped => Errno::NoMem,
+			MemoryError::AlignmentError => Errno::Inval,
+			MemoryError::UnknownError => Errno::Io,
+		}
+	}
+}
+
+impl From<ProcessError> for Errno {
+	fn from(error: ProcessError) -> Self {
+		match error {
+			ProcessError::InvalidPid => Errno::Inval,
+			ProcessError::ProcessNotFound | ProcessError::ZombieProcess => Errno::Srch,
+			ProcessError::MaxProcessesReached => Errno::Again,
+			ProcessError::InsufficientPrivilege | ProcessError::RogueProcessDetected => Errno::Perm,
+			ProcessError::IpcError => Errno::Io,
+			ProcessError::Timeout => Errno::TimedOut,
+			ProcessError::NoChild => Errno::Child,
+			ProcessError::Service(e) => e.into(),
+			ProcessError::UnknownError => Errno::Io,
+		}
+	}
+}
+
+impl From<ServiceError> for Errno {
+	fn from(error: ServiceError) -> Self {
+		match error {
+			ServiceError::NotFound | ServiceError::Unregistered => Errno::NoEnt,
+			ServiceError::StartFailure | ServiceError::StopFailure => Errno::Io,
+			ServiceError::NoResponse => Errno::TimedOut,
+			ServiceError::InsufficientPrivilege => Errno::Perm,
+			ServiceError::InvalidState => Errno::Inval,
+			ServiceError::Conflict => Errno::Exist,
+			ServiceError::UnknownError => Errno::Io,
+		}
+	}
+}
+
+impl From<FileSystemError> for Errno {
+	fn from(error: FileSystemError) -> Self {
+		match error {
+			FileSystemError::NotFound => Errno::NoEnt,
+			FileSystemError::NotADirectory => Errno::NotDir,
+			FileSystemError::IsADirectory => Errno::IsDir,
+			FileSystemError::ReadOnly => Errno::RoFs,
+			FileSystemError::BadDescriptor => Errno::BadF,
+			FileSystemError::TooManyOpenFiles => Errno::MFile,
+			FileSystemError::NameTooLong => Errno::NameTooLong,
+			FileSystemError::NotSeekable => Errno::SPipe,
+			FileSystemError::NotMappable => Errno::NoDev,
+			FileSystemError::NotATerminal => Errno::NoTty,
+			FileSystemError::Corrupted => Errno::Io,
+			FileSystemError::UnknownError => Errno::Io,
+		}
+	}
+}
+
+impl From<DeviceError> for Errno {
+	fn from(error: DeviceError) -> Self {
+		match error {
+			DeviceError::Busy => Errno::Busy,
+			DeviceError::HardwareFailure | DeviceError::CommunicationLost => Errno::Io,
+			DeviceError::Timeout => Errno::TimedOut,
+			DeviceError::InvalidOperation => Errno::Inval,
+			DeviceError::DeviceNotFound | DeviceError::DriverLoadFailure | DeviceError::Disconnected => Errno::NoDev,
+			DeviceError::Unsupported => Errno::NotSup,
+			DeviceError::ResourceUnavailable => Errno::Again,
+			DeviceError::NoData => Errno::NoData,
+			DeviceError::UnknownError => Errno::Io,
+		}
+	}
+}
+
+impl From<IpcError> for Errno {
+	fn from(error: IpcError) -> Self {
+		match error {
+			IpcError::BufferFull | IpcError::Empty => Errno::Again,
+			IpcError::Timeout => Errno::TimedOut,
+			IpcError::InvalidTarget => Errno::Srch,
+			IpcError::UnknownError => Errno::Io,
+		}
+	}
+}
+
+/// 処理の結果を戻り値（RAX）に変換
+pub fn to_return_value(result: crate::Result<u64>) -> u64 {
+	match result {
+		Ok(value) => value,
+		Err(e) => Errno::from(e).code(),
+	}
+}
@@ -1,39 +1,36 @@
+use crate::error::{FileSystemError, KernelError, Result};
 use crate::init;
-use crate::syscall::{EINVAL, ENOENT};
+
+use super::user_ptr::UserSlice;
 
 const MAX_PATH_LEN: usize = 256;
 
 /// initfs 読み込み (path_ptr, path_len, buf_ptr, buf_len)
-pub fn read(path_ptr: u64, path_len: u64, buf_ptr: u64, buf_len: u64) -> u64 {
+pub fn read(path_ptr: u64, path_len: u64, buf_ptr: u64, buf_len: u64) -> Result<u64> {
     if path_ptr == 0 || buf_ptr == 0 {
-        return EINVAL;
+        return Err(KernelError::InvalidParam);
     }
 
     let path_len = path_len as usize;
     let buf_len = buf_len as usize;
 
-    if path_len == 0 || path_len > MAX_PATH_LEN {
-        return EINVAL;
+    if path_len == 0 {
+        return Err(KernelError::InvalidParam);
+    }
+    if path_len > MAX_PATH_LEN {
+        return Err(KernelError::Fs(FileSystemError::NameTooLong));
     }
 
-    let path_bytes = unsafe { core::slice::from_raw_parts(path_ptr as *const u8, path_len) };
-    let path = match core::str::from_utf8(path_bytes) {
-        Ok(s) => s,
-        Err(_) => return EINVAL,
-    };
+    let path_bytes = UserSlice::new(path_ptr, path_len).read_to_vec()?;
+    let path = core::str::from_utf8(&path_bytes).map_err(|_| KernelError::InvalidParam)?;
 
-    let data = match init::fs::read(path) {
-        Some(d) => d,
-        None => return ENOENT,
-    };
+    let data = init::fs::read(path)?;
 
     if data.len() > buf_len {
-        return EINVAL;
+        return Err(KernelError::InvalidParam);
     }
 
-    unsafe {
-        core::ptr::copy_nonoverlapping(data.as_ptr(), buf_ptr as *mut u8, data.len());
-    }
+    UserSlice::new(buf_ptr, buf_len).write(&data)?;
 
-    data.len() as u64
+    Ok(data.len() as u64)
 }
@@ -1,116 +1,215 @@
+use alloc::collections::{BTreeMap, VecDeque};
+
+use crate::error::{IpcError, KernelError, MemoryError, Result};
 use crate::interrupt::spinlock::SpinLock;
+use crate::task::ThreadId;
 
-use super::{EAGAIN, EINVAL};
+use super::user_ptr::UserPtr;
 
-const MAX_THREADS: usize = crate::task::ThreadQueue::MAX_THREADS;
 const MAILBOX_CAP: usize = 64;
 
+pub use swiftcore_abi::ipc::{IPC_NONBLOCK, IPC_WAIT_FOREVER};
+
 #[derive(Debug, Clone, Copy)]
 struct Message {
 	from: u64,
 	value: u64,
 }
 
-impl Message {
-	const fn empty() -> Self {
-		Self { from: 0, value: 0 }
-	}
-}
-
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Default)]
 struct Mailbox {
-	head: usize,
-	tail: usize,
-	count: usize,
-	buf: [Message; MAILBOX_CAP],
+	queue: VecDeque<Message>,
+	/// メッセージ到着を待つスレッド
+	receivers: VecDeque<ThreadId>,
+	/// 空きを待つ送信スレッド
+	senders: VecDeque<ThreadId>,
 }
 
 impl Mailbox {
-	const fn new() -> Self {
-		Self {
-			head: 0,
-			tail: 0,
-			count: 0,
-			buf: [Message::empty(); MAILBOX_CAP],
-		}
-	}
-
-	fn push(&mut self, msg: Message) -> Result<(), ()> {
-		if self.count >= MAILBOX_CAP {
+	fn push(&mut self, msg: Message) -> core::result::Result<(), ()> {
+		if self.queue.len() >= MAILBOX_CAP {
 			return Err(());
 		}
-		self.buf[self.tail] = msg;
-		self.tail = (self.tail + 1) % MAILBOX_CAP;
-		self.count += 1;
+		self.queue.try_reserve(1).map_err(|_| ())?;
+		self.queue.push_back(msg);
 		Ok(())
 	}
 
 	fn pop(&mut self) -> Option<Message> {
-		if self.count == 0 {
-			return None;
-		}
-		let msg = self.buf[self.head];
-		self.head = (self.head + 1) % MAILBOX_CAP;
-		self.count -= 1;
-		Some(msg)
+		self.queue.pop_front()
 	}
 }
 
-static MAILBOXES: SpinLock<[Mailbox; MAX_THREADS]> = SpinLock::new([Mailbox::new(); MAX_THREADS]);
+/// 待ち行列に登録（登録できない場合はfalse）
+fn add_waiter(waiters: &mut VecDeque<ThreadId>, id: ThreadId) -> bool {
+	if waiters.contains(&id) {
+		return true;
+	}
+	if waiters.try_reserve(1).is_err() {
+		return false;
+	}
+	waiters.push_back(id);
+	true
+}
 
-/// IPC送信
-pub fn send(dest_thread_id: u64, value: u64) -> u64 {
-	if dest_thread_id == 0 {
-		return EINVAL;
+/// 待ち行列から取り除く
+fn remove_waiter(waiters: &mut VecDeque<ThreadId>, id: ThreadId) {
+	waiters.retain(|&w| w != id);
+}
+
+/// タイムアウト（ティック数）を期限に変換
+///
+/// `IPC_NONBLOCK`は待たない（None）、`IPC_WAIT_FOREVER`は期限なし（Some(None)）
+fn deadline_of(timeout: u64) -> Option<Option<u64>> {
+	match timeout {
+		IPC_NONBLOCK => None,
+		IPC_WAIT_FOREVER => Some(None),
+		ticks => Some(Some(crate::interrupt::timer::get_ticks().saturating_add(ticks))),
 	}
+}
 
-	let sender = match crate::task::current_thread_id() {
-		Some(id) => id.as_u64(),
-		None => return EINVAL,
-	};
+/// 期限を過ぎたかどうか
+fn expired(deadline: Option<u64>) -> bool {
+	deadline.is_some_and(|d| crate::interrupt::timer::get_ticks() >= d)
+}
 
-	let idx = dest_thread_id.saturating_sub(1) as usize;
-	if idx >= MAX_THREADS {
-		return EINVAL;
+/// スレッドIDごとのメールボックス（初回送信時に作成）
+static MAILBOXES: SpinLock<BTreeMap<u64, Mailbox>> = SpinLock::new(BTreeMap::new());
+
+/// スレッドのメールボックスを破棄する（スレッドの削除時に呼ぶ）
+///
+/// 未受信のメッセージは捨て、空きを待っていた送信側を起床させる。
+/// 起床した送信側は宛先がないことを確認して`IpcError::InvalidTarget`を返す
+pub fn release_mailbox(thread_id: ThreadId) {
+	let mailbox = {
+		let mut boxes = MAILBOXES.lock();
+		// 他のメールボックスの待ち行列に残った登録も消し、起床が空振りしないようにする
+		for other in boxes.values_mut() {
+			remove_waiter(&mut other.receivers, thread_id);
+			remove_waiter(&mut other.senders, thread_id);
+		}
+		boxes.remove(&thread_id.as_u64())
+	};
+	if let Some(mailbox) = mailbox {
+		for id in mailbox.senders {
+			crate::task::wake_thread(id);
+		}
 	}
+}
 
-	let mut boxes = MAILBOXES.lock();
-	if boxes[idx]
-		.push(Message {
-			from: sender,
-			value,
-		})
-		.is_err()
-	{
-		return EAGAIN;
+/// IPC送信
+///
+/// メールボックスが満杯の場合、`timeout`ティックまで空きを待つ。
+/// `IPC_NONBLOCK`なら即座に`BufferFull`、期限切れなら`Timeout`を返す
+pub fn send(dest_thread_id: u64, value: u64, timeout: u64) -> Result<u64> {
+	if dest_thread_id == 0 {
+		return Err(KernelError::InvalidParam);
 	}
 
-	0
-}
+	let sender = crate::task::current_thread_id().ok_or(KernelError::InvalidParam)?;
 
-/// IPC受信
-pub fn recv(sender_ptr: u64) -> u64 {
-	let receiver = match crate::task::current_thread_id() {
-		Some(id) => id.as_u64(),
-		None => return EINVAL,
+	let msg = Message {
+		from: sender.as_u64(),
+		value,
 	};
+	let deadline = deadline_of(timeout);
+
+	loop {
+		// 存在しないスレッド宛てにメールボックスを作らない（待機中に宛先が終了した場合も含む）
+		let mut exists = false;
+		crate::task::for_each_thread(|t| {
+			exists |= t.id().as_u64() == dest_thread_id && t.state() != crate::task::ThreadState::Terminated
+		});
+		if !exists {
+			return Err(KernelError::Ipc(IpcError::InvalidTarget));
+		}
 
-	let idx = receiver.saturating_sub(1) as usize;
-	if idx >= MAX_THREADS {
-		return EINVAL;
+		// システムコール中は割り込み禁止のため、待ち行列への登録からブロックまでの間に
+		// 起床処理が割り込むことはない
+		let woken = {
+			let mut boxes = MAILBOXES.lock();
+			let mailbox = boxes.entry(dest_thread_id).or_default();
+			if mailbox.push(msg).is_ok() {
+				remove_waiter(&mut mailbox.senders, sender);
+				Some(mailbox.receivers.pop_front())
+			} else {
+				let deadline = deadline.ok_or(KernelError::Ipc(IpcError::BufferFull))?;
+				if expired(deadline) {
+					remove_waiter(&mut mailbox.senders, sender);
+					return Err(KernelError::Ipc(IpcError::Timeout));
+				}
+				if !add_waiter(&mut mailbox.senders, sender) {
+					return Err(KernelError::Memory(MemoryError::OutOfMemory));
+				}
+				None
+			}
+		};
+
+		match woken {
+			Some(receiver) => {
+				if let Some(id) = receiver {
+					crate::task::wake_thread(id);
+				}
+				return Ok(0);
+			}
+			None => crate::task::block_current_until(deadline.flatten()),
+		}
 	}
+}
 
-	let mut boxes = MAILBOXES.lock();
-	let msg = match boxes[idx].pop() {
-		Some(msg) => msg,
-		None => return EAGAIN,
-	};
+/// IPC受信
+///
+/// メッセージがない場合、`timeout`ティックまで到着を待つ。
+/// `IPC_NONBLOCK`なら即座に`Empty`、期限切れなら`Timeout`を返す
+pub fn recv(sender_ptr: u64, timeout: u64) -> Result<u64> {
+	let receiver = crate::task::current_thread_id().ok_or(KernelError::InvalidParam)?;
+
+	// メッセージを取り出す前に書き込み先を検証し、失敗時にメッセージを失わないようにする
+	let sender_ptr = UserPtr::<u64>::new(sender_ptr);
+	if !sender_ptr.is_null() {
+		sender_ptr.check_writable()?;
+	}
 
-	if sender_ptr != 0 {
-		unsafe {
-			(sender_ptr as *mut u64).write_volatile(msg.from);
+	let deadline = deadline_of(timeout);
+
+	let msg = loop {
+		// 登録からブロックまでの扱いは`send`と同じ
+		let received = {
+			let mut boxes = MAILBOXES.lock();
+			let mailbox = boxes.entry(receiver.as_u64()).or_default();
+			match mailbox.pop() {
+				Some(msg) => {
+					remove_waiter(&mut mailbox.receivers, receiver);
+					Some((msg, mailbox.senders.pop_front()))
+				}
+				None => {
+					let deadline = deadline.ok_or(KernelError::Ipc(IpcError::Empty))?;
+					if expired(deadline) {
+						remove_waiter(&mut mailbox.receivers, receiver);
+						return Err(KernelError::Ipc(IpcError::Timeout));
+					}
+					if !add_waiter(&mut mailbox.receivers, receiver) {
+						return Err(KernelError::Memory(MemoryError::OutOfMemory));
+					}
+					None
+				}
+			}
+		};
+
+		match received {
+			Some((msg, sender)) => {
+				if let Some(id) = sender {
+					crate::task::wake_thread(id);
+				}
+				break msg;
+			}
+			None => crate::task::block_current_until(deadline.flatten()),
 		}
+	};
+
+	if !sender_ptr.is_null() {
+		sender_ptr.write(msg.from)?;
 	}
 
-	msg.value
+	Ok(msg.value)
 }
@@ -1,10 +1,10 @@
 use crate::driver::ps2_keyboard;
-use crate::syscall::ENODATA;
+use crate::error::{DeviceError, KernelError, Result};
 
 /// キーボード1文字読み取り
-pub fn read_char() -> u64 {
+pub fn read_char() -> Result<u64> {
     match ps2_keyboard::read_char() {
-        Some(ch) => ch as u64,
-        None => ENODATA,
+        Some(ch) => Ok(ch as u64),
+        None => Err(KernelError::Device(DeviceError::NoData)),
     }
 }
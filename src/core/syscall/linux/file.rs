@@ -0,0 +1,369 @@
+//! ファイルディスクリプタを扱うLinux互換のシステムコール
+//!
+//! 対象は現在のプロセスのファイルディスクリプタ表（`task::FileTable`）。
+//! プロセステーブルのロック中はユーザー空間にアクセスできないため、
+//! 表からは必要なデータを取り出すだけにして、コピーはロックの外で行う。
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::driver::ps2_keyboard;
+use crate::error::{FileSystemError, KernelError, MemoryError, Result};
+use crate::task::{FileKind, FileTable, OpenFile};
+
+use super::super::user_ptr::{self, UserPtr, UserSlice};
+
+/// パスの最大長（NULを含む）
+const PATH_MAX: usize = 4096;
+/// writevで受け付けるバッファの数の上限
+const IOV_MAX: u64 = 1024;
+
+const O_ACCMODE: u64 = 0o3;
+const O_RDONLY: u64 = 0o0;
+const O_CREAT: u64 = 0o100;
+const O_TRUNC: u64 = 0o1000;
+const O_DIRECTORY: u64 = 0o200000;
+
+const SEEK_SET: u64 = 0;
+const SEEK_CUR: u64 = 1;
+const SEEK_END: u64 = 2;
+
+const S_IFCHR: u32 = 0o020000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+/// accessの書き込み権限の確認
+const W_OK: u64 = 2;
+
+/// コンソールとして開けるパス
+const CONSOLE_PATHS: [&str; 2] = ["/dev/console", "/dev/tty"];
+/// コンソールのデバイス番号（/dev/console: 5,1）
+const CONSOLE_RDEV: u64 = (5 << 8) | 1;
+
+/// Linux x86-64の`struct stat`
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+struct Stat {
+	st_dev: u64,
+	st_ino: u64,
+	st_nlink: u64,
+	st_mode: u32,
+	st_uid: u32,
+	st_gid: u32,
+	__pad0: u32,
+	st_rdev: u64,
+	st_size: i64,
+	st_blksize: i64,
+	st_blocks: i64,
+	st_atime: i64,
+	st_atime_nsec: i64,
+	st_mtime: i64,
+	st_mtime_nsec: i64,
+	st_ctime: i64,
+	st_ctime_nsec: i64,
+	__unused: [i64; 3],
+}
+
+/// Linuxの`struct iovec`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct IoVec {
+	base: u64,
+	len: u64,
+}
+
+/// 現在のプロセスのファイルディスクリプタ表に対して操作を実行
+fn with_files<F, R>(f: F) -> Result<R>
+where
+	F: FnOnce(&mut FileTable) -> Result<R>,
+{
+	let pid = crate::task::current_process_id().ok_or(KernelError::InvalidParam)?;
+	crate::task::with_process_mut(pid, |p| f(p.files_mut())).ok_or(KernelError::InvalidParam)?
+}
+
+/// 開いているファイルに対して操作を実行（存在しない番号は`BadDescriptor`）
+fn with_file<F, R>(fd: u64, f: F) -> Result<R>
+where
+	F: FnOnce(&mut OpenFile) -> Result<R>,
+{
+	with_files(|files| files.get_mut(fd as usize).ok_or(fs_error(FileSystemError::BadDescriptor)).and_then(f))
+}
+
+/// ファイルシステムのエラーを作成
+fn fs_error(error: FileSystemError) -> KernelError {
+	KernelError::Fs(error)
+}
+
+/// ファイルの種類（読み書きの方法）
+enum Target {
+	Console,
+	/// 読み取った内容と読み取り後の位置
+	Initfs(Vec<u8>, u64),
+}
+
+/// ユーザー空間のパス（NUL終端）を読み出す
+fn read_path(path_ptr: u64) -> Result<String> {
+	let path = user_ptr::read_c_string(path_ptr, PATH_MAX)?;
+	String::from_utf8(path).map_err(|_| fs_error(FileSystemError::NotFound))
+}
+
+/// open (path, flags)
+///
+/// initfsは読み取り専用のため、書き込みや作成を伴うものは`ReadOnly`
+pub fn open(path_ptr: u64, flags: u64) -> Result<u64> {
+	let path = read_path(path_ptr)?;
+	let path = path.as_str();
+
+	let file = if CONSOLE_PATHS.contains(&path) {
+		OpenFile::console()
+	} else {
+		if flags & O_ACCMODE != O_RDONLY || flags & O_TRUNC != 0 {
+			return Err(fs_error(FileSystemError::ReadOnly));
+		}
+		match crate::init::fs::read(path) {
+			Ok(_) if flags & O_DIRECTORY != 0 => return Err(fs_error(FileSystemError::NotADirectory)),
+			Ok(data) => OpenFile::initfs(data),
+			Err(KernelError::Fs(FileSystemError::NotFound)) if flags & O_CREAT != 0 => {
+				return Err(fs_error(FileSystemError::ReadOnly))
+			}
+			Err(e) => return Err(e),
+		}
+	};
+
+	with_files(|files| {
+		files
+			.insert(file)
+			.map(|fd| fd as u64)
+			.ok_or(fs_error(FileSystemError::TooManyOpenFiles))
+	})
+}
+
+/// close (fd)
+pub fn close(fd: u64) -> Result<u64> {
+	with_files(|files| {
+		files
+			.remove(fd as usize)
+			.map(|_| 0)
+			.ok_or(fs_error(FileSystemError::BadDescriptor))
+	})
+}
+
+/// read (fd, buf, count)
+///
+/// コンソールは1文字以上の入力があるまで待つ
+pub fn read(fd: u64, buf_ptr: u64, count: u64) -> Result<u64> {
+	let count = count as usize;
+	let target = with_file(fd, |file| {
+		Ok(match file.kind() {
+			FileKind::Console => Target::Console,
+			FileKind::Initfs(data) => {
+				let start = (file.offset() as usize).min(data.len());
+				let end = start + count.min(data.len() - start);
+				Target::Initfs(data[start..end].to_vec(), end as u64)
+			}
+		})
+	})?;
+
+	match target {
+		Target::Console => read_console(buf_ptr, count),
+		Target::Initfs(bytes, end) => {
+			UserSlice::new(buf_ptr, bytes.len()).write(&bytes)?;
+			with_file(fd, |file| {
+				file.set_offset(end);
+				Ok(bytes.len() as u64)
+			})
+		}
+	}
+}
+
+/// キーボードから読み取る
+fn read_console(buf_ptr: u64, count: usize) -> Result<u64> {
+	if count == 0 {
+		return Ok(0);
+	}
+	let mut bytes = Vec::new();
+	loop {
+		while bytes.len() < count {
+			match ps2_keyboard::read_char() {
+				Some(ch) => bytes.push(ch),
+				None => break,
+			}
+		}
+		if !bytes.is_empty() {
+			break;
+		}
+		crate::task::sleep_ticks(1);
+	}
+	UserSlice::new(buf_ptr, bytes.len()).write(&bytes)?;
+	Ok(bytes.len() as u64)
+}
+
+/// 書き込み先がコンソールか確認する（initfsのファイルは読み取り専用）
+fn check_writable(fd: u64) -> Result<()> {
+	with_file(fd, |file| match file.kind() {
+		FileKind::Console => Ok(()),
+		FileKind::Initfs(_) => Err(fs_error(FileSystemError::BadDescriptor)),
+	})
+}
+
+/// コンソールへ出力する（UTF-8として不正なバイトは置き換える）
+fn write_console(bytes: &[u8]) {
+	let text = String::from_utf8_lossy(bytes);
+	crate::util::console::print(format_args!("{}", text));
+	crate::util::vga::print(format_args!("{}", text));
+}
+
+/// write (fd, buf, count)
+pub fn write(fd: u64, buf_ptr: u64, count: u64) -> Result<u64> {
+	check_writable(fd)?;
+	if count == 0 {
+		return Ok(0);
+	}
+	let bytes = UserSlice::new(buf_ptr, count as usize).read_to_vec()?;
+	write_console(&bytes);
+	Ok(count)
+}
+
+/// writev (fd, iov, iovcnt)
+pub fn writev(fd: u64, iov_ptr: u64, iov_count: u64) -> Result<u64> {
+	check_writable(fd)?;
+	if iov_count > IOV_MAX {
+		return Err(KernelError::InvalidParam);
+	}
+
+	let mut iovs = Vec::new();
+	iovs.try_reserve_exact(iov_count as usize).map_err(|_| KernelError::Memory(MemoryError::OutOfMemory))?;
+	for i in 0..iov_count {
+		let iov = UserPtr::<IoVec>::new(iov_ptr + i * core::mem::size_of::<IoVec>() as u64).read()?;
+		iovs.push(iov);
+	}
+
+	let mut written = 0u64;
+	for iov in iovs.iter().filter(|iov| iov.len != 0) {
+		let bytes = UserSlice::new(iov.base, iov.len as usize).read_to_vec()?;
+		write_console(&bytes);
+		written += iov.len;
+	}
+	Ok(written)
+}
+
+/// コンソールの情報
+fn console_stat() -> Stat {
+	Stat {
+		st_nlink: 1,
+		st_mode: S_IFCHR | 0o620,
+		st_rdev: CONSOLE_RDEV,
+		st_blksize: 1024,
+		..Stat::default()
+	}
+}
+
+/// initfsのファイルの情報
+fn file_stat(size: u64) -> Stat {
+	Stat {
+		st_nlink: 1,
+		st_mode: S_IFREG | 0o444,
+		st_size: size as i64,
+		st_blksize: 4096,
+		st_blocks: size.div_ceil(512) as i64,
+		..Stat::default()
+	}
+}
+
+/// ioctl (fd, request, arg)
+///
+/// 端末の操作（`TIOCGWINSZ`など）には対応しないため、開いているファイルにはすべて`NotATerminal`
+pub fn ioctl(fd: u64, _request: u64) -> Result<u64> {
+	with_file(fd, |_| Err(fs_error(FileSystemError::NotATerminal)))
+}
+
+/// fstat (fd, statbuf)
+pub fn fstat(fd: u64, stat_ptr: u64) -> Result<u64> {
+	let stat = with_file(fd, |file| {
+		Ok(match file.kind() {
+			FileKind::Console => console_stat(),
+			FileKind::Initfs(data) => file_stat(data.len() as u64),
+		})
+	})?;
+	UserPtr::<Stat>::new(stat_ptr).write(stat)?;
+	Ok(0)
+}
+
+/// stat (path, statbuf)
+///
+/// initfsにシンボリックリンクはないため、lstatも同じ処理になる
+pub fn stat(path_ptr: u64, stat_ptr: u64) -> Result<u64> {
+	let path = read_path(path_ptr)?;
+	let stat = if CONSOLE_PATHS.contains(&path.as_str()) {
+		console_stat()
+	} else {
+		let metadata = crate::init::fs::metadata(&path)?;
+		let stat = if metadata.is_dir {
+			Stat {
+				st_nlink: 2,
+				st_mode: S_IFDIR | 0o555,
+				st_size: metadata.size as i64,
+				st_blksize: 4096,
+				st_blocks: metadata.size.div_ceil(512) as i64,
+				..Stat::default()
+			}
+		} else {
+			file_stat(metadata.size)
+		};
+		Stat {
+			st_ino: metadata.inode as u64,
+			..stat
+		}
+	};
+	UserPtr::<Stat>::new(stat_ptr).write(stat)?;
+	Ok(0)
+}
+
+/// access (path, mode)
+///
+/// initfsは読み取り専用のため、書き込みの確認は`ReadOnly`
+pub fn access(path_ptr: u64, mode: u64) -> Result<u64> {
+	let path = read_path(path_ptr)?;
+	if CONSOLE_PATHS.contains(&path.as_str()) {
+		return Ok(0);
+	}
+	crate::init::fs::metadata(&path)?;
+	if mode & W_OK != 0 {
+		return Err(fs_error(FileSystemError::ReadOnly));
+	}
+	Ok(0)
+}
+
+/// lseek (fd, offset, whence)
+pub fn lseek(fd: u64, offset: i64, whence: u64) -> Result<u64> {
+	with_file(fd, |file| {
+		let size = match file.kind() {
+			FileKind::Console => return Err(fs_error(FileSystemError::NotSeekable)),
+			FileKind::Initfs(data) => data.len() as u64,
+		};
+		let base = match whence {
+			SEEK_SET => 0,
+			SEEK_CUR => file.offset(),
+			SEEK_END => size,
+			_ => return Err(KernelError::InvalidParam),
+		};
+		let new = base
+			.checked_add_signed(offset)
+			.filter(|&o| o <= i64::MAX as u64)
+			.ok_or(KernelError::InvalidParam)?;
+		file.set_offset(new);
+		Ok(new)
+	})
+}
+
+/// mmapでマップするファイルの内容（`offset`から最大`len`バイト）
+pub fn contents(fd: u64, offset: u64, len: u64) -> Result<Vec<u8>> {
+	with_file(fd, |file| match file.kind() {
+		FileKind::Console => Err(fs_error(FileSystemError::NotMappable)),
+		FileKind::Initfs(data) => {
+			let start = (offset as usize).min(data.len());
+			let end = start + (len as usize).min(data.len() - start);
+			Ok(data[start..end].to_vec())
+		}
+	})
+}
@@ -0,0 +1,308 @@
+//! Linux x86-64互換のシステムコール
+//!
+//! ネイティブの番号（`NATIVE_SYSCALL_BASE`以降）に当たらない番号はLinuxの番号として扱う。
+//! 静的リンクされたmuslのプログラムを動かすのに必要な範囲を実装する。
+//! 戻り値はLinuxと同じく、エラーをエラー番号の符号を反転した値で返す。
+
+pub mod file;
+
+use x86_64::registers::model_specific::FsBase;
+use x86_64::VirtAddr;
+
+use crate::error::{KernelError, ProcessError, Result};
+use crate::mem::address_space::USER_SPACE_END;
+
+use super::user_ptr::{UserPtr, UserSlice};
+use super::{memory, time, Errno};
+
+/// READ（読み取ったバイト数）
+pub const SYS_READ: u64 = 0;
+/// WRITE（書き込んだバイト数）
+pub const SYS_WRITE: u64 = 1;
+/// OPEN（ファイルディスクリプタ）
+pub const SYS_OPEN: u64 = 2;
+/// CLOSE（クローズする）
+pub const SYS_CLOSE: u64 = 3;
+/// STAT（ファイル情報を取得する）
+pub const SYS_STAT: u64 = 4;
+/// FSTAT（ファイル情報を取得する）
+pub const SYS_FSTAT: u64 = 5;
+/// LSTAT（シンボリックリンクの情報を取得する）
+pub const SYS_LSTAT: u64 = 6;
+/// LSEEK（読み書きする位置を変更する）
+pub const SYS_LSEEK: u64 = 8;
+/// MMAP（メモリマップドファイルをマップする）
+pub const SYS_MMAP: u64 = 9;
+/// MPROTECT（メモリの保護属性を変更する）
+pub const SYS_MPROTECT: u64 = 10;
+/// MUNMAP（メモリのマップを解除する）
+pub const SYS_MUNMAP: u64 = 11;
+/// BRK（ヒープ領域の終端を設定する）
+pub const SYS_BRK: u64 = 12;
+/// RT_SIGACTION（シグナルの動作を設定する）
+pub const SYS_RT_SIGACTION: u64 = 13;
+/// RT_SIGPROCMASK（シグナルマスクを設定する）
+pub const SYS_RT_SIGPROCMASK: u64 = 14;
+/// IOCTL（デバイス固有の操作）
+pub const SYS_IOCTL: u64 = 16;
+/// WRITEV（複数のバッファを書き込む）
+pub const SYS_WRITEV: u64 = 20;
+/// NANOSLEEP（指定時間スリープする）
+pub const SYS_NANOSLEEP: u64 = 35;
+/// ACCESS（ファイルアクセス権を確認する）
+pub const SYS_ACCESS: u64 = 21;
+/// EXIT（スレッドを終了する）
+pub const SYS_EXIT: u64 = 60;
+/// GETPID（プロセスIDを取得する）
+pub const SYS_GETPID: u64 = 39;
+/// UNAME（システムの名前を取得する）
+pub const SYS_UNAME: u64 = 63;
+/// GETUID（ユーザーIDを取得する）
+pub const SYS_GETUID: u64 = 102;
+/// GETGID（グループIDを取得する）
+pub const SYS_GETGID: u64 = 104;
+/// GETEUID（実効ユーザーIDを取得する）
+pub const SYS_GETEUID: u64 = 107;
+/// GETEGID（実効グループIDを取得する）
+pub const SYS_GETEGID: u64 = 108;
+/// ARCH_PRCTL（FSベースを設定/取得する）
+pub const SYS_ARCH_PRCTL: u64 = 158;
+/// GETTID（スレッドIDを取得する）
+pub const SYS_GETTID: u64 = 186;
+/// SET_TID_ADDRESS（スレッドIDを返す）
+pub const SYS_SET_TID_ADDRESS: u64 = 218;
+/// CLOCK_GETTIME（時刻を取得する）
+pub const SYS_CLOCK_GETTIME: u64 = 228;
+/// EXIT_GROUP（プロセスを終了する）
+pub const SYS_EXIT_GROUP: u64 = 231;
+
+/// Linuxのエラー番号
+pub mod errno {
+	/// 操作が許可されていない
+	pub const EPERM: u64 = 1;
+	/// ファイルが見つからない
+	pub const ENOENT: u64 = 2;
+	/// プロセスが見つからない
+	pub const ESRCH: u64 = 3;
+	/// 入出力エラー
+	pub const EIO: u64 = 5;
+	/// 不正なファイルディスクリプタ
+	pub const EBADF: u64 = 9;
+	/// 子プロセスがない
+	pub const ECHILD: u64 = 10;
+	/// 再試行が必要
+	pub const EAGAIN: u64 = 11;
+	/// メモリ不足
+	pub const ENOMEM: u64 = 12;
+	/// 不正なアドレス
+	pub const EFAULT: u64 = 14;
+	/// 使用中
+	pub const EBUSY: u64 = 16;
+	/// すでに存在する
+	pub const EEXIST: u64 = 17;
+	/// マップできないデバイス
+	pub const ENODEV: u64 = 19;
+	/// ディレクトリではない
+	pub const ENOTDIR: u64 = 20;
+	/// ディレクトリである
+	pub const EISDIR: u64 = 21;
+	/// 無効な引数
+	pub const EINVAL: u64 = 22;
+	/// 開いているファイルが多すぎる
+	pub const EMFILE: u64 = 24;
+	/// 端末ではない
+	pub const ENOTTY: u64 = 25;
+	/// シークできない
+	pub const ESPIPE: u64 = 29;
+	/// 読み取り専用のファイルシステム
+	pub const EROFS: u64 = 30;
+	/// パスが長すぎる
+	pub const ENAMETOOLONG: u64 = 36;
+	/// 未実装
+	pub const ENOSYS: u64 = 38;
+	/// データがない
+	pub const ENODATA: u64 = 61;
+	/// 対応していない操作
+	pub const EOPNOTSUPP: u64 = 95;
+	/// 時間切れ
+	pub const ETIMEDOUT: u64 = 110;
+}
+
+use errno::*;
+
+/// エラーの種類をLinuxのエラー番号に変換
+///
+/// ネイティブと同じ`Errno`を経由するため、カーネルのエラーとの対応は`syscall::errno`で決まる
+fn linux_errno(errno: Errno) -> u64 {
+	match errno {
+		Errno::NoSys => ENOSYS,
+		Errno::Inval => EINVAL,
+		Errno::Again => EAGAIN,
+		Errno::NoEnt => ENOENT,
+		Errno::NoData => ENODATA,
+		Errno::NoMem => ENOMEM,
+		Errno::Fault => EFAULT,
+		Errno::TimedOut => ETIMEDOUT,
+		Errno::Perm => EPERM,
+		Errno::Child => ECHILD,
+		Errno::BadF => EBADF,
+		Errno::NotDir => ENOTDIR,
+		Errno::IsDir => EISDIR,
+		Errno::NameTooLong => ENAMETOOLONG,
+		Errno::RoFs => EROFS,
+		Errno::MFile => EMFILE,
+		Errno::SPipe => ESPIPE,
+		Errno::Srch => ESRCH,
+		Errno::Busy => EBUSY,
+		Errno::Io => EIO,
+		Errno::NoDev => ENODEV,
+		Errno::Exist => EEXIST,
+		Errno::NotSup => EOPNOTSUPP,
+		Errno::NoTty => ENOTTY,
+	}
+}
+
+/// Linuxのシステムコールのディスパッチ
+pub fn dispatch(num: u64, arg0: u64, arg1: u64, arg2: u64, arg3: u64, arg4: u64, arg5: u64) -> u64 {
+	let result = match num {
+		SYS_READ => file::read(arg0, arg1, arg2),
+		SYS_WRITE => file::write(arg0, arg1, arg2),
+		SYS_OPEN => file::open(arg0, arg1),
+		SYS_CLOSE => file::close(arg0),
+		SYS_STAT | SYS_LSTAT => file::stat(arg0, arg1),
+		SYS_FSTAT => file::fstat(arg0, arg1),
+		SYS_LSEEK => file::lseek(arg0, arg1 as i64, arg2),
+		SYS_MMAP => mmap(arg0, arg1, arg2, arg3, arg4, arg5),
+		SYS_MPROTECT => memory::mprotect(arg0, arg1, arg2),
+		SYS_MUNMAP => memory::munmap(arg0, arg1),
+		// 失敗時も現在のブレークを返す（エラー番号は返さない）
+		SYS_BRK => memory::brk(arg0),
+		SYS_RT_SIGACTION => rt_sigaction(arg2),
+		SYS_RT_SIGPROCMASK => rt_sigprocmask(arg2),
+		SYS_IOCTL => file::ioctl(arg0, arg1),
+		SYS_WRITEV => file::writev(arg0, arg1, arg2),
+		SYS_ACCESS => file::access(arg0, arg1),
+		SYS_NANOSLEEP => time::nanosleep(arg0, arg1),
+		SYS_GETPID => getpid(),
+		SYS_UNAME => uname(arg0),
+		// ユーザーとグループを持たないため、すべてroot（0）として扱う
+		SYS_GETUID | SYS_GETGID | SYS_GETEUID | SYS_GETEGID => Ok(0),
+		SYS_EXIT => exit(arg0),
+		SYS_ARCH_PRCTL => arch_prctl(arg0, arg1),
+		SYS_GETTID => gettid(),
+		SYS_SET_TID_ADDRESS => gettid(),
+		SYS_CLOCK_GETTIME => time::clock_gettime(arg0, arg1),
+		SYS_EXIT_GROUP => exit_group(arg0),
+		_ => Err(KernelError::NotImplemented),
+	};
+	result.unwrap_or_else(|e| linux_errno(e.into()).wrapping_neg())
+}
+
+/// mmap (addr, len, prot, flags, fd, offset)
+///
+/// ファイルのマッピングはプライベートのみ対応し、内容をコピーして作る
+fn mmap(addr: u64, len: u64, prot: u64, flags: u64, fd: u64, offset: u64) -> Result<u64> {
+	if flags & memory::MAP_ANONYMOUS != 0 {
+		return memory::mmap(addr, len, prot, flags);
+	}
+	if !offset.is_multiple_of(4096) {
+		return Err(KernelError::InvalidParam);
+	}
+	let contents = file::contents(fd, offset, len)?;
+	memory::mmap_copy(addr, len, prot, flags, &contents)
+}
+
+/// arch_prctl (code, addr)
+///
+/// FSベースの設定と取得のみ対応する
+fn arch_prctl(code: u64, addr: u64) -> Result<u64> {
+	const ARCH_SET_FS: u64 = 0x1002;
+	const ARCH_GET_FS: u64 = 0x1003;
+
+	let id = crate::task::current_thread_id().ok_or(KernelError::InvalidParam)?;
+	match code {
+		ARCH_SET_FS => {
+			if addr >= USER_SPACE_END {
+				return Err(KernelError::Process(ProcessError::InsufficientPrivilege));
+			}
+			crate::task::with_thread_mut(id, |t| t.set_fs_base(addr)).ok_or(KernelError::InvalidParam)?;
+			FsBase::write(VirtAddr::new(addr));
+			Ok(0)
+		}
+		ARCH_GET_FS => {
+			let base = crate::task::with_thread(id, |t| t.fs_base()).ok_or(KernelError::InvalidParam)?;
+			UserPtr::<u64>::new(addr).write(base)?;
+			Ok(0)
+		}
+		_ => Err(KernelError::InvalidParam),
+	}
+}
+
+/// rt_sigaction (sig, act, oact, sigsetsize)
+///
+/// 起動処理が呼ぶため受け付けるだけで、動作は設定しない（ネイティブの`SigAction`のみ対応）。
+/// 以前の動作を求められた場合はデフォルト（すべて0）を返す
+fn rt_sigaction(old_ptr: u64) -> Result<u64> {
+	/// カーネルの`struct sigaction`（handler, flags, restorer, mask）
+	const KERNEL_SIGACTION_SIZE: usize = 32;
+	if old_ptr != 0 {
+		UserSlice::new(old_ptr, KERNEL_SIGACTION_SIZE).write(&[0; KERNEL_SIGACTION_SIZE])?;
+	}
+	Ok(0)
+}
+
+/// rt_sigprocmask (how, set, oset, sigsetsize)
+///
+/// シグナルマスクを持たないため受け付けるだけで、以前のマスクは空として返す
+fn rt_sigprocmask(old_ptr: u64) -> Result<u64> {
+	if old_ptr != 0 {
+		UserPtr::<u64>::new(old_ptr).write(0)?;
+	}
+	Ok(0)
+}
+
+/// uname (buf)
+fn uname(buf_ptr: u64) -> Result<u64> {
+	/// `struct utsname`の各フィールドの長さ
+	const FIELD_LEN: usize = 65;
+	const FIELDS: [&str; 6] = ["SwiftCore", "swiftcore", env!("CARGO_PKG_VERSION"), "", "x86_64", "(none)"];
+
+	let mut buf = [0u8; FIELD_LEN * FIELDS.len()];
+	for (field, value) in buf.chunks_exact_mut(FIELD_LEN).zip(FIELDS) {
+		field[..value.len()].copy_from_slice(value.as_bytes());
+	}
+	UserSlice::new(buf_ptr, buf.len()).write(&buf)?;
+	Ok(0)
+}
+
+/// getpid ()
+fn getpid() -> Result<u64> {
+	crate::task::current_process_id().map(|pid| pid.as_u64()).ok_or(KernelError::InvalidParam)
+}
+
+/// gettid ()
+///
+/// set_tid_addressも同じ値を返す。`clone`を持たないため、渡されたアドレスは使わない
+fn gettid() -> Result<u64> {
+	crate::task::current_thread_id().map(|id| id.as_u64()).ok_or(KernelError::InvalidParam)
+}
+
+/// exit (code)
+///
+/// Linuxと同様に現在のスレッドのみを終了する。終了コードは下位8ビット
+fn exit(code: u64) -> Result<u64> {
+	if crate::task::current_thread_id().is_none() {
+		return Err(KernelError::InvalidParam);
+	}
+	crate::task::exit_current_thread(code & 0xFF);
+	Ok(0)
+}
+
+/// exit_group (code)
+fn exit_group(code: u64) -> Result<u64> {
+	if crate::task::current_thread_id().is_none() {
+		return Err(KernelError::InvalidParam);
+	}
+	crate::task::exit_current_process(code & 0xFF);
+	Ok(0)
+}
@@ -0,0 +1,168 @@
+//! メモリ管理システムコール（mmap / munmap / mprotect / brk）
+//!
+//! 匿名プライベートマッピングのみ対応する。ページは最初のアクセス時に割り当てる。
+//! ファイルのマッピングは内容をコピーした匿名マッピングとして作る（`mmap_copy`）。
+
+use x86_64::structures::paging::PageTableFlags;
+
+use crate::error::{KernelError, MemoryError, Result};
+use crate::mem::address_space::USER_SPACE_END;
+use crate::mem::paging;
+use crate::mem::vma::RegionKind;
+use crate::mem::AddressSpace;
+
+pub use swiftcore_abi::memory::{MAP_ANONYMOUS, MAP_FIXED, MAP_PRIVATE, MAP_SHARED, PROT_EXEC, PROT_READ, PROT_WRITE};
+
+/// アドレス指定がない場合にマッピングを配置する下限
+const MMAP_BASE: u64 = 0x0000_1000_0000_0000;
+
+const PAGE_SIZE: u64 = 4096;
+
+/// 保護属性をページテーブルフラグに変換
+fn prot_to_flags(prot: u64) -> PageTableFlags {
+	let mut flags = PageTableFlags::PRESENT;
+	if prot & (PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+		flags |= PageTableFlags::USER_ACCESSIBLE;
+	}
+	if prot & PROT_WRITE != 0 {
+		flags |= PageTableFlags::WRITABLE;
+	}
+	if prot & PROT_EXEC == 0 {
+		flags |= PageTableFlags::NO_EXECUTE;
+	}
+	flags
+}
+
+/// 長さをページ単位に切り上げる（0や桁あふれはNone）
+fn page_len(len: u64) -> Option<u64> {
+	if len == 0 {
+		return None;
+	}
+	len.checked_add(PAGE_SIZE - 1).map(|l| l & !(PAGE_SIZE - 1))
+}
+
+/// 現在のプロセスのアドレス空間に対して操作を実行
+fn with_current_space<F>(f: F) -> Result<u64>
+where
+	F: FnOnce(&mut AddressSpace) -> Result<u64>,
+{
+	let pid = crate::task::current_thread_id()
+		.and_then(|id| crate::task::with_thread(id, |t| t.process_id()))
+		.ok_or(KernelError::InvalidParam)?;
+	// カーネル空間を共有するプロセス（Core）はユーザー空間のマッピングを持たない
+	crate::task::with_process_mut(pid, |p| p.address_space_mut().map(f))
+		.flatten()
+		.ok_or(KernelError::InvalidParam)?
+}
+
+/// 匿名メモリをマップ (addr, len, prot, flags)
+///
+/// 成功時はマップした先頭アドレスを返す。匿名マッピングのみのためfdとoffsetは受け取らない
+pub fn mmap(addr: u64, len: u64, prot: u64, flags: u64) -> Result<u64> {
+	let len = page_len(len).ok_or(KernelError::InvalidParam)?;
+	if flags & MAP_SHARED != 0 || flags & MAP_PRIVATE == 0 || flags & MAP_ANONYMOUS == 0 {
+		return Err(KernelError::InvalidParam);
+	}
+	let fixed = flags & MAP_FIXED != 0;
+	if fixed && (!addr.is_multiple_of(PAGE_SIZE) || addr == 0 || addr.checked_add(len).is_none_or(|end| end > USER_SPACE_END)) {
+		return Err(KernelError::InvalidParam);
+	}
+	// 既存のマッピングを外す前に、カーネルが恒等マッピングで使う範囲を拒否する
+	if fixed && paging::overlaps_identity_range(addr, addr + len) {
+		return Err(KernelError::Memory(MemoryError::InvalidAddress));
+	}
+	let page_flags = prot_to_flags(prot);
+
+	with_current_space(|space| {
+		let start = if fixed {
+			space.unmap_range(addr, len)?;
+			addr
+		} else {
+			let hint = addr & !(PAGE_SIZE - 1);
+			if hint != 0 && space.is_range_free(hint, len) {
+				hint
+			} else {
+				space
+					.find_free_range(MMAP_BASE, len)
+					.ok_or(KernelError::Memory(MemoryError::OutOfMemory))?
+			}
+		};
+
+		space.add_region(start, len, page_flags, RegionKind::Anon)?;
+		Ok(start)
+	})
+}
+
+/// 匿名メモリをマップし、先頭に`contents`をコピーする (addr, len, prot, flags)
+///
+/// ファイルのプライベートマッピング用。`contents`より後ろはゼロで埋まる
+pub fn mmap_copy(addr: u64, len: u64, prot: u64, flags: u64, contents: &[u8]) -> Result<u64> {
+	let start = mmap(addr, len, prot, flags | MAP_ANONYMOUS)?;
+	if contents.is_empty() {
+		return Ok(start);
+	}
+	let contents = &contents[..contents.len().min(len as usize)];
+
+	with_current_space(|space| match space.write_bytes(start, contents) {
+		Ok(()) => Ok(start),
+		Err(e) => {
+			if let Some(len) = page_len(len) {
+				let _ = space.unmap_range(start, len);
+			}
+			Err(e)
+		}
+	})
+}
+
+/// メモリのマップを解除 (addr, len)
+pub fn munmap(addr: u64, len: u64) -> Result<u64> {
+	let len = page_len(len).ok_or(KernelError::InvalidParam)?;
+	if !addr.is_multiple_of(PAGE_SIZE) {
+		return Err(KernelError::Memory(MemoryError::AlignmentError));
+	}
+
+	with_current_space(|space| space.unmap_range(addr, len).map(|()| 0))
+}
+
+/// メモリの保護属性を変更 (addr, len, prot)
+pub fn mprotect(addr: u64, len: u64, prot: u64) -> Result<u64> {
+	let len = page_len(len).ok_or(KernelError::InvalidParam)?;
+	if !addr.is_multiple_of(PAGE_SIZE) {
+		return Err(KernelError::Memory(MemoryError::AlignmentError));
+	}
+	let page_flags = prot_to_flags(prot);
+
+	with_current_space(|space| space.protect_range(addr, len, page_flags).map(|()| 0))
+}
+
+/// プログラムブレークを設定 (addr)
+///
+/// Linuxと同様に、成功時は新しいブレーク、失敗時や`addr`が0の場合は現在のブレークを返す
+pub fn brk(addr: u64) -> Result<u64> {
+	with_current_space(|space| {
+		if addr == 0 {
+			return Ok(space.program_break());
+		}
+		Ok(space.set_program_break(addr).unwrap_or(space.program_break()))
+	})
+}
+
+/// プログラムブレークを増減 (increment)
+///
+/// 成功時は変更前のブレークを返す
+pub fn sbrk(increment: i64) -> Result<u64> {
+	with_current_space(|space| {
+		let old = space.program_break();
+		if increment == 0 {
+			return Ok(old);
+		}
+		let new = old
+			.checked_add_signed(increment)
+			.ok_or(KernelError::Memory(MemoryError::OutOfMemory))?;
+		match space.set_program_break(new) {
+			Ok(_) => Ok(old),
+			Err(KernelError::Memory(MemoryError::InvalidAddress)) if increment < 0 => Err(KernelError::InvalidParam),
+			Err(_) => Err(KernelError::Memory(MemoryError::OutOfMemory)),
+		}
+	})
+}
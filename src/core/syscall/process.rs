@@ -0,0 +1,232 @@
+//! プロセス関連システムコール
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use swiftcore_abi::process::{StrRef, PRIVILEGE_CORE, PRIVILEGE_SERVICE, PRIVILEGE_USER};
+
+use crate::error::{KernelError, MemoryError, ProcessError, Result};
+use crate::task::signal::SIGKILL;
+use crate::task::{PrivilegeLevel, ProcessId, SigAction};
+
+use super::user_ptr::{UserPtr, UserSlice};
+
+const MAX_PATH_LEN: usize = 256;
+/// argv/envpの要素数の上限
+const MAX_ARGS: usize = 64;
+/// argv/envpの各文字列の長さの上限
+const MAX_ARG_LEN: usize = 4096;
+
+pub use swiftcore_abi::process::WNOHANG;
+pub use swiftcore_abi::signal::{SIG_DFL, SIG_IGN};
+
+/// ユーザー空間の文字列を読み出す
+fn read_user_str(ptr: u64, len: usize, max_len: usize) -> Result<String> {
+	if ptr == 0 || len > max_len {
+		return Err(KernelError::InvalidParam);
+	}
+	let bytes = UserSlice::new(ptr, len).read_to_vec()?;
+	String::from_utf8(bytes).map_err(|_| KernelError::InvalidParam)
+}
+
+/// 文字列配列を読み出す
+///
+/// `ptr`は`StrRef`の配列を指し、アドレスが0の要素で終わる。
+/// `ptr`が0の場合は空の配列として扱う
+fn read_user_str_array(ptr: u64) -> Result<Vec<String>> {
+	let mut strings = Vec::new();
+	if ptr == 0 {
+		return Ok(strings);
+	}
+
+	for i in 0..=MAX_ARGS {
+		let entry = UserPtr::<StrRef>::new(ptr + (i * size_of::<StrRef>()) as u64).read()?;
+		if entry.is_end() {
+			return Ok(strings);
+		}
+		if i == MAX_ARGS {
+			break;
+		}
+		let s = read_user_str(entry.ptr, entry.len as usize, MAX_ARG_LEN)?;
+		strings.try_reserve(1).map_err(|_| KernelError::Memory(MemoryError::OutOfMemory))?;
+		strings.push(s);
+	}
+
+	Err(KernelError::InvalidParam)
+}
+
+/// 権限レベルの番号を変換
+fn privilege_from_u64(value: u64) -> Option<PrivilegeLevel> {
+	match value {
+		PRIVILEGE_CORE => Some(PrivilegeLevel::Core),
+		PRIVILEGE_SERVICE => Some(PrivilegeLevel::Service),
+		PRIVILEGE_USER => Some(PrivilegeLevel::User),
+		_ => None,
+	}
+}
+
+/// 権限の強さ（小さいほど強い）
+fn privilege_rank(level: PrivilegeLevel) -> u8 {
+	match level {
+		PrivilegeLevel::Core => 0,
+		PrivilegeLevel::Service => 1,
+		PrivilegeLevel::User => 2,
+	}
+}
+
+/// 番号からプロセスIDを探す
+fn find_process(pid: u64) -> Option<ProcessId> {
+	let mut found = None;
+	crate::task::for_each_process(|p| {
+		if p.id().as_u64() == pid {
+			found = Some(p.id());
+		}
+	});
+	found
+}
+
+/// 呼び出し元のプロセスID
+fn current_process() -> Result<ProcessId> {
+	crate::task::current_process_id().ok_or(KernelError::InvalidParam)
+}
+
+/// プロセスの権限（プロセスがない場合は`ProcessNotFound`）
+fn privilege_of(pid: ProcessId) -> Result<PrivilegeLevel> {
+	crate::task::with_process(pid, |p| p.privilege()).ok_or(KernelError::Process(ProcessError::ProcessNotFound))
+}
+
+/// initfsのELFを新しいプロセスとして起動し、プロセスIDを返す
+///
+/// 呼び出し元より強い権限のプロセスは起動できない
+pub fn spawn(path_ptr: u64, path_len: u64, argv_ptr: u64, envp_ptr: u64, privilege: u64) -> Result<u64> {
+	let privilege = match privilege_from_u64(privilege) {
+		Some(PrivilegeLevel::Core) | None => return Err(KernelError::InvalidParam),
+		Some(level) => level,
+	};
+
+	let parent = current_process()?;
+	if privilege_rank(privilege) < privilege_rank(privilege_of(parent)?) {
+		return Err(KernelError::Process(ProcessError::InsufficientPrivilege));
+	}
+
+	let path = read_user_str(path_ptr, path_len as usize, MAX_PATH_LEN)?;
+	if path.is_empty() {
+		return Err(KernelError::InvalidParam);
+	}
+	let argv = read_user_str_array(argv_ptr)?;
+	let envp = read_user_str_array(envp_ptr)?;
+
+	// 存在しないパスやディレクトリはその原因のまま返す
+	crate::init::fs::lookup(&path)?;
+
+	// argvが空の場合はパスをargv[0]とする
+	let argv: Vec<&str> = if argv.is_empty() {
+		alloc::vec![path.as_str()]
+	} else {
+		argv.iter().map(String::as_str).collect()
+	};
+	let envp: Vec<&str> = envp.iter().map(String::as_str).collect();
+
+	let name = path.rsplit('/').next().unwrap_or(&path);
+	crate::task::spawn_process(&path, name, &argv, &envp, privilege, Some(parent)).map(|pid| pid.as_u64())
+}
+
+/// 子プロセスの終了を待って回収し、そのプロセスIDを返す
+///
+/// `pid`が0の場合はいずれかの子を待つ。終了コードは`status_ptr`（NULL可）に書き込む
+pub fn wait(pid: u64, status_ptr: u64, options: u64) -> Result<u64> {
+	if options & !WNOHANG != 0 {
+		return Err(KernelError::InvalidParam);
+	}
+
+	let parent = current_process()?;
+
+	let status_ptr = UserPtr::<u64>::new(status_ptr);
+	if !status_ptr.is_null() {
+		status_ptr.check_writable()?;
+	}
+
+	let target = if pid == 0 {
+		None
+	} else {
+		Some(find_process(pid).ok_or(KernelError::Process(ProcessError::NoChild))?)
+	};
+
+	match crate::task::wait_child(parent, target, options & WNOHANG != 0)? {
+		Some((child, code)) => {
+			if !status_ptr.is_null() {
+				status_ptr.write(code)?;
+			}
+			Ok(child.as_u64())
+		}
+		None => Ok(0),
+	}
+}
+
+/// プロセスにシグナルを送る
+///
+/// `sig`が0の場合は送信できるかどうかだけを確認する。
+/// 呼び出し元より強い権限のプロセスには送れない
+pub fn kill(pid: u64, sig: u64) -> Result<u64> {
+	let sig = u32::try_from(sig).map_err(|_| KernelError::InvalidParam)?;
+	let target = find_process(pid).ok_or(KernelError::Process(ProcessError::ProcessNotFound))?;
+	let caller_privilege = privilege_of(current_process()?)?;
+	let target_privilege = privilege_of(target)?;
+	if target_privilege == PrivilegeLevel::Core || privilege_rank(target_privilege) < privilege_rank(caller_privilege) {
+		return Err(KernelError::Process(ProcessError::InsufficientPrivilege));
+	}
+
+	crate::task::send_signal(target, sig)?;
+	Ok(0)
+}
+
+/// シグナルの動作を設定し、以前のハンドラを返す
+///
+/// `handler`はSIG_DFL、SIG_IGN、またはハンドラのアドレス。ハンドラは`restorer`へ戻り、
+/// restorerは`SigReturn`を呼ぶこと。SIGKILLとSIGSTOPは変更できない
+pub fn sigaction(sig: u64, handler: u64, restorer: u64) -> Result<u64> {
+	let sig = match u32::try_from(sig) {
+		Ok(sig) if sig != SIGKILL => sig,
+		_ => return Err(KernelError::InvalidParam),
+	};
+	let pid = current_process()?;
+
+	let action = match handler {
+		SIG_DFL => SigAction::Default,
+		SIG_IGN => SigAction::Ignore,
+		entry => SigAction::Handler { entry, restorer },
+	};
+	Ok(match crate::task::set_action(pid, sig, action)? {
+		SigAction::Default => SIG_DFL,
+		SigAction::Ignore => SIG_IGN,
+		SigAction::Handler { entry, .. } => entry,
+	})
+}
+
+/// システムコールのトレースを切り替え、以前の設定（0/1）を返す (pid, enable)
+///
+/// `pid`が0の場合は自プロセスが対象。Userの権限では使えず、
+/// 呼び出し元より強い権限のプロセスは対象にできない
+pub fn trace(pid: u64, enable: u64) -> Result<u64> {
+	let caller = current_process()?;
+	let target = if pid == 0 {
+		caller
+	} else {
+		find_process(pid).ok_or(KernelError::Process(ProcessError::ProcessNotFound))?
+	};
+	let caller_privilege = privilege_of(caller)?;
+	if caller_privilege == PrivilegeLevel::User {
+		return Err(KernelError::Process(ProcessError::InsufficientPrivilege));
+	}
+
+	crate::task::with_process_mut(target, |p| {
+		if privilege_rank(p.privilege()) < privilege_rank(caller_privilege) {
+			return Err(KernelError::Process(ProcessError::InsufficientPrivilege));
+		}
+		let previous = p.is_traced();
+		p.set_traced(enable != 0);
+		Ok(previous as u64)
+	})
+	.ok_or(KernelError::Process(ProcessError::ProcessNotFound))?
+}
@@ -1,43 +1,44 @@
+use crate::error::{KernelError, MemoryError, ProcessError, Result};
+
 /// タスク関連システムコール
-pub fn yield_now() -> u64 {
+pub fn yield_now() -> Result<u64> {
 	crate::task::yield_now();
-	0
+	Ok(0)
 }
 
-/// 現在のスレッドを終了
-pub fn exit(_code: u64) -> u64 {
-	if let Some(id) = crate::task::current_thread_id() {
-		crate::task::terminate_thread(id);
-		0
-	} else {
-		crate::syscall::EINVAL
+/// 現在のプロセスを終了
+///
+/// 終了コードは親プロセスが`Wait`で受け取る
+pub fn exit(code: u64) -> Result<u64> {
+	if crate::task::current_thread_id().is_none() {
+		return Err(KernelError::InvalidParam);
 	}
+	crate::task::exit_current_process(code);
+	Ok(0)
 }
 
 /// 現在のスレッドIDを取得
-pub fn get_thread_id() -> u64 {
-	match crate::task::current_thread_id() {
-		Some(id) => id.as_u64(),
-		None => crate::syscall::EINVAL,
-	}
+pub fn get_thread_id() -> Result<u64> {
+	crate::task::current_thread_id()
+		.map(|id| id.as_u64())
+		.ok_or(KernelError::InvalidParam)
 }
 
 /// スレッド名からIDを取得
-pub fn get_thread_id_by_name(name_ptr: u64, name_len: u64) -> u64 {
+///
+/// 該当するスレッドがない場合は`ProcessNotFound`
+pub fn get_thread_id_by_name(name_ptr: u64, name_len: u64) -> Result<u64> {
 	const MAX_NAME_LEN: usize = 64;
 	if name_ptr == 0 {
-		return crate::syscall::EINVAL;
+		return Err(KernelError::InvalidParam);
 	}
 	let name_len = name_len as usize;
 	if name_len == 0 || name_len > MAX_NAME_LEN {
-		return crate::syscall::EINVAL;
+		return Err(KernelError::InvalidParam);
 	}
 
-	let name_bytes = unsafe { core::slice::from_raw_parts(name_ptr as *const u8, name_len) };
-	let name = match core::str::from_utf8(name_bytes) {
-		Ok(s) => s,
-		Err(_) => return crate::syscall::EINVAL,
-	};
+	let name_bytes = super::user_ptr::UserSlice::new(name_ptr, name_len).read_to_vec()?;
+	let name = core::str::from_utf8(&name_bytes).map_err(|_| KernelError::InvalidParam)?;
 
 	let mut found: Option<u64> = None;
 	crate::task::for_each_thread(|t| {
@@ -46,8 +47,113 @@ pub fn get_thread_id_by_name(name_ptr: u64, name_len: u64) -> u64 {
 		}
 	});
 
-	match found {
-		Some(id) => id,
-		None => crate::syscall::ENOENT,
+	found.ok_or(KernelError::Process(ProcessError::ProcessNotFound))
+}
+
+/// 現在のスレッドと同じプロセスにスレッドを作成
+///
+/// `stack_top`が0の場合はユーザースタックを新たに確保する。
+/// 新しいスレッドは`entry(arg)`として開始する。戻り値はスレッドID
+pub fn thread_create(entry: u64, stack_top: u64, arg: u64) -> Result<u64> {
+	use crate::mem::address_space::USER_SPACE_END;
+	use crate::mem::user::{alloc_user_stack, USER_STACK_PAGES};
+
+	if entry == 0 || entry >= USER_SPACE_END || stack_top >= USER_SPACE_END {
+		return Err(KernelError::InvalidParam);
+	}
+
+	let (pid, name) = crate::task::current_thread_id()
+		.and_then(|id| crate::task::with_thread(id, |t| (t.process_id(), alloc::string::String::from(t.name()))))
+		.ok_or(KernelError::InvalidParam)?;
+
+	let stack_top = if stack_top != 0 {
+		stack_top
+	} else {
+		let stack = crate::task::with_process_mut(pid, |p| {
+			p.address_space_mut()
+				.map(|space| alloc_user_stack(space, USER_STACK_PAGES))
+		})
+		.flatten();
+		match stack {
+			Some(Ok(stack)) => stack.top,
+			Some(Err(_)) => return Err(KernelError::Memory(MemoryError::OutOfMemory)),
+			// カーネル空間を共有するプロセスからは作成できない
+			None => return Err(KernelError::InvalidParam),
+		}
+	};
+
+	// 関数呼び出し直後と同じく、RSP+8が16バイト境界になるようにする
+	let rsp = (stack_top & !0xF).checked_sub(8).ok_or(KernelError::InvalidParam)?;
+	let mut context = crate::task::Context::user(entry, rsp);
+	context.rdi = arg;
+
+	crate::task::add_user_thread(pid, &name, context).map(|id| id.as_u64())
+}
+
+/// 同じプロセスのスレッドの終了を待ち、終了コードを返す
+///
+/// 待ち合わせたスレッドはキューから削除する。参加する前に`reap_threads`で
+/// 回収されたスレッドは、プロセスに残した終了コードを返す
+pub fn thread_join(thread_id: u64) -> Result<u64> {
+	let (current, pid) = crate::task::current_thread_id()
+		.and_then(|id| crate::task::with_thread(id, |t| (id, t.process_id())))
+		.ok_or(KernelError::InvalidParam)?;
+	if thread_id == current.as_u64() {
+		return Err(KernelError::InvalidParam);
+	}
+
+	loop {
+		// 待っている間に回収されることがあるため、起床のたびに探し直す
+		let mut target = None;
+		crate::task::for_each_thread(|t| {
+			if t.id().as_u64() == thread_id {
+				target = Some(t.id());
+			}
+		});
+		let target = match target {
+			Some(id) => id,
+			None => {
+				return crate::task::with_process_mut(pid, |p| p.take_thread_exit(thread_id))
+					.flatten()
+					.ok_or(KernelError::Process(ProcessError::ProcessNotFound));
+			}
+		};
+
+		// システムコール中は割り込み禁止のため、登録からブロックまでの間に
+		// 対象スレッドが終了することはない
+		let finished = crate::task::with_thread_mut(target, |t| {
+			if t.process_id() != pid {
+				return Err(KernelError::InvalidParam);
+			}
+			if t.state() == crate::task::ThreadState::Terminated {
+				return Ok(Some(t.exit_code().unwrap_or(0)));
+			}
+			match t.joiner() {
+				Some(joiner) if joiner != current => Err(KernelError::InvalidParam),
+				_ => {
+					t.set_joiner(current);
+					Ok(None)
+				}
+			}
+		});
+
+		match finished {
+			None => return Err(KernelError::Process(ProcessError::ProcessNotFound)),
+			Some(Err(e)) => return Err(e),
+			Some(Ok(Some(code))) => {
+				crate::task::remove_thread(target);
+				return Ok(code);
+			}
+			Some(Ok(None)) => crate::task::block_current_until(None),
+		}
+	}
+}
+
+/// 終了コードを記録して現在のスレッドを終了
+pub fn thread_exit(code: u64) -> Result<u64> {
+	if crate::task::current_thread_id().is_none() {
+		return Err(KernelError::InvalidParam);
 	}
+	crate::task::exit_current_thread(code);
+	Ok(0)
 }
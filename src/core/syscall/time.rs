@@ -1,4 +1,80 @@
+use crate::error::{KernelError, Result};
+use crate::interrupt::timer::TICKS_PER_SECOND;
+
+use super::user_ptr::UserPtr;
+
+/// 1ティックあたりのナノ秒
+const NANOS_PER_TICK: u64 = 1_000_000_000 / TICKS_PER_SECOND;
+
+/// Linuxの`struct timespec`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct Timespec {
+	tv_sec: i64,
+	tv_nsec: i64,
+}
+
 /// 時刻関連システムコール
-pub fn get_ticks() -> u64 {
-	crate::interrupt::timer::get_ticks()
+pub fn get_ticks() -> Result<u64> {
+	Ok(crate::interrupt::timer::get_ticks())
+}
+
+/// 指定ティック数スリープ (ticks)
+pub fn sleep(ticks: u64) -> Result<u64> {
+	crate::task::sleep_ticks(ticks);
+	Ok(0)
+}
+
+/// Linux互換のnanosleep (req_ptr, rem_ptr)
+///
+/// ティック単位に切り上げてスリープする。途中で起こされることはないため`rem`には0を書き込む
+pub fn nanosleep(req_ptr: u64, rem_ptr: u64) -> Result<u64> {
+	let req = UserPtr::<Timespec>::new(req_ptr).read()?;
+	if req.tv_sec < 0 || !(0..1_000_000_000).contains(&req.tv_nsec) {
+		return Err(KernelError::InvalidParam);
+	}
+
+	let ticks = (req.tv_sec as u64)
+		.saturating_mul(TICKS_PER_SECOND)
+		.saturating_add((req.tv_nsec as u64).div_ceil(NANOS_PER_TICK));
+	crate::task::sleep_ticks(ticks);
+
+	let rem = UserPtr::<Timespec>::new(rem_ptr);
+	if !rem.is_null() {
+		rem.write(Timespec { tv_sec: 0, tv_nsec: 0 })?;
+	}
+	Ok(0)
+}
+
+/// Linux互換のclock_gettime (clock_id, tp_ptr)
+///
+/// 時計を持たないため、どの時計も起動からの経過時間（ティック単位）を返す。
+/// CPU時間の時計には対応しない
+pub fn clock_gettime(clock_id: u64, tp_ptr: u64) -> Result<u64> {
+	const CLOCK_REALTIME: u64 = 0;
+	const CLOCK_MONOTONIC: u64 = 1;
+	const CLOCK_MONOTONIC_RAW: u64 = 4;
+	const CLOCK_REALTIME_COARSE: u64 = 5;
+	const CLOCK_MONOTONIC_COARSE: u64 = 6;
+	const CLOCK_BOOTTIME: u64 = 7;
+
+	if !matches!(
+		clock_id,
+		CLOCK_REALTIME
+			| CLOCK_MONOTONIC
+			| CLOCK_MONOTONIC_RAW
+			| CLOCK_REALTIME_COARSE
+			| CLOCK_MONOTONIC_COARSE
+			| CLOCK_BOOTTIME
+	) {
+		return Err(KernelError::InvalidParam);
+	}
+
+	let ticks = crate::interrupt::timer::get_ticks();
+	let now = Timespec {
+		tv_sec: (ticks / TICKS_PER_SECOND) as i64,
+		tv_nsec: ((ticks % TICKS_PER_SECOND) * NANOS_PER_TICK) as i64,
+	};
+	UserPtr::<Timespec>::new(tp_ptr).write(now)?;
+	Ok(0)
 }
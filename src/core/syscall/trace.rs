@@ -0,0 +1,187 @@
+//! システムコールのトレース
+//!
+//! トレースを有効にしたプロセス（`Process::is_traced`）のシステムコールを、
+//! 呼び出したスレッドの名前、番号、名前付きの引数、戻り値、所要ティック数とともに
+//! シリアルのログへ出力する。
+
+use alloc::string::String;
+use core::fmt;
+
+use crate::util::log::{self, LogLevel};
+
+use super::linux::{self, errno as linux_errno};
+use super::{Errno, SyscallNumber, NATIVE_SYSCALL_BASE};
+
+/// 現在のスレッドのプロセスがトレース対象なら、スレッド名を返す
+///
+/// すべてのシステムコールで呼ばれるため、トレース対象でなければ名前をコピーしない
+pub fn traced_thread() -> Option<String> {
+	let id = crate::task::current_thread_id()?;
+	let pid = crate::task::with_thread(id, |t| t.process_id())?;
+	if !crate::task::with_process(pid, |p| p.is_traced())? {
+		return None;
+	}
+	crate::task::with_thread(id, |t| String::from(t.name()))
+}
+
+/// 1回の呼び出しを記録する
+pub fn log_call(thread: &str, num: u64, args: &[u64; 6], ret: u64, ticks: u64) {
+	let (name, params) = signature(num);
+	log::log(
+		LogLevel::Info,
+		format_args!(
+			"[strace] {}: {}({}) = {} <{} ticks>",
+			thread,
+			name,
+			Args { params, values: args },
+			Ret { num, value: ret },
+			ticks
+		),
+	);
+}
+
+/// 引数の表示（引数名=値）
+struct Args<'a> {
+	params: &'static [&'static str],
+	values: &'a [u64; 6],
+}
+
+impl fmt::Display for Args<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for (i, (param, value)) in self.params.iter().zip(self.values).enumerate() {
+			if i != 0 {
+				f.write_str(", ")?;
+			}
+			write!(f, "{}={:#x}", param, value)?;
+		}
+		Ok(())
+	}
+}
+
+/// 戻り値の表示（エラーはその種類で表す）
+struct Ret {
+	num: u64,
+	value: u64,
+}
+
+impl fmt::Display for Ret {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.num >= NATIVE_SYSCALL_BASE {
+			return match Errno::from_code(self.value) {
+				Some(e) => write!(f, "{:?}", e),
+				None => write!(f, "{:#x}", self.value),
+			};
+		}
+		// Linuxの番号はエラー番号の符号を反転した値（-4095..-1）で失敗を返す
+		let signed = self.value as i64;
+		if (-4095..0).contains(&signed) {
+			write!(f, "-{}", linux_errno_name(signed.unsigned_abs()))
+		} else {
+			write!(f, "{:#x}", self.value)
+		}
+	}
+}
+
+fn linux_errno_name(errno: u64) -> &'static str {
+	use linux_errno::*;
+	match errno {
+		EPERM => "EPERM",
+		ENOENT => "ENOENT",
+		ESRCH => "ESRCH",
+		EIO => "EIO",
+		EBADF => "EBADF",
+		ECHILD => "ECHILD",
+		EAGAIN => "EAGAIN",
+		ENOMEM => "ENOMEM",
+		EFAULT => "EFAULT",
+		EBUSY => "EBUSY",
+		EEXIST => "EEXIST",
+		ENODEV => "ENODEV",
+		ENOTDIR => "ENOTDIR",
+		EISDIR => "EISDIR",
+		EINVAL => "EINVAL",
+		EMFILE => "EMFILE",
+		ENOTTY => "ENOTTY",
+		ESPIPE => "ESPIPE",
+		EROFS => "EROFS",
+		ENAMETOOLONG => "ENAMETOOLONG",
+		ENOSYS => "ENOSYS",
+		ENODATA => "ENODATA",
+		EOPNOTSUPP => "EOPNOTSUPP",
+		ETIMEDOUT => "ETIMEDOUT",
+		_ => "E?",
+	}
+}
+
+/// システムコールの名前と引数名
+fn signature(num: u64) -> (&'static str, &'static [&'static str]) {
+	if let Some(number) = SyscallNumber::from_u64(num) {
+		return native_signature(number);
+	}
+	match num {
+		linux::SYS_READ => ("read", &["fd", "buf", "count"]),
+		linux::SYS_WRITE => ("write", &["fd", "buf", "count"]),
+		linux::SYS_OPEN => ("open", &["path", "flags", "mode"]),
+		linux::SYS_CLOSE => ("close", &["fd"]),
+		linux::SYS_STAT => ("stat", &["path", "statbuf"]),
+		linux::SYS_FSTAT => ("fstat", &["fd", "statbuf"]),
+		linux::SYS_LSTAT => ("lstat", &["path", "statbuf"]),
+		linux::SYS_LSEEK => ("lseek", &["fd", "offset", "whence"]),
+		linux::SYS_MMAP => ("mmap", &["addr", "len", "prot", "flags", "fd", "offset"]),
+		linux::SYS_MPROTECT => ("mprotect", &["addr", "len", "prot"]),
+		linux::SYS_MUNMAP => ("munmap", &["addr", "len"]),
+		linux::SYS_BRK => ("brk", &["addr"]),
+		linux::SYS_RT_SIGACTION => ("rt_sigaction", &["sig", "act", "oact", "sigsetsize"]),
+		linux::SYS_RT_SIGPROCMASK => ("rt_sigprocmask", &["how", "set", "oset", "sigsetsize"]),
+		linux::SYS_IOCTL => ("ioctl", &["fd", "request", "arg"]),
+		linux::SYS_WRITEV => ("writev", &["fd", "iov", "iovcnt"]),
+		linux::SYS_ACCESS => ("access", &["path", "mode"]),
+		linux::SYS_NANOSLEEP => ("nanosleep", &["req", "rem"]),
+		linux::SYS_GETPID => ("getpid", &[]),
+		linux::SYS_UNAME => ("uname", &["buf"]),
+		linux::SYS_GETUID => ("getuid", &[]),
+		linux::SYS_GETGID => ("getgid", &[]),
+		linux::SYS_GETEUID => ("geteuid", &[]),
+		linux::SYS_GETEGID => ("getegid", &[]),
+		linux::SYS_EXIT => ("exit", &["code"]),
+		linux::SYS_ARCH_PRCTL => ("arch_prctl", &["code", "addr"]),
+		linux::SYS_GETTID => ("gettid", &[]),
+		linux::SYS_SET_TID_ADDRESS => ("set_tid_address", &["tidptr"]),
+		linux::SYS_CLOCK_GETTIME => ("clock_gettime", &["clock", "tp"]),
+		linux::SYS_EXIT_GROUP => ("exit_group", &["code"]),
+		_ => ("unknown", &["arg0", "arg1", "arg2", "arg3", "arg4", "arg5"]),
+	}
+}
+
+/// ネイティブのシステムコールの名前と引数名
+///
+/// すべての番号を列挙し、ABIに追加した番号の記述漏れをコンパイル時に検出する
+fn native_signature(number: SyscallNumber) -> (&'static str, &'static [&'static str]) {
+	match number {
+		SyscallNumber::Yield => ("Yield", &[]),
+		SyscallNumber::GetTicks => ("GetTicks", &[]),
+		SyscallNumber::IpcSend => ("IpcSend", &["dest", "value", "timeout"]),
+		SyscallNumber::IpcRecv => ("IpcRecv", &["sender", "timeout"]),
+		SyscallNumber::ConsoleWrite => ("ConsoleWrite", &["buf", "len"]),
+		SyscallNumber::InitfsRead => ("InitfsRead", &["path", "path_len", "buf", "buf_len"]),
+		SyscallNumber::Exit => ("Exit", &["code"]),
+		SyscallNumber::KeyboardRead => ("KeyboardRead", &[]),
+		SyscallNumber::GetThreadId => ("GetThreadId", &[]),
+		SyscallNumber::GetThreadIdByName => ("GetThreadIdByName", &["name", "name_len"]),
+		SyscallNumber::Mmap => ("Mmap", &["addr", "len", "prot", "flags"]),
+		SyscallNumber::Munmap => ("Munmap", &["addr", "len"]),
+		SyscallNumber::Mprotect => ("Mprotect", &["addr", "len", "prot"]),
+		SyscallNumber::Brk => ("Brk", &["addr"]),
+		SyscallNumber::Sbrk => ("Sbrk", &["increment"]),
+		SyscallNumber::Sleep => ("Sleep", &["ticks"]),
+		SyscallNumber::ThreadCreate => ("ThreadCreate", &["entry", "stack_top", "arg"]),
+		SyscallNumber::ThreadJoin => ("ThreadJoin", &["thread"]),
+		SyscallNumber::ThreadExit => ("ThreadExit", &["code"]),
+		SyscallNumber::Spawn => ("Spawn", &["path", "path_len", "argv", "envp", "privilege"]),
+		SyscallNumber::Wait => ("Wait", &["pid", "status", "options"]),
+		SyscallNumber::Kill => ("Kill", &["pid", "sig"]),
+		SyscallNumber::SigAction => ("SigAction", &["sig", "handler", "restorer"]),
+		SyscallNumber::SigReturn => ("SigReturn", &[]),
+		SyscallNumber::Trace => ("Trace", &["pid", "enable"]),
+	}
+}
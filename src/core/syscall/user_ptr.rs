@@ -0,0 +1,172 @@
+//! ユーザー空間ポインタへの安全なアクセス
+//!
+//! システムコールの引数として渡されたアドレスは、現在のプロセスの仮想メモリ領域に
+//! 収まっているかを検証してから、アドレス空間経由（物理メモリのカーネル側エイリアス）で
+//! コピーする。範囲外やアクセス権のない範囲は`MemoryError::InvalidAddress`（`EFAULT`）になる。
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::mem::{size_of, MaybeUninit};
+
+use crate::error::{FileSystemError, KernelError, MemoryError, Result};
+use crate::mem::AddressSpace;
+
+/// 現在のプロセスのアドレス空間に対して操作を実行
+///
+/// アドレス空間を持たないプロセス（Core）やメモリ不足以外のエラーはすべて不正なアドレスとして扱う
+fn with_current_space<F, R>(f: F) -> Result<R>
+where
+	F: FnOnce(&mut AddressSpace) -> Result<R>,
+{
+	const FAULT: KernelError = KernelError::Memory(MemoryError::InvalidAddress);
+
+	let pid = crate::task::current_thread_id()
+		.and_then(|id| crate::task::with_thread(id, |t| t.process_id()))
+		.ok_or(FAULT)?;
+	crate::task::with_process_mut(pid, |p| p.address_space_mut().map(f))
+		.flatten()
+		.ok_or(FAULT)?
+		.map_err(|e| match e {
+			KernelError::Memory(MemoryError::OutOfMemory) => e,
+			_ => FAULT,
+		})
+}
+
+/// ユーザー空間から`dst`へコピー
+pub fn copy_from_user(dst: &mut [u8], src: u64) -> Result<()> {
+	with_current_space(|space| {
+		space.check_user_range(src, dst.len(), false)?;
+		space.read_bytes(src, dst)
+	})
+}
+
+/// `src`をユーザー空間へコピー
+pub fn copy_to_user(dst: u64, src: &[u8]) -> Result<()> {
+	with_current_space(|space| {
+		space.check_user_range(dst, src.len(), true)?;
+		space.write_bytes(dst, src)
+	})
+}
+
+/// ユーザー空間の単一の値を指すポインタ
+///
+/// `T`は任意のビット列が有効な値になる型（整数や`#[repr(C)]`の構造体）に限る
+#[derive(Debug, Clone, Copy)]
+pub struct UserPtr<T> {
+	addr: u64,
+	_marker: PhantomData<T>,
+}
+
+impl<T: Copy> UserPtr<T> {
+	/// 新しいユーザーポインタを作成（この時点では検証しない）
+	pub const fn new(addr: u64) -> Self {
+		Self {
+			addr,
+			_marker: PhantomData,
+		}
+	}
+
+	/// アドレスを取得
+	pub fn addr(&self) -> u64 {
+		self.addr
+	}
+
+	/// NULLポインタかどうか
+	pub fn is_null(&self) -> bool {
+		self.addr == 0
+	}
+
+	/// 書き込み可能な範囲を指しているか検証
+	pub fn check_writable(&self) -> Result<()> {
+		with_current_space(|space| space.check_user_range(self.addr, size_of::<T>(), true))
+	}
+
+	/// 値を読み出す
+	pub fn read(&self) -> Result<T> {
+		let mut value = MaybeUninit::<T>::uninit();
+		let buf = unsafe { core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>()) };
+		copy_from_user(buf, self.addr)?;
+		Ok(unsafe { value.assume_init() })
+	}
+
+	/// 値を書き込む
+	pub fn write(&self, value: T) -> Result<()> {
+		let buf = unsafe { core::slice::from_raw_parts(&value as *const T as *const u8, size_of::<T>()) };
+		copy_to_user(self.addr, buf)
+	}
+}
+
+/// ユーザー空間のバイト列
+#[derive(Debug, Clone, Copy)]
+pub struct UserSlice {
+	addr: u64,
+	len: usize,
+}
+
+impl UserSlice {
+	/// 新しいユーザースライスを作成（この時点では検証しない）
+	pub const fn new(addr: u64, len: usize) -> Self {
+		Self { addr, len }
+	}
+
+	/// 先頭アドレスを取得
+	pub fn addr(&self) -> u64 {
+		self.addr
+	}
+
+	/// 長さを取得
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	/// 空かどうか
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// 内容をカーネルのバッファへ読み出す
+	pub fn read_to_vec(&self) -> Result<Vec<u8>> {
+		with_current_space(|space| space.check_user_range(self.addr, self.len, false))?;
+
+		let mut buf = Vec::new();
+		buf.try_reserve_exact(self.len).map_err(|_| KernelError::Memory(MemoryError::OutOfMemory))?;
+		buf.resize(self.len, 0);
+		copy_from_user(&mut buf, self.addr)?;
+		Ok(buf)
+	}
+
+	/// 先頭から`data`を書き込む（`data`がスライスより長い場合は不正なアドレス）
+	pub fn write(&self, data: &[u8]) -> Result<()> {
+		if data.len() > self.len {
+			return Err(KernelError::Memory(MemoryError::InvalidAddress));
+		}
+		copy_to_user(self.addr, data)
+	}
+}
+
+/// NUL終端の文字列を読み出す（NULは含めない）
+///
+/// ページ境界ごとに区切って読むため、文字列の後ろがマップされていなくてもよい。
+/// 名前やパスの読み出しに使い、`max_len`バイト以内にNULがない場合は`NameTooLong`
+pub fn read_c_string(addr: u64, max_len: usize) -> Result<Vec<u8>> {
+	const PAGE_SIZE: u64 = 4096;
+	const NO_MEMORY: KernelError = KernelError::Memory(MemoryError::OutOfMemory);
+
+	let mut buf = Vec::new();
+	let mut cursor = addr;
+	while buf.len() < max_len {
+		let chunk_len = ((PAGE_SIZE - cursor % PAGE_SIZE) as usize).min(max_len - buf.len());
+		let chunk = UserSlice::new(cursor, chunk_len).read_to_vec()?;
+		if let Some(end) = chunk.iter().position(|&b| b == 0) {
+			buf.try_reserve(end).map_err(|_| NO_MEMORY)?;
+			buf.extend_from_slice(&chunk[..end]);
+			return Ok(buf);
+		}
+		buf.try_reserve(chunk.len()).map_err(|_| NO_MEMORY)?;
+		buf.extend_from_slice(&chunk);
+		cursor = cursor
+			.checked_add(chunk_len as u64)
+			.ok_or(KernelError::Memory(MemoryError::InvalidAddress))?;
+	}
+	Err(KernelError::Fs(FileSystemError::NameTooLong))
+}
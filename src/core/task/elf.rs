@@ -1,16 +1,19 @@
 //! ELFローダ
 
 use crate::error::{KernelError, MemoryError, ProcessError, Result};
-use crate::mem::{self, user, frame};
-use x86_64::structures::paging::Page;
-use x86_64::VirtAddr;
+use crate::mem::{self, user, AddressSpace};
+use crate::mem::vma::RegionKind;
 use x86_64::structures::paging::PageTableFlags;
-use crate::task::{add_process, add_thread, Process, PrivilegeLevel, Thread};
+use crate::task::{
+    add_process, add_user_thread, init_process_id, remove_process, Context, Process, PrivilegeLevel, ProcessId, DEFAULT_PRIORITY,
+};
 use crate::init;
+use alloc::vec::Vec;
 
 const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
 const PT_LOAD: u32 = 1;
 const PT_DYNAMIC: u32 = 2;
+const PT_GNU_RELRO: u32 = 0x6474_e552;
 const PF_X: u32 = 0x1;
 const PF_W: u32 = 0x2;
 
@@ -25,6 +28,11 @@ const R_X86_64_RELATIVE: u32 = 8;
 
 const PIE_LOAD_BIAS: u64 = 0x2000_0000;
 
+/// サービスとして起動するプロセスの優先度
+const SERVICE_PRIORITY: u8 = 1;
+/// argvとenvpの文字列の合計サイズの上限
+const MAX_ARG_STRINGS_SIZE: u64 = 64 * 1024;
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 struct Elf64Header {
@@ -62,9 +70,15 @@ pub struct LoadedElf {
     pub entry: u64,
     pub stack_top: u64,
     pub stack_bottom: u64,
+    /// プログラムヘッダの仮想アドレス（auxvのAT_PHDR）
+    pub phdr: u64,
 }
 
-pub fn load_elf(data: &[u8]) -> Result<LoadedElf> {
+/// ELFをアドレス空間`space`へロード
+///
+/// セグメントの内容は物理フレームのカーネル側エイリアスを経由して書き込むため、
+/// `space`がアクティブである必要はない
+pub fn load_elf(space: &mut AddressSpace, data: &[u8]) -> Result<LoadedElf> {
     let header = parse_header(data)?;
     validate_header(header)?;
 
@@ -74,6 +88,11 @@ pub fn load_elf(data: &[u8]) -> Result<LoadedElf> {
     let phentsize = header.e_phentsize as usize;
     let phnum = header.e_phnum as usize;
 
+    // 最も高いPT_LOADセグメントの終端（プログラムブレークの初期値）
+    let mut image_end = 0u64;
+    // プログラムヘッダを含むPT_LOADセグメントから求めたその仮想アドレス
+    let mut phdr_vaddr = None;
+
     for i in 0..phnum {
         let off = phoff + i * phentsize;
         let phdr = read_phdr(data, off)?;
@@ -92,100 +111,115 @@ pub fn load_elf(data: &[u8]) -> Result<LoadedElf> {
             return Err(KernelError::Memory(MemoryError::InvalidAddress));
         }
 
-        let mut flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
-        // ロード時は常に書き込み可能にする（初期データコピーのため）
-        // TODO: 実行後にPF_Wに応じて保護属性を調整する
-        flags |= PageTableFlags::WRITABLE;
-        if phdr.p_flags & PF_X == 0 {
-            flags |= PageTableFlags::NO_EXECUTE;
-        }
+        let flags = segment_flags(phdr.p_flags)?;
 
+        // 領域だけ予約し、ページはファイル内容の書き込み時かフォルト時に割り当てる。
+        // 内容は物理フレームのカーネル側エイリアス経由で書き込むため、
+        // 最初から最終的な保護属性（text=RX, rodata=R, data=RW）でマップできる
         let vaddr = phdr.p_vaddr.wrapping_add(load_bias);
-        user::map_user_range(vaddr, phdr.p_memsz, flags)?;
+        if (phdr.p_offset..phdr.p_offset + phdr.p_filesz).contains(&header.e_phoff) {
+            phdr_vaddr.get_or_insert(vaddr + (header.e_phoff - phdr.p_offset));
+        }
+        image_end = image_end.max(vaddr.saturating_add(phdr.p_memsz));
+        let kind = if filesz == 0 { RegionKind::Bss } else { RegionKind::Elf };
+        if let Err(e) = user::reserve_user_range(space, vaddr, phdr.p_memsz, flags, kind) {
+            // 端のページを共有する前のセグメントと合わせると書き込み可能かつ実行可能になる
+            if e == KernelError::Memory(MemoryError::PermissionDenied) {
+                crate::warn!(
+                    "elf: PT_LOAD segment {} ({:#x}..{:#x}) shares a page with a previous segment that would become writable and executable",
+                    i,
+                    vaddr,
+                    vaddr.saturating_add(phdr.p_memsz)
+                );
+            }
+            return Err(e);
+        }
 
-        unsafe {
-            let dst = vaddr as *mut u8;
-            let src = data.as_ptr().add(phdr.p_offset as usize);
-            core::ptr::copy_nonoverlapping(src, dst, filesz);
+        let src = &data[phdr.p_offset as usize..file_end];
+        space.write_bytes(vaddr, src)?;
 
-            if memsz > filesz {
-                core::ptr::write_bytes(dst.add(filesz), 0, memsz - filesz);
-            }
+        // ファイル内容の最終ページの残りだけをゼロクリア（以降のBSSページは割り当て時にゼロ）
+        if memsz > filesz && filesz > 0 {
+            let file_end_vaddr = vaddr + filesz as u64;
+            let page_end = (file_end_vaddr + 0xFFF) & !0xFFF;
+            let zero_len = core::cmp::min(page_end - file_end_vaddr, (memsz - filesz) as u64);
+            space.zero(file_end_vaddr, zero_len as usize)?;
         }
     }
 
     if load_bias != 0 {
-        apply_relocations(data, header, load_bias)?;
+        apply_relocations(space, data, header, load_bias)?;
     }
 
-    let stack = user::alloc_user_stack(8)?;
+    apply_relro(space, data, header, load_bias)?;
+
+    space.init_program_break(image_end);
+
+    let stack = user::alloc_user_stack(space, user::USER_STACK_PAGES)?;
 
     Ok(LoadedElf {
         entry: header.e_entry.wrapping_add(load_bias),
         stack_top: stack.top,
         stack_bottom: stack.bottom,
+        phdr: phdr_vaddr.unwrap_or(load_bias.wrapping_add(header.e_phoff)),
     })
 }
 
-pub fn spawn_service(path: &str, name: &'static str) -> Result<()> {
-    let data = init::fs::read(path).ok_or(KernelError::InvalidParam)?;
-    let loaded = load_elf(data)?;
+/// initfsのELFをinitプロセスの子のサービスとして起動する
+pub fn spawn_service(path: &str, name: &str) -> Result<ProcessId> {
+    spawn_process(path, name, &[path], &[], PrivilegeLevel::Service, init_process_id())
+}
+
+/// initfsのELFを新しいプロセスとして起動し、プロセスIDを返す
+///
+/// スタックにはargc/argv/envp/auxvを積む。Ring0での実行は対応しない
+pub fn spawn_process(
+    path: &str,
+    name: &str,
+    argv: &[&str],
+    envp: &[&str],
+    privilege: PrivilegeLevel,
+    parent: Option<ProcessId>,
+) -> Result<ProcessId> {
+    let priority = match privilege {
+        PrivilegeLevel::Core => return Err(KernelError::InvalidParam),
+        PrivilegeLevel::Service => SERVICE_PRIORITY,
+        PrivilegeLevel::User => DEFAULT_PRIORITY,
+    };
+
+    let data = init::fs::read(path)?;
+    let mut space = AddressSpace::new()?;
+    let loaded = load_elf(&mut space, &data)?;
 
-    // Services run in Ring3 (Service), not Core
-    let process = Process::new(name, PrivilegeLevel::Service, None, 1);
+    let header = parse_header(&data)?;
+    let sp = build_initial_stack(&mut space, &loaded, header, argv, envp)?;
+
+    let mut process = Process::new(name, privilege, parent, priority);
+    process.set_address_space(space);
     let pid = process.id();
 
     if add_process(process).is_none() {
         return Err(KernelError::Process(ProcessError::MaxProcessesReached));
     }
 
-    // Allocate a kernel stack (pages) for the service thread and map frames
-    let stack_size = (loaded.stack_top - loaded.stack_bottom) as usize;
-    let page_size: usize = 4096;
-    let pages = (stack_size + page_size - 1) / page_size;
-
-    // Allocate physical frames and map them immediately into kernel virtual space
-    let first_frame = frame::allocate_frame()?;
-    let first_phys = first_frame.start_address().as_u64();
-    let phys_offset = crate::mem::paging::physical_memory_offset();
-    let kernel_stack_addr = first_phys + phys_offset;
-
-    // Map the first frame
-    let page = Page::containing_address(VirtAddr::new(kernel_stack_addr));
-    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
-    crate::mem::paging::map_page(page, first_frame, flags)?;
-
-    // Allocate and map remaining frames
-    for i in 1..pages {
-        let f = frame::allocate_frame()?;
-        let vaddr = kernel_stack_addr + (i as u64) * (page_size as u64);
-        let page = Page::containing_address(VirtAddr::new(vaddr));
-        crate::mem::paging::map_page(page, f, flags)?;
+    if let Err(e) = add_user_thread(pid, name, Context::user(loaded.entry, sp)) {
+        remove_process(pid);
+        return Err(e);
     }
 
-    let entry_fn: fn() -> ! = unsafe { core::mem::transmute(loaded.entry) };
-    // Create thread with kernel stack, then set its context.rsp to the user stack
-    let mut thread = Thread::new(pid, name, entry_fn, kernel_stack_addr, pages * page_size);
-
-    // Build initial user stack: argc/argv/envp/auxv and strings
-    // We'll place strings at lower addresses and pointers/auxv above them.
-    let mut sp = loaded.stack_top;
-
-    // argv[0] = path
-    let argv0 = path.as_bytes();
-    // store argv0 string
-    sp = sp.saturating_sub((argv0.len() + 1) as u64);
-    unsafe {
-        let dst = sp as *mut u8;
-        core::ptr::copy_nonoverlapping(argv0.as_ptr(), dst, argv0.len());
-        *dst.add(argv0.len()) = 0;
-    }
-    let argv0_addr = sp;
-
-    // Align stack to 16 bytes
-    sp &= !0xF;
+    Ok(pid)
+}
 
-    // auxv entries: (key, val) pairs
+/// 初期ユーザースタック（argc/argv/envp/auxvと文字列）を構築し、開始時のRSPを返す
+///
+/// 文字列を上位に置き、その下にポインタ配列とauxvを積む。RSPはargcを指し、16バイト境界に揃える
+fn build_initial_stack(
+    space: &mut AddressSpace,
+    loaded: &LoadedElf,
+    header: Elf64Header,
+    argv: &[&str],
+    envp: &[&str],
+) -> Result<u64> {
     const AT_NULL: u64 = 0;
     const AT_PHDR: u64 = 3;
     const AT_PHENT: u64 = 4;
@@ -193,61 +227,111 @@ pub fn spawn_service(path: &str, name: &'static str) -> Result<()> {
     const AT_PAGESZ: u64 = 6;
     const AT_ENTRY: u64 = 9;
 
-    // Fetch ELF header again to compute phdr addr and counts
-    let header = parse_header(data)?;
-    let load_bias = if header.e_type == ET_DYN { PIE_LOAD_BIAS } else { 0 };
-    let at_phdr = load_bias.wrapping_add(header.e_phoff);
-    let at_phent = header.e_phentsize as u64;
-    let at_phnum = header.e_phnum as u64;
-
-    // push auxv (key,val) ... AT_NULL
-    let mut push_u64 = |val: u64| {
-        sp = sp.saturating_sub(8);
-        unsafe { *(sp as *mut u64) = val; }
+    let strings_size: usize = argv.iter().chain(envp).map(|s| s.len() + 1).sum();
+    if strings_size as u64 > MAX_ARG_STRINGS_SIZE {
+        return Err(KernelError::InvalidParam);
+    }
+
+    let mut sp = loaded.stack_top;
+
+    // 文字列（NUL終端）を配置
+    let mut push_str = |sp: &mut u64, s: &str| -> Result<u64> {
+        *sp -= (s.len() + 1) as u64;
+        space.write_bytes(*sp, s.as_bytes())?;
+        space.write_bytes(*sp + s.len() as u64, &[0])?;
+        Ok(*sp)
     };
+    let mut argv_addrs = Vec::new();
+    argv_addrs.try_reserve_exact(argv.len()).map_err(|_| KernelError::Memory(MemoryError::OutOfMemory))?;
+    for arg in argv {
+        argv_addrs.push(push_str(&mut sp, arg)?);
+    }
+    let mut envp_addrs = Vec::new();
+    envp_addrs.try_reserve_exact(envp.len()).map_err(|_| KernelError::Memory(MemoryError::OutOfMemory))?;
+    for env in envp {
+        envp_addrs.push(push_str(&mut sp, env)?);
+    }
 
-    // AT_NULL
-    push_u64(0);
-    push_u64(AT_NULL);
+    let auxv = [
+        (AT_PHDR, loaded.phdr),
+        (AT_PHENT, header.e_phentsize as u64),
+        (AT_PHNUM, header.e_phnum as u64),
+        (AT_PAGESZ, 4096),
+        (AT_ENTRY, loaded.entry),
+        (AT_NULL, 0),
+    ];
+
+    // 積む値の数が奇数ならパディングを入れ、argcの位置を16バイト境界にする
+    let words = 1 + (argv.len() + 1) + (envp.len() + 1) + auxv.len() * 2;
+    sp &= !0xF;
+    if words % 2 == 1 {
+        sp -= 8;
+    }
 
-    // AT_ENTRY
-    push_u64(loaded.entry);
-    push_u64(AT_ENTRY);
+    let mut push_u64 = |val: u64| -> Result<()> {
+        sp -= 8;
+        space.write_u64(sp, val)
+    };
 
-    // AT_PAGESZ
-    push_u64(4096);
-    push_u64(AT_PAGESZ);
+    for &(key, val) in auxv.iter().rev() {
+        push_u64(val)?;
+        push_u64(key)?;
+    }
 
-    // AT_PHNUM
-    push_u64(at_phnum);
-    push_u64(AT_PHNUM);
+    push_u64(0)?;
+    for &addr in envp_addrs.iter().rev() {
+        push_u64(addr)?;
+    }
 
-    // AT_PHENT
-    push_u64(at_phent);
-    push_u64(AT_PHENT);
+    push_u64(0)?;
+    for &addr in argv_addrs.iter().rev() {
+        push_u64(addr)?;
+    }
 
-    // AT_PHDR
-    push_u64(at_phdr);
-    push_u64(AT_PHDR);
+    push_u64(argv.len() as u64)?;
 
-    // envp NULL terminator (no env)
-    push_u64(0);
+    Ok(sp)
+}
 
-    // argv pointers (argv[0], NULL)
-    push_u64(0); // argv NULL
-    push_u64(argv0_addr);
+/// セグメントのp_flagsからページの保護属性を決める
+///
+/// 書き込みと実行の両方を要求するセグメントは拒否する（W^X）
+fn segment_flags(p_flags: u32) -> Result<PageTableFlags> {
+    let writable = p_flags & PF_W != 0;
+    let executable = p_flags & PF_X != 0;
+    if writable && executable {
+        return Err(KernelError::Memory(MemoryError::PermissionDenied));
+    }
 
-    // argc
-    push_u64(1);
+    let mut flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    if writable {
+        flags |= PageTableFlags::WRITABLE;
+    }
+    if !executable {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+    Ok(flags)
+}
 
-    // final alignment: ensure %16 == 0
-    sp &= !0xF;
+/// PT_GNU_RELROの範囲を読み取り専用にする（再配置の適用後に呼ぶ）
+fn apply_relro(space: &mut AddressSpace, data: &[u8], header: Elf64Header, load_bias: u64) -> Result<()> {
+    let phoff = header.e_phoff as usize;
+    let phentsize = header.e_phentsize as usize;
+    let phnum = header.e_phnum as usize;
 
-    thread.context_mut().rsp = sp as u64;
-    thread.context_mut().rbp = 0;
+    for i in 0..phnum {
+        let phdr = read_phdr(data, phoff + i * phentsize)?;
+        if phdr.p_type != PT_GNU_RELRO {
+            continue;
+        }
 
-    if add_thread(thread).is_none() {
-        return Err(KernelError::Process(ProcessError::MaxProcessesReached));
+        // 終端は切り捨て（最終ページの残りは通常のデータと共有しているため書き込み可能のまま）
+        let start = phdr.p_vaddr.wrapping_add(load_bias) & !0xFFF;
+        let end = phdr.p_vaddr.wrapping_add(load_bias).saturating_add(phdr.p_memsz) & !0xFFF;
+        if end > start {
+            let flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE | PageTableFlags::NO_EXECUTE;
+            space.protect_range(start, end - start, flags)?;
+        }
     }
 
     Ok(())
@@ -289,7 +373,7 @@ struct Elf64Rela {
     r_addend: i64,
 }
 
-fn apply_relocations(data: &[u8], header: Elf64Header, load_bias: u64) -> Result<()> {
+fn apply_relocations(space: &mut AddressSpace, data: &[u8], header: Elf64Header, load_bias: u64) -> Result<()> {
     let mut rela_addr = None;
     let mut rela_size = None;
     let mut rela_ent = None;
@@ -326,11 +410,9 @@ fn apply_relocations(data: &[u8], header: Elf64Header, load_bias: u64) -> Result
         let rela = read_rela(data, off)?;
         let r_type = (rela.r_info & 0xffffffff) as u32;
         if r_type == R_X86_64_RELATIVE {
-            let reloc_addr = load_bias.wrapping_add(rela.r_offset) as *mut u64;
+            let reloc_addr = load_bias.wrapping_add(rela.r_offset);
             let value = load_bias.wrapping_add(rela.r_addend as u64);
-            unsafe {
-                reloc_addr.write(value);
-            }
+            space.write_u64(reloc_addr, value)?;
         }
     }
 
@@ -0,0 +1,202 @@
+//! プロセスの終了と回収
+//!
+//! 終了したプロセスは終了コードを保持したゾンビとなり、親が`wait_child`で回収するまで残る。
+//! 親が先に終了した場合、子はinitプロセスに引き取られる。
+//! initの子のゾンビは`reap_orphans`がタイマー割り込みの延長で回収する。
+//! 回収時にスレッドのカーネルスタックとメールボックス、アドレス空間のフレームをすべて解放する。
+//! 終了したスレッドは参加（`thread_join`）がなくても`reap_threads`が同じく回収し、
+//! 終了コードだけをプロセスに残す。
+
+use alloc::vec::Vec;
+
+use crate::error::{KernelError, MemoryError, ProcessError, Result};
+use crate::interrupt::spinlock::SpinLock;
+
+use super::ids::{ProcessId, ProcessState, ThreadId, ThreadState};
+use super::process::{init_process_id, PROCESS_TABLE};
+use super::scheduler::wake_thread;
+use super::sleep::block_current_until;
+use super::thread::{current_thread_id, remove_thread, THREAD_QUEUE};
+
+/// 1回の`reap_orphans`/`reap_threads`で回収する数の上限（割り込み処理を長引かせないため）
+const REAP_BATCH: usize = 8;
+
+/// 子プロセスの終了を待っているスレッド（親プロセスID, スレッドID）
+static CHILD_WAITERS: SpinLock<Vec<(ProcessId, ThreadId)>> = SpinLock::new(Vec::new());
+
+/// プロセスを終了させてゾンビにする
+///
+/// すべてのスレッドをTerminated状態にし、子プロセスをinitに引き取らせ、
+/// 親にSIGCHLDを送って終了を待っている親のスレッドを起床させる。実行中のスレッドの切り替えは呼び出し側で行う
+pub fn exit_process(pid: ProcessId, code: u64) {
+    {
+        let mut queue = THREAD_QUEUE.lock();
+        for thread in queue.iter_mut().filter(|t| t.process_id() == pid) {
+            thread.set_state(ThreadState::Terminated);
+        }
+    }
+
+    let parent = {
+        let mut table = PROCESS_TABLE.lock();
+        let parent = match table.get_mut(pid) {
+            Some(process) if process.state() != ProcessState::Zombie => {
+                process.set_exit_code(code);
+                process.set_state(ProcessState::Zombie);
+                process.parent_id()
+            }
+            _ => return,
+        };
+
+        let init = init_process_id();
+        for child in table.iter_mut().filter(|p| p.parent_id() == Some(pid)) {
+            child.set_parent_id(init);
+        }
+        parent
+    };
+
+    crate::debug!("Process {:?} exited with code {:#x}", pid, code);
+
+    if let Some(parent) = parent {
+        let _ = super::signal::send_signal(parent, super::signal::SIGCHLD);
+        let waiters: Vec<ThreadId> = CHILD_WAITERS
+            .lock()
+            .iter()
+            .filter(|(p, _)| *p == parent)
+            .map(|&(_, id)| id)
+            .collect();
+        for id in waiters {
+            wake_thread(id);
+        }
+    }
+}
+
+/// ゾンビのプロセスを回収する
+///
+/// スレッドとプロセスを削除して資源を解放し、終了コードを返す。
+/// プロセスのアドレス空間はProcessの破棄とともに解放される
+fn reap(pid: ProcessId) -> Option<u64> {
+    let process = PROCESS_TABLE.lock().remove(pid)?;
+    // Wait中に終了させられたスレッドの登録を取り除く
+    CHILD_WAITERS.lock().retain(|&(parent, _)| parent != pid);
+
+    let threads: Vec<ThreadId> = THREAD_QUEUE
+        .lock()
+        .iter_by_process(pid)
+        .map(|t| t.id())
+        .collect();
+    for id in threads {
+        remove_thread(id);
+    }
+
+    process.exit_code()
+}
+
+/// 子プロセスの終了を待って回収し、（プロセスID, 終了コード）を返す
+///
+/// `target`がNoneの場合はいずれかの子を対象とする。
+/// `nohang`が真で終了した子がない場合はNoneを返す。対象となる子がない場合は`NoChild`
+pub fn wait_child(parent: ProcessId, target: Option<ProcessId>, nohang: bool) -> Result<Option<(ProcessId, u64)>> {
+    wait_child_until(parent, target, if nohang { Some(0) } else { None })
+}
+
+/// 期限（ティック）までに終了した子プロセスを回収する
+///
+/// `wait_child`と同じだが、`deadline`を過ぎても終了した子がない場合はNoneを返す
+pub fn wait_child_until(
+    parent: ProcessId,
+    target: Option<ProcessId>,
+    deadline: Option<u64>,
+) -> Result<Option<(ProcessId, u64)>> {
+    let current = current_thread_id().ok_or(KernelError::Process(ProcessError::ProcessNotFound))?;
+
+    loop {
+        // システムコール中は割り込み禁止のため、待ち行列への登録からブロックまでの間に
+        // 子プロセスの終了処理が割り込むことはない
+        let zombie = {
+            let table = PROCESS_TABLE.lock();
+            let mut children = table
+                .iter()
+                .filter(|p| p.parent_id() == Some(parent))
+                .filter(|p| target.is_none_or(|t| p.id() == t))
+                .peekable();
+            if children.peek().is_none() {
+                CHILD_WAITERS.lock().retain(|&(_, id)| id != current);
+                return Err(KernelError::Process(ProcessError::NoChild));
+            }
+            children.find(|p| p.state() == ProcessState::Zombie).map(|p| p.id())
+        };
+
+        if let Some(pid) = zombie {
+            CHILD_WAITERS.lock().retain(|&(_, id)| id != current);
+            let code = reap(pid).unwrap_or(0);
+            return Ok(Some((pid, code)));
+        }
+
+        if deadline.is_some_and(|d| crate::interrupt::timer::get_ticks() >= d) {
+            CHILD_WAITERS.lock().retain(|&(_, id)| id != current);
+            return Ok(None);
+        }
+
+        {
+            let mut waiters = CHILD_WAITERS.lock();
+            if !waiters.contains(&(parent, current)) {
+                waiters
+                    .try_reserve(1)
+                    .map_err(|_| KernelError::Memory(MemoryError::OutOfMemory))?;
+                waiters.push((parent, current));
+            }
+        }
+        block_current_until(deadline);
+    }
+}
+
+/// 親のいない（initの子の）ゾンビを回収する
+///
+/// 終了したスレッド自身のスタック上では解放できないため、別のスレッドの
+/// 実行中（タイマー割り込み）に呼ぶ
+pub fn reap_orphans() {
+    let init = init_process_id();
+    let mut orphans = [None; REAP_BATCH];
+    {
+        let table = PROCESS_TABLE.lock();
+        let zombies = table
+            .iter()
+            .filter(|p| p.state() == ProcessState::Zombie)
+            .filter(|p| p.parent_id().is_none() || p.parent_id() == init);
+        for (slot, process) in orphans.iter_mut().zip(zombies) {
+            *slot = Some(process.id());
+        }
+    }
+
+    for pid in orphans.into_iter().flatten() {
+        let code = reap(pid);
+        crate::debug!("Reaped orphan process {:?} (exit code {:?})", pid, code);
+    }
+}
+
+/// 終了したスレッドを回収する
+///
+/// 実行中でない終了済みのスレッドを削除してカーネルスタック、FPUの領域、メールボックスを解放し、
+/// 終了コードをプロセスに記録する（後から`thread_join`で受け取れる）。
+/// `reap_orphans`と同じく、別のスレッドの実行中（タイマー割り込み）に呼ぶ
+pub fn reap_threads() {
+    let current = current_thread_id();
+    let mut finished = [None; REAP_BATCH];
+    {
+        let queue = THREAD_QUEUE.lock();
+        let threads = queue
+            .iter()
+            .filter(|t| t.state() == ThreadState::Terminated && Some(t.id()) != current);
+        for (slot, thread) in finished.iter_mut().zip(threads) {
+            *slot = Some((thread.id(), thread.process_id(), thread.exit_code().unwrap_or(0)));
+        }
+    }
+
+    for (id, pid, code) in finished.into_iter().flatten() {
+        if let Some(process) = PROCESS_TABLE.lock().get_mut(pid) {
+            process.record_thread_exit(id, code);
+        }
+        CHILD_WAITERS.lock().retain(|&(_, waiter)| waiter != id);
+        remove_thread(id);
+    }
+}
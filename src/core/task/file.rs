@@ -0,0 +1,113 @@
+//! プロセスごとのファイルディスクリプタ表
+//!
+//! Linux互換のシステムコールが使う。開けるのはinitfs上のファイル（読み取り専用）と
+//! コンソールのみで、ファイルの内容は`open`の時点でコピーして保持する。
+//! 新しいプロセスはfd 0/1/2にコンソールを開いた状態で始まる。
+
+use alloc::vec::Vec;
+
+/// 1プロセスが同時に開けるファイルの数
+pub const MAX_FILES: usize = 64;
+
+/// 開いているファイルの種類
+#[derive(Debug)]
+pub enum FileKind {
+    /// コンソール（読み取りはキーボード、書き込みはシリアルとVGA）
+    Console,
+    /// initfs上のファイル（内容のコピー）
+    Initfs(Vec<u8>),
+}
+
+/// 開いているファイル
+#[derive(Debug)]
+pub struct OpenFile {
+    kind: FileKind,
+    /// 次に読み書きする位置
+    offset: u64,
+}
+
+impl OpenFile {
+    /// コンソールを開いたファイルを作成
+    pub const fn console() -> Self {
+        Self {
+            kind: FileKind::Console,
+            offset: 0,
+        }
+    }
+
+    /// initfsのファイルの内容から作成
+    pub fn initfs(data: Vec<u8>) -> Self {
+        Self {
+            kind: FileKind::Initfs(data),
+            offset: 0,
+        }
+    }
+
+    /// ファイルの種類を取得
+    pub fn kind(&self) -> &FileKind {
+        &self.kind
+    }
+
+    /// 現在の位置を取得
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// 現在の位置を設定
+    pub fn set_offset(&mut self, offset: u64) {
+        self.offset = offset;
+    }
+}
+
+/// ファイルディスクリプタ表
+#[derive(Debug)]
+pub struct FileTable {
+    files: Vec<Option<OpenFile>>,
+}
+
+impl FileTable {
+    /// fd 0/1/2にコンソールを開いた表を作成
+    pub fn new() -> Self {
+        let mut files = Vec::new();
+        files.extend((0..3).map(|_| Some(OpenFile::console())));
+        Self { files }
+    }
+
+    /// 空いている最小の番号にファイルを登録する（表が満杯の場合はNone）
+    pub fn insert(&mut self, file: OpenFile) -> Option<usize> {
+        if let Some(fd) = self.files.iter().position(Option::is_none) {
+            self.files[fd] = Some(file);
+            return Some(fd);
+        }
+        if self.files.len() >= MAX_FILES {
+            return None;
+        }
+        self.files.push(Some(file));
+        Some(self.files.len() - 1)
+    }
+
+    /// 番号でファイルを取得
+    pub fn get(&self, fd: usize) -> Option<&OpenFile> {
+        self.files.get(fd)?.as_ref()
+    }
+
+    /// 番号でファイルの可変参照を取得
+    pub fn get_mut(&mut self, fd: usize) -> Option<&mut OpenFile> {
+        self.files.get_mut(fd)?.as_mut()
+    }
+
+    /// ファイルを閉じ、登録されていたファイルを返す
+    pub fn remove(&mut self, fd: usize) -> Option<OpenFile> {
+        let file = self.files.get_mut(fd)?.take();
+        while self.files.last().is_some_and(Option::is_none) {
+            self.files.pop();
+        }
+        file
+    }
+}
+
+impl Default for FileTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,295 @@
+//! FPU/SSE/AVXの状態管理
+//!
+//! 状態の退避と復元は遅延して行う。スレッド切り替え時はCR0.TSを立てるだけにし、
+//! 切り替え後のスレッドが最初にFPU/SIMD命令を使った時点の#NMで、
+//! 前の所有者の状態を退避して自スレッドの状態を復元する。
+//! XSAVEが使える場合はXSAVE/XRSTOR、使えない場合はFXSAVE/FXRSTORを使う。
+
+use alloc::vec::Vec;
+use core::alloc::Layout;
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags};
+use x86_64::registers::xcontrol::{XCr0, XCr0Flags};
+
+use crate::error::{KernelError, MemoryError, ProcessError, Result};
+use crate::interrupt::spinlock::SpinLock;
+
+use super::ids::ThreadId;
+
+/// FXSAVE領域のサイズ
+const FXSAVE_AREA_SIZE: usize = 512;
+/// 保存領域のアラインメント（XSAVEは64バイト境界を要求）
+const AREA_ALIGN: usize = 64;
+
+/// 保存領域内のFCW（x87制御ワード）のオフセットと初期値
+const FCW_OFFSET: usize = 0;
+const FCW_DEFAULT: u16 = 0x037F;
+/// 保存領域内のMXCSRのオフセットと初期値
+const MXCSR_OFFSET: usize = 24;
+const MXCSR_DEFAULT: u32 = 0x1F80;
+/// MXCSRのうち書き込んでよいビット（予約ビットを立てるとFXRSTOR/XRSTORが#GPになる）
+const MXCSR_WRITABLE: u32 = 0xFFBF;
+/// XSAVEヘッダのオフセットとサイズ
+const XSAVE_HEADER_OFFSET: usize = 512;
+const XSAVE_HEADER_SIZE: usize = 64;
+
+/// XSAVEを使うかどうか
+static USE_XSAVE: AtomicBool = AtomicBool::new(false);
+/// 保存領域のサイズ
+static AREA_SIZE: AtomicUsize = AtomicUsize::new(FXSAVE_AREA_SIZE);
+/// 現在FPUレジスタに状態が載っているスレッド
+static OWNER: SpinLock<Option<ThreadId>> = SpinLock::new(None);
+
+/// スレッドごとのFPU状態の保存領域
+pub struct FpuState {
+    area: *mut u8,
+    layout: Layout,
+}
+
+// 保存領域はスレッドが排他的に所有する
+unsafe impl Send for FpuState {}
+
+impl FpuState {
+    /// 初期状態の保存領域を確保（確保できない場合はNone）
+    pub fn new() -> Option<Self> {
+        let layout = Layout::from_size_align(AREA_SIZE.load(Ordering::Relaxed), AREA_ALIGN).ok()?;
+        let area = unsafe { alloc::alloc::alloc_zeroed(layout) };
+        if area.is_null() {
+            return None;
+        }
+        unsafe {
+            area.add(FCW_OFFSET).cast::<u16>().write(FCW_DEFAULT);
+            area.add(MXCSR_OFFSET).cast::<u32>().write(MXCSR_DEFAULT);
+        }
+        Some(Self { area, layout })
+    }
+
+    /// 保存領域の内容
+    fn bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.area, self.layout.size()) }
+    }
+
+    /// 保存領域の内容（可変）
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.area, self.layout.size()) }
+    }
+
+    /// FPUレジスタの内容を保存領域へ退避
+    fn save(&mut self) {
+        unsafe {
+            if USE_XSAVE.load(Ordering::Relaxed) {
+                core::arch::asm!(
+                    "xsave64 [{}]",
+                    in(reg) self.area,
+                    in("eax") u32::MAX,
+                    in("edx") u32::MAX,
+                    options(nostack, preserves_flags)
+                );
+            } else {
+                core::arch::asm!("fxsave64 [{}]", in(reg) self.area, options(nostack, preserves_flags));
+            }
+        }
+    }
+
+    /// 保存領域の内容をFPUレジスタへ復元
+    fn restore(&self) {
+        unsafe {
+            if USE_XSAVE.load(Ordering::Relaxed) {
+                core::arch::asm!(
+                    "xrstor64 [{}]",
+                    in(reg) self.area,
+                    in("eax") u32::MAX,
+                    in("edx") u32::MAX,
+                    options(nostack, preserves_flags)
+                );
+            } else {
+                core::arch::asm!("fxrstor64 [{}]", in(reg) self.area, options(nostack, preserves_flags));
+            }
+        }
+    }
+}
+
+impl Drop for FpuState {
+    fn drop(&mut self) {
+        unsafe { alloc::alloc::dealloc(self.area, self.layout) };
+    }
+}
+
+/// FPU/SSEを有効化し、XSAVEが使えるか検出する
+pub fn init() {
+    unsafe {
+        // x87をハードウェアで実行し、TS=1のときWAIT/FWAITも#NMにする
+        Cr0::update(|cr0| {
+            cr0.remove(Cr0Flags::EMULATE_COPROCESSOR);
+            cr0.insert(Cr0Flags::MONITOR_COPROCESSOR | Cr0Flags::NUMERIC_ERROR);
+        });
+        Cr4::update(|cr4| {
+            cr4.insert(Cr4Flags::OSFXSR | Cr4Flags::OSXMMEXCPT_ENABLE);
+        });
+    }
+
+    let features = __cpuid(1);
+    let has_xsave = features.ecx & (1 << 26) != 0;
+    let has_avx = features.ecx & (1 << 28) != 0;
+
+    if has_xsave {
+        unsafe {
+            Cr4::update(|cr4| cr4.insert(Cr4Flags::OSXSAVE));
+            let mut xcr0 = XCr0Flags::X87 | XCr0Flags::SSE;
+            if has_avx {
+                xcr0 |= XCr0Flags::AVX;
+            }
+            XCr0::write(xcr0);
+        }
+        // 有効にした機能の保存に必要なサイズ
+        let size = __cpuid_count(0xD, 0).ebx as usize;
+        AREA_SIZE.store(size.max(FXSAVE_AREA_SIZE), Ordering::Relaxed);
+        USE_XSAVE.store(true, Ordering::Relaxed);
+    }
+
+    crate::info!(
+        "FPU: {} (save area {} bytes, AVX={})",
+        if has_xsave { "XSAVE" } else { "FXSAVE" },
+        AREA_SIZE.load(Ordering::Relaxed),
+        has_xsave && has_avx
+    );
+
+    // 最初にFPUを使うスレッドで#NMを発生させる
+    set_task_switched(true);
+}
+
+/// CR0.TSを設定
+fn set_task_switched(on: bool) {
+    unsafe {
+        if on {
+            Cr0::update(|cr0| cr0.insert(Cr0Flags::TASK_SWITCHED));
+        } else {
+            core::arch::asm!("clts", options(nomem, nostack, preserves_flags));
+        }
+    }
+}
+
+/// スレッド切り替え時に呼ぶ
+///
+/// 切り替え先がFPUの所有者でなければCR0.TSを立て、最初のFPU命令で#NMを発生させる
+pub fn on_switch(next: ThreadId) {
+    set_task_switched(*OWNER.lock() != Some(next));
+}
+
+/// #NM（デバイス使用不可例外）の処理
+///
+/// 前の所有者の状態を退避し、現在のスレッドの状態を復元する
+pub fn handle_device_not_available() -> Result<()> {
+    set_task_switched(false);
+
+    let current = super::current_thread_id().ok_or(KernelError::Process(ProcessError::ProcessNotFound))?;
+    let mut owner = OWNER.lock();
+    if *owner == Some(current) {
+        return Ok(());
+    }
+
+    let mut queue = super::thread::THREAD_QUEUE.lock();
+    if let Some(prev) = owner.and_then(|id| queue.get_mut(id)) {
+        if let Some(state) = prev.fpu_state_mut() {
+            state.save();
+        }
+    }
+
+    let thread = queue
+        .get_mut(current)
+        .ok_or(KernelError::Process(ProcessError::ProcessNotFound))?;
+    if thread.fpu_state_mut().is_none() {
+        let state = FpuState::new().ok_or(KernelError::Memory(MemoryError::OutOfMemory))?;
+        thread.set_fpu_state(state);
+    }
+    if let Some(state) = thread.fpu_state_mut() {
+        state.restore();
+    }
+
+    *owner = Some(current);
+    Ok(())
+}
+
+/// スレッドが破棄される際に所有者の記録を消す
+pub fn release(id: ThreadId) {
+    let mut owner = OWNER.lock();
+    if *owner == Some(id) {
+        *owner = None;
+    }
+}
+
+/// スレッドのFPU状態の写しを取る（シグナルハンドラの呼び出し前に使用）
+///
+/// FPUを使っていないスレッドはNone
+pub fn snapshot(id: ThreadId) -> Option<Vec<u8>> {
+    let owner = OWNER.lock();
+    let mut queue = super::thread::THREAD_QUEUE.lock();
+    let state = queue.get_mut(id)?.fpu_state_mut()?;
+    if *owner == Some(id) {
+        // 最新の内容はレジスタにある
+        let ts = Cr0::read().contains(Cr0Flags::TASK_SWITCHED);
+        set_task_switched(false);
+        state.save();
+        set_task_switched(ts);
+    }
+
+    let mut copy = Vec::new();
+    copy.try_reserve_exact(state.bytes().len()).ok()?;
+    copy.extend_from_slice(state.bytes());
+    Some(copy)
+}
+
+/// 写しからスレッドのFPU状態を戻す（シグナルハンドラからの復帰時に使用）
+///
+/// 内容はユーザー空間から読んだものなので、復元時に例外にならないよう予約ビットを落とす。
+/// レジスタの内容は次にFPUを使った時点の#NMで保存領域から読み直す
+pub fn restore_snapshot(id: ThreadId, data: &[u8]) -> Result<()> {
+    if data.len() != AREA_SIZE.load(Ordering::Relaxed) {
+        return Err(KernelError::InvalidParam);
+    }
+
+    let mut owner = OWNER.lock();
+    let mut queue = super::thread::THREAD_QUEUE.lock();
+    let thread = queue
+        .get_mut(id)
+        .ok_or(KernelError::Process(ProcessError::ProcessNotFound))?;
+    if thread.fpu_state_mut().is_none() {
+        let state = FpuState::new().ok_or(KernelError::Memory(MemoryError::OutOfMemory))?;
+        thread.set_fpu_state(state);
+    }
+    if let Some(state) = thread.fpu_state_mut() {
+        let area = state.bytes_mut();
+        area.copy_from_slice(data);
+        sanitize(area);
+    }
+
+    if *owner == Some(id) {
+        *owner = None;
+        set_task_switched(true);
+    }
+    Ok(())
+}
+
+/// 保存領域の予約ビットを落とす
+fn sanitize(area: &mut [u8]) {
+    let mxcsr = u32::from_le_bytes([
+        area[MXCSR_OFFSET],
+        area[MXCSR_OFFSET + 1],
+        area[MXCSR_OFFSET + 2],
+        area[MXCSR_OFFSET + 3],
+    ]) & MXCSR_WRITABLE;
+    area[MXCSR_OFFSET..MXCSR_OFFSET + 4].copy_from_slice(&mxcsr.to_le_bytes());
+
+    if USE_XSAVE.load(Ordering::Relaxed) && area.len() >= XSAVE_HEADER_OFFSET + XSAVE_HEADER_SIZE {
+        let header = &mut area[XSAVE_HEADER_OFFSET..XSAVE_HEADER_OFFSET + XSAVE_HEADER_SIZE];
+        // XSTATE_BVは有効にした機能のみ、XCOMP_BVと予約領域は0（標準形式）
+        let enabled = XCr0::read_raw();
+        let mut bv = [0u8; 8];
+        bv.copy_from_slice(&header[..8]);
+        let bv = u64::from_le_bytes(bv) & enabled;
+        header.fill(0);
+        header[..8].copy_from_slice(&bv.to_le_bytes());
+    }
+}
@@ -60,6 +60,8 @@ pub enum ProcessState {
     Running,
     /// スリープ中（すべてのスレッドがSleeping）
     Sleeping,
+    /// 停止シグナルで停止中
+    Stopped,
     /// ゾンビ（終了したが親に回収されていない）
     Zombie,
     /// 終了済み
@@ -3,26 +3,37 @@
 //! マルチタスク機能を提供（プロセスとスレッドの管理）
 
 pub mod context;
+pub mod exit;
+pub mod file;
 pub mod ids;
 pub mod process;
 pub mod scheduler;
+pub mod signal;
+pub mod sleep;
 pub mod thread;
 pub mod elf;
+pub mod fpu;
 
-pub use context::{switch_context, switch_to_thread, Context};
+pub use context::{switch_frame, Context};
 pub use ids::{PrivilegeLevel, ProcessId, ProcessState, ThreadId, ThreadState};
 pub use process::{
-	add_process, for_each_process, process_count, remove_process, with_process, with_process_mut,
-	Process, ProcessTable,
+	add_process, for_each_process, init_process_id, process_count, remove_process, set_init_process,
+	with_process, with_process_mut, Process, ProcessTable,
 };
 pub use scheduler::{
-	block_current_thread, disable_scheduler, enable_scheduler, init_scheduler, is_scheduler_enabled,
-	schedule, schedule_and_switch, scheduler_tick, set_time_slice, sleep_thread, start_scheduling,
-	terminate_thread, wake_thread, yield_now, Scheduler,
+	block_current_thread, disable_scheduler, enable_scheduler, exit_current_process, exit_current_thread,
+	init_scheduler, is_scheduler_enabled, kill_current_process_from_isr, kill_process,
+	reschedule, schedule, scheduler_tick, set_time_slice, sleep_thread, start_scheduling,
+	terminate_thread, wake_thread, yield_now, Scheduler, DEFAULT_PRIORITY, IDLE_PRIORITY, KILLED_EXIT_CODE,
+	PRIORITY_LEVELS,
 };
+pub use exit::{exit_process, reap_orphans, reap_threads, wait_child, wait_child_until};
+pub use file::{FileKind, FileTable, OpenFile};
+pub use signal::{send_signal, set_action, SigAction, SignalState};
+pub use sleep::{block_current_until, sleep_ticks, sleep_until, wake_expired};
 pub use thread::{
-	add_thread, count_threads_by_state, current_thread_id, for_each_thread, peek_next_thread,
-	remove_thread, set_current_thread, thread_count, with_thread, with_thread_mut, Thread,
-	ThreadQueue,
+	add_kernel_thread, add_thread, add_user_thread, count_threads_by_state, current_process_id, current_thread_id,
+	for_each_thread, peek_next_thread, remove_thread, set_current_thread, thread_count, with_thread,
+	with_thread_mut, Thread, ThreadQueue,
 };
-pub use elf::{load_elf, spawn_service, LoadedElf};
\ No newline at end of file
+pub use elf::{load_elf, spawn_process, spawn_service, LoadedElf};
\ No newline at end of file
@@ -1,6 +1,15 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
 use crate::interrupt::spinlock::SpinLock;
+use crate::mem::AddressSpace;
+
+use super::file::FileTable;
+use super::ids::{PrivilegeLevel, ProcessId, ProcessState, ThreadId};
+use super::signal::SignalState;
 
-use super::ids::{PrivilegeLevel, ProcessId, ProcessState};
+/// 回収済みのスレッドの終了コードを保持する数の上限（超えた分は古いものから捨てる）
+const MAX_THREAD_EXITS: usize = 64;
 
 /// プロセス構造体
 ///
@@ -10,17 +19,27 @@ pub struct Process {
     /// プロセスID
     id: ProcessId,
     /// プロセス名
-    name: &'static str,
+    name: String,
     /// プロセスの状態
     state: ProcessState,
     /// 権限レベル
     privilege: PrivilegeLevel,
     /// 親プロセスID（存在する場合）
     parent_id: Option<ProcessId>,
-    /// ページテーブルのアドレス（メモリ空間）。Noneの場合はカーネル空間を共有。
-    page_table: Option<u64>,
+    /// プロセス専用のアドレス空間。Noneの場合はカーネル空間を共有。
+    address_space: Option<AddressSpace>,
     /// 優先度（0が最高、値が大きいほど低い）
     priority: u8,
+    /// 終了コード（ゾンビになった時点で設定）
+    exit_code: Option<u64>,
+    /// シグナルの状態
+    signals: SignalState,
+    /// 開いているファイル
+    files: FileTable,
+    /// システムコールをログに記録するかどうか
+    traced: bool,
+    /// 参加される前に回収されたスレッドの（スレッドID, 終了コード）
+    thread_exits: Vec<(ThreadId, u64)>,
 }
 
 impl Process {
@@ -32,19 +51,24 @@ impl Process {
     /// * `parent_id` - 親プロセスID
     /// * `priority` - プロセスの優先度
     pub fn new(
-        name: &'static str,
+        name: &str,
         privilege: PrivilegeLevel,
         parent_id: Option<ProcessId>,
         priority: u8,
     ) -> Self {
         Self {
             id: ProcessId::new(),
-            name,
+            name: String::from(name),
             state: ProcessState::Running,
             privilege,
             parent_id,
-            page_table: None, // TODO: ページテーブル実装後に設定
+            address_space: None,
             priority,
+            exit_code: None,
+            signals: SignalState::new(),
+            files: FileTable::new(),
+            traced: false,
+            thread_exits: Vec::new(),
         }
     }
 
@@ -54,8 +78,8 @@ impl Process {
     }
 
     /// プロセス名を取得
-    pub fn name(&self) -> &'static str {
-        self.name
+    pub fn name(&self) -> &str {
+        &self.name
     }
 
     /// プロセスの状態を取得
@@ -78,19 +102,90 @@ impl Process {
         self.parent_id
     }
 
+    /// 親プロセスIDを設定
+    pub fn set_parent_id(&mut self, parent_id: Option<ProcessId>) {
+        self.parent_id = parent_id;
+    }
+
     /// 優先度を取得
     pub fn priority(&self) -> u8 {
         self.priority
     }
 
-    /// ページテーブルアドレスを取得
+    /// 終了コードを取得
+    pub fn exit_code(&self) -> Option<u64> {
+        self.exit_code
+    }
+
+    /// 終了コードを設定
+    pub fn set_exit_code(&mut self, code: u64) {
+        self.exit_code = Some(code);
+    }
+
+    /// 回収したスレッドの終了コードを記録する（`thread_join`が後から受け取る）
+    pub fn record_thread_exit(&mut self, id: ThreadId, code: u64) {
+        if self.thread_exits.len() >= MAX_THREAD_EXITS {
+            self.thread_exits.remove(0);
+        }
+        if self.thread_exits.try_reserve(1).is_ok() {
+            self.thread_exits.push((id, code));
+        }
+    }
+
+    /// 回収済みのスレッドの終了コードを番号で取り出す
+    pub fn take_thread_exit(&mut self, id: u64) -> Option<u64> {
+        let index = self.thread_exits.iter().position(|&(t, _)| t.as_u64() == id)?;
+        Some(self.thread_exits.remove(index).1)
+    }
+
+    /// シグナルの状態を取得
+    pub fn signals(&self) -> &SignalState {
+        &self.signals
+    }
+
+    /// シグナルの状態の可変参照を取得
+    pub fn signals_mut(&mut self) -> &mut SignalState {
+        &mut self.signals
+    }
+
+    /// ファイルディスクリプタ表を取得
+    pub fn files(&self) -> &FileTable {
+        &self.files
+    }
+
+    /// ファイルディスクリプタ表の可変参照を取得
+    pub fn files_mut(&mut self) -> &mut FileTable {
+        &mut self.files
+    }
+
+    /// システムコールをトレースするかどうか
+    pub fn is_traced(&self) -> bool {
+        self.traced
+    }
+
+    /// システムコールのトレースを設定
+    pub fn set_traced(&mut self, traced: bool) {
+        self.traced = traced;
+    }
+
+    /// ページテーブルアドレス（CR3に設定する値）を取得
     pub fn page_table(&self) -> Option<u64> {
-        self.page_table
+        self.address_space.as_ref().map(|space| space.cr3())
+    }
+
+    /// アドレス空間を取得
+    pub fn address_space(&self) -> Option<&AddressSpace> {
+        self.address_space.as_ref()
+    }
+
+    /// アドレス空間の可変参照を取得
+    pub fn address_space_mut(&mut self) -> Option<&mut AddressSpace> {
+        self.address_space.as_mut()
     }
 
-    /// ページテーブルアドレスを設定
-    pub fn set_page_table(&mut self, page_table: u64) {
-        self.page_table = Some(page_table);
+    /// アドレス空間を設定
+    pub fn set_address_space(&mut self, space: AddressSpace) {
+        self.address_space = Some(space);
     }
 }
 
@@ -103,9 +198,11 @@ impl core::fmt::Debug for Process {
             .field("state", &self.state)
             .field("privilege", &self.privilege)
             .field("parent_id", &self.parent_id)
-            .field("priority", &self.priority);
+            .field("priority", &self.priority)
+            .field("exit_code", &self.exit_code)
+            .field("traced", &self.traced);
 
-        if let Some(pt) = self.page_table {
+        if let Some(pt) = self.page_table() {
             debug_struct.field("page_table", &format_args!("{:#x}", pt));
         } else {
             debug_struct.field("page_table", &None::<u64>);
@@ -119,10 +216,8 @@ impl core::fmt::Debug for Process {
 ///
 /// システム内のすべてのプロセスを管理する
 pub struct ProcessTable {
-    /// プロセスの配列（最大容量）
-    processes: [Option<Process>; Self::MAX_PROCESSES],
-    /// 現在のプロセス数
-    count: usize,
+    /// プロセスの一覧
+    processes: Vec<Process>,
 }
 
 impl ProcessTable {
@@ -131,10 +226,8 @@ impl ProcessTable {
 
     /// 新しいプロセステーブルを作成
     pub const fn new() -> Self {
-        const INIT: Option<Process> = None;
         Self {
-            processes: [INIT; Self::MAX_PROCESSES],
-            count: 0,
+            processes: Vec::new(),
         }
     }
 
@@ -143,36 +236,23 @@ impl ProcessTable {
     /// # Returns
     /// 成功時はプロセスIDを返す。テーブルが満杯の場合はNone
     pub fn add(&mut self, process: Process) -> Option<ProcessId> {
-        if self.count >= Self::MAX_PROCESSES {
+        if self.processes.len() >= Self::MAX_PROCESSES {
             return None;
         }
 
         let id = process.id();
-
-        // 空きスロットを探す
-        for slot in &mut self.processes {
-            if slot.is_none() {
-                *slot = Some(process);
-                self.count += 1;
-                return Some(id);
-            }
-        }
-
-        None
+        self.processes.push(process);
+        Some(id)
     }
 
     /// プロセスIDでプロセスを取得
     pub fn get(&self, id: ProcessId) -> Option<&Process> {
-        self.processes
-            .iter()
-            .find_map(|slot| slot.as_ref().filter(|p| p.id() == id))
+        self.processes.iter().find(|p| p.id() == id)
     }
 
     /// プロセスIDでプロセスの可変参照を取得
     pub fn get_mut(&mut self, id: ProcessId) -> Option<&mut Process> {
-        self.processes
-            .iter_mut()
-            .find_map(|slot| slot.as_mut().filter(|p| p.id() == id))
+        self.processes.iter_mut().find(|p| p.id() == id)
     }
 
     /// プロセスを削除
@@ -180,45 +260,51 @@ impl ProcessTable {
     /// # Returns
     /// 削除されたプロセスを返す。存在しない場合はNone
     pub fn remove(&mut self, id: ProcessId) -> Option<Process> {
-        for slot in &mut self.processes {
-            if let Some(ref process) = slot {
-                if process.id() == id {
-                    self.count -= 1;
-                    return slot.take();
-                }
-            }
-        }
-        None
+        let index = self.processes.iter().position(|p| p.id() == id)?;
+        Some(self.processes.remove(index))
     }
 
     /// すべてのプロセスを反復処理
     pub fn iter(&self) -> impl Iterator<Item = &Process> {
-        self.processes.iter().filter_map(|slot| slot.as_ref())
+        self.processes.iter()
     }
 
     /// すべてのプロセスを可変反復処理
     pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Process> {
-        self.processes.iter_mut().filter_map(|slot| slot.as_mut())
+        self.processes.iter_mut()
     }
 
     /// 現在のプロセス数を取得
     pub fn count(&self) -> usize {
-        self.count
+        self.processes.len()
     }
 
     /// プロセステーブルが満杯かどうか
     pub fn is_full(&self) -> bool {
-        self.count >= Self::MAX_PROCESSES
+        self.processes.len() >= Self::MAX_PROCESSES
     }
 
     /// プロセステーブルが空かどうか
     pub fn is_empty(&self) -> bool {
-        self.count == 0
+        self.processes.is_empty()
     }
 }
 
 /// グローバルプロセステーブル
-static PROCESS_TABLE: SpinLock<ProcessTable> = SpinLock::new(ProcessTable::new());
+pub(super) static PROCESS_TABLE: SpinLock<ProcessTable> = SpinLock::new(ProcessTable::new());
+
+/// initプロセス（親を失ったプロセスの引き取り先）
+static INIT_PROCESS: SpinLock<Option<ProcessId>> = SpinLock::new(None);
+
+/// initプロセスを設定
+pub fn set_init_process(id: ProcessId) {
+    *INIT_PROCESS.lock() = Some(id);
+}
+
+/// initプロセスのIDを取得
+pub fn init_process_id() -> Option<ProcessId> {
+    *INIT_PROCESS.lock()
+}
 
 /// プロセステーブルにプロセスを追加
 pub fn add_process(process: Process) -> Option<ProcessId> {
@@ -1,33 +1,50 @@
 use crate::interrupt::spinlock::SpinLock;
 
-use super::context::switch_to_thread;
-use super::ids::{ThreadId, ThreadState};
+use super::context::{enter_thread, switch_frame, Context};
+use super::ids::{ProcessId, ThreadId, ThreadState};
 use super::thread::{
-    current_thread_id, remove_thread, set_current_thread, with_thread_mut, CURRENT_THREAD,
+    current_thread_id, remove_thread, set_current_thread, with_thread, with_thread_mut, CURRENT_THREAD,
     THREAD_QUEUE,
 };
 
+/// 優先度の段階数（0が最高）
+pub const PRIORITY_LEVELS: usize = 8;
+/// アイドルスレッド用の最低優先度（エージングの対象外）
+pub const IDLE_PRIORITY: u8 = PRIORITY_LEVELS as u8 - 1;
+/// 優先度を指定しない場合の既定値
+pub const DEFAULT_PRIORITY: u8 = 4;
+/// 実行待ちがこのティック数を超えると優先度を1段上げる
+pub const AGING_TICKS: u64 = 50;
+
+/// 強制終了されたプロセスの終了コード
+pub const KILLED_EXIT_CODE: u64 = u64::MAX;
+
 /// スケジューラ
 ///
 /// スレッドのスケジューリングを管理
 pub struct Scheduler {
     /// スケジューラが有効かどうか
     enabled: bool,
-    /// タイムスライス（タイマー割り込み回数）
-    time_slice: u64,
+    /// 優先度ごとのタイムスライス（タイマー割り込み回数）
+    time_slices: [u64; PRIORITY_LEVELS],
+    /// 実行中のスレッドの優先度
+    current_priority: u8,
     /// 現在のタイムスライスカウンタ
     current_slice: u64,
 }
 
 impl Scheduler {
-    /// デフォルトのタイムスライス（10ms × 10 = 100ms）
-    pub const DEFAULT_TIME_SLICE: u64 = 10;
+    /// 優先度ごとのデフォルトのタイムスライス（10ms単位）
+    ///
+    /// 優先度の高いスレッドは短いスライスで頻繁に、低いスレッドは長いスライスでまとめて動かす
+    pub const DEFAULT_TIME_SLICES: [u64; PRIORITY_LEVELS] = [2, 4, 6, 8, 10, 12, 16, 20];
 
     /// 新しいスケジューラを作成
     pub const fn new() -> Self {
         Self {
             enabled: false,
-            time_slice: Self::DEFAULT_TIME_SLICE,
+            time_slices: Self::DEFAULT_TIME_SLICES,
+            current_priority: IDLE_PRIORITY,
             current_slice: 0,
         }
     }
@@ -47,9 +64,16 @@ impl Scheduler {
         self.enabled
     }
 
-    /// タイムスライスを設定
-    pub fn set_time_slice(&mut self, slice: u64) {
-        self.time_slice = slice;
+    /// 指定した優先度のタイムスライスを設定
+    pub fn set_time_slice(&mut self, priority: u8, slice: u64) {
+        if let Some(s) = self.time_slices.get_mut(priority as usize) {
+            *s = slice.max(1);
+        }
+    }
+
+    /// 実行中のスレッドの優先度を取得
+    pub fn current_priority(&self) -> u8 {
+        self.current_priority
     }
 
     /// タイマー割り込み時に呼ばれる
@@ -61,7 +85,7 @@ impl Scheduler {
         }
 
         self.current_slice += 1;
-        if self.current_slice >= self.time_slice {
+        if self.current_slice >= self.time_slices[self.current_priority as usize] {
             self.current_slice = 0;
             true // スケジューリングが必要
         } else {
@@ -69,6 +93,12 @@ impl Scheduler {
         }
     }
 
+    /// 指定した優先度のスレッドでタイムスライスを開始
+    pub fn start_slice(&mut self, priority: u8) {
+        self.current_priority = priority.min(IDLE_PRIORITY);
+        self.current_slice = 0;
+    }
+
     /// タイムスライスをリセット
     pub fn reset_slice(&mut self) {
         self.current_slice = 0;
@@ -89,9 +119,9 @@ pub fn enable_scheduler() {
     SCHEDULER.lock().enable();
 }
 
-/// タイムスライスを設定
-pub fn set_time_slice(slice: u64) {
-    SCHEDULER.lock().set_time_slice(slice);
+/// 指定した優先度のタイムスライスを設定
+pub fn set_time_slice(priority: u8, slice: u64) {
+    SCHEDULER.lock().set_time_slice(priority, slice);
 }
 
 /// スケジューラを無効化
@@ -106,15 +136,29 @@ pub fn is_scheduler_enabled() -> bool {
 
 /// タイマー割り込み時に呼ばれる（タイマー割り込みハンドラから呼び出す）
 ///
+/// 実行待ちスレッドのエージングも行う
+///
 /// # Returns
-/// スケジューリングが必要な場合はtrue
+/// タイムスライスが尽きた場合、または実行中より優先度の高いスレッドが
+/// 実行待ちになっている場合はtrue
 pub fn scheduler_tick() -> bool {
-    SCHEDULER.lock().tick()
+    let (expired, running) = {
+        let mut scheduler = SCHEDULER.lock();
+        if !scheduler.is_enabled() {
+            return false;
+        }
+        (scheduler.tick(), scheduler.current_priority())
+    };
+
+    let mut queue = THREAD_QUEUE.lock();
+    queue.age(crate::interrupt::timer::get_ticks());
+    expired || queue.highest_ready_priority().is_some_and(|p| p < running)
 }
 
 /// 次に実行すべきスレッドを選択
 ///
-/// ラウンドロビンスケジューリング：Ready状態のスレッドを順に選択
+/// 優先度ごとの実行待ちキューから、最も優先度の高いスレッドを選ぶ。
+/// 同じ優先度のスレッド間はラウンドロビン
 ///
 /// # Returns
 /// 次に実行すべきスレッドID。実行可能なスレッドがない場合はNone
@@ -124,33 +168,30 @@ pub fn schedule() -> Option<ThreadId> {
     // 現在のスレッドを取得
     let current = *CURRENT_THREAD.lock();
 
-    // 現在のスレッドがあれば、状態をReadyに戻す（Running -> Ready）
+    // 現在のスレッドがあれば、状態をReadyに戻してキューの末尾へ（Running -> Ready）
     if let Some(current_id) = current {
-        if let Some(thread) = queue.get_mut(current_id) {
-            if thread.state() == ThreadState::Running {
-                thread.set_state(ThreadState::Ready);
-            }
+        if queue.get(current_id).is_some_and(|t| t.state() == ThreadState::Running) {
+            queue.make_ready(current_id);
         }
     }
 
-    // 現在のスレッドの次のReady状態のスレッドを探す
-    if let Some(next_thread) = queue.peek_next_after(current) {
-        let next_id = next_thread.id();
-        next_thread.set_state(ThreadState::Running);
+    // 最も優先度の高いReady状態のスレッドを探す
+    let next_thread = queue.pop_ready()?;
+    let next_id = next_thread.id();
+    let priority = next_thread.priority();
+    next_thread.set_state(ThreadState::Running);
 
-        // スケジューラのタイムスライスをリセット
-        drop(queue);
-        SCHEDULER.lock().reset_slice();
+    // 選んだスレッドの優先度でタイムスライスを開始
+    drop(queue);
+    SCHEDULER.lock().start_slice(priority);
 
-        Some(next_id)
-    } else {
-        None
-    }
+    Some(next_id)
 }
 
 /// 現在のスレッドを明示的にCPUを手放す（yield）
 ///
-/// スケジューラを呼び出して次のスレッドに切り替える
+/// スレッド切り替え要求の割り込み（int 0x81）を発行し、割り込み入口で
+/// 保存したトラップフレームから次のスレッドへ切り替える
 pub fn yield_now() {
     if !is_scheduler_enabled() {
         return;
@@ -158,24 +199,26 @@ pub fn yield_now() {
 
     crate::debug!("yield_now() called");
 
-    // スケジューリングを実行
-    if let Some(next_id) = schedule() {
-        let current = current_thread_id();
+    unsafe {
+        core::arch::asm!("int 0x81", options(nomem, nostack));
+    }
+}
 
-        crate::debug!("yield_now: current={:?}, next={:?}", current, next_id);
+/// トラップフレーム上で次のスレッドへ切り替える
+///
+/// 割り込み入口（タイマー、int 0x81）から呼ばれる
+pub fn reschedule(frame: &mut Context) {
+    if !is_scheduler_enabled() {
+        return;
+    }
 
+    let current = current_thread_id();
+    if let Some(next_id) = schedule() {
         // 次のスレッドが現在のスレッドと異なる場合のみ切り替え
         if Some(next_id) != current {
+            crate::debug!("reschedule: current={:?}, next={:?}", current, next_id);
             set_current_thread(Some(next_id));
-
-            crate::debug!("Calling switch_to_thread...");
-
-            // コンテキストスイッチを実行
-            unsafe {
-                switch_to_thread(current, next_id);
-            }
-
-            crate::debug!("Returned from switch_to_thread");
+            switch_frame(frame, current, next_id);
         }
     }
 }
@@ -207,54 +250,101 @@ pub fn sleep_thread(id: ThreadId) {
 ///
 /// Sleeping/Blocked状態のスレッドをReady状態にする
 pub fn wake_thread(id: ThreadId) {
-    with_thread_mut(id, |thread| {
-        let state = thread.state();
-        if state == ThreadState::Sleeping || state == ThreadState::Blocked {
-            thread.set_state(ThreadState::Ready);
-        }
-    });
+    let mut queue = THREAD_QUEUE.lock();
+    let waiting = queue
+        .get(id)
+        .is_some_and(|t| matches!(t.state(), ThreadState::Sleeping | ThreadState::Blocked));
+    if waiting {
+        queue.make_ready(id);
+    }
 }
 
 /// スレッドを終了させる
 ///
-/// 指定されたスレッドをTerminated状態にして削除
+/// 指定されたスレッドをTerminated状態にし、終了を待っているスレッドがあれば起床させる。
+/// 終了したスレッドは自身のカーネルスタック上では解放できないため、削除は参加したスレッドか
+/// `reap_threads`が行う。現在のスレッドの場合は戻らない
 pub fn terminate_thread(id: ThreadId) {
-    with_thread_mut(id, |thread| {
+    let joiner = with_thread_mut(id, |thread| {
         thread.set_state(ThreadState::Terminated);
-    });
+        thread.take_joiner()
+    })
+    .flatten();
+
+    if let Some(joiner) = joiner {
+        wake_thread(joiner);
+    }
 
     // 現在のスレッドの場合は次のスレッドにスケジューリング
     if Some(id) == current_thread_id() {
         set_current_thread(None);
         yield_now();
     }
-
-    // スレッドをキューから削除
-    remove_thread(id);
 }
 
-/// スケジューリングしてコンテキストスイッチを実行
+/// 終了コードを記録して現在のスレッドを終了させる
 ///
-/// タイマー割り込みハンドラから呼び出される
-pub fn schedule_and_switch() {
-    if !is_scheduler_enabled() {
-        return;
+/// プロセスの最後のスレッドだった場合はプロセスも同じ終了コードで終了する
+pub fn exit_current_thread(code: u64) {
+    let id = match current_thread_id() {
+        Some(id) => id,
+        None => return,
+    };
+    let pid = with_thread_mut(id, |thread| {
+        thread.set_exit_code(code);
+        thread.process_id()
+    });
+
+    if let Some(pid) = pid {
+        let last = !THREAD_QUEUE
+            .lock()
+            .iter_by_process(pid)
+            .any(|t| t.id() != id && t.state() != ThreadState::Terminated);
+        if last {
+            super::exit::exit_process(pid, code);
+        }
     }
 
-    let current = current_thread_id();
+    terminate_thread(id);
+}
 
-    // 次のスレッドを選択
-    if let Some(next_id) = schedule() {
-        // 次のスレッドが現在のスレッドと異なる場合のみ切り替え
-        if Some(next_id) != current {
-            set_current_thread(Some(next_id));
+/// 現在のプロセスを終了させる
+pub fn exit_current_process(code: u64) {
+    let id = match current_thread_id() {
+        Some(id) => id,
+        None => return,
+    };
+    if let Some(pid) = with_thread(id, |thread| thread.process_id()) {
+        super::exit::exit_process(pid, code);
+    }
+    terminate_thread(id);
+}
+
+/// プロセスを強制終了する
+///
+/// プロセスに属するすべてのスレッドをTerminated状態にし、プロセスをゾンビにする
+pub fn kill_process(pid: ProcessId) {
+    super::exit::exit_process(pid, KILLED_EXIT_CODE);
+}
 
-            // コンテキストスイッチを実行
-            unsafe {
-                switch_to_thread(current, next_id);
-            }
+/// 割込み（例外）ハンドラ内から現在のプロセスを強制終了し、次のスレッドへ切り替える
+///
+/// 現在のスレッドのコンテキストは保存しない
+pub fn kill_current_process_from_isr() -> ! {
+    if let Some(current) = current_thread_id() {
+        if let Some(pid) = super::thread::with_thread(current, |t| t.process_id()) {
+            crate::warn!("Killing process {:?}", pid);
+            kill_process(pid);
         }
     }
+    set_current_thread(None);
+
+    if let Some(next_id) = schedule() {
+        set_current_thread(Some(next_id));
+        unsafe { enter_thread(next_id) };
+    }
+
+    panic!("No threads to schedule after killing the current process");
 }
 
 /// 最初のスレッドを起動
@@ -262,15 +352,16 @@ pub fn schedule_and_switch() {
 /// スケジューラを開始して最初のスレッドにジャンプ
 pub fn start_scheduling() -> ! {
     // 最初のスレッドを選択
-    if let Some(first_id) = super::thread::peek_next_thread() {
+    if let Some(first_id) = schedule() {
         set_current_thread(Some(first_id));
 
         // 情報出力（表示確実化のため info にする）
         with_thread_mut(first_id, |thread| {
             crate::info!(
-                "Starting first thread: {} (id={:?})",
+                "Starting first thread: {} (id={:?}, priority={})",
                 thread.name(),
-                thread.id()
+                thread.id(),
+                thread.priority()
             );
             crate::info!(
                 "  Context: rsp={:#x}, rip={:#x}, rflags={:#x}",
@@ -278,29 +369,10 @@ pub fn start_scheduling() -> ! {
                 thread.context().rip,
                 thread.context().rflags
             );
-            thread.set_state(ThreadState::Running);
         });
 
         // 最初のスレッドにジャンプ（戻ってこない）
-        // Determine if the first thread is user (Service) and capture its context pointer
-        let (is_user, ctx_ptr) = crate::task::with_thread(first_id, |thread| {
-            let priv_level = crate::task::with_process(thread.process_id(), |p| p.privilege())
-                .unwrap_or(crate::task::PrivilegeLevel::Core);
-            (priv_level != crate::task::PrivilegeLevel::Core, thread.context() as *const _)
-        })
-        .unwrap_or((false, core::ptr::null()));
-
-        unsafe {
-            if is_user {
-                let ctx = &*ctx_ptr;
-                crate::info!("Entering user mode for first thread via enter_user_from_kernel");
-                crate::task::context::enter_user_from_kernel(ctx);
-            } else {
-                switch_to_thread(None, first_id);
-            }
-        }
-
-        unreachable!("switch_to_thread should never return");
+        unsafe { enter_thread(first_id) }
     } else {
         panic!("No threads to schedule!");
     }
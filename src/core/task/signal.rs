@@ -0,0 +1,474 @@
+//! シグナル（プロセスへの非同期通知）
+//!
+//! シグナルはプロセス単位で保留し、そのプロセスのスレッドがRing3へ戻る直前
+//! （`before_user_return`）に処理する。ハンドラが登録されている場合は
+//! ユーザースタックにシグナルフレームを積んでハンドラへ飛び、
+//! ハンドラが戻るとrestorer経由の`SigReturn`で元のコンテキストに戻る。
+//!
+//! 既定の動作が終了・停止・再開のシグナルは、ブロック中のスレッドにも効くよう
+//! 送信時に処理する。SIGKILLとSIGSTOPはハンドラの登録も無視もできない。
+
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use swiftcore_abi::signal as abi;
+
+use crate::error::{KernelError, ProcessError, Result};
+use crate::mem::address_space::USER_SPACE_END;
+
+use super::context::Context;
+use super::ids::{ProcessId, ProcessState, ThreadId, ThreadState};
+use super::process::with_process_mut;
+use super::scheduler::reschedule;
+use super::thread::{current_thread_id, set_current_thread, with_thread, THREAD_QUEUE};
+
+/// シグナル番号の上限（1..NSIGが有効）
+pub const NSIG: usize = 32;
+
+// 番号はユーザー空間と共有するABIの定義に合わせる
+
+pub const SIGHUP: u32 = abi::SIGHUP as u32;
+pub const SIGINT: u32 = abi::SIGINT as u32;
+pub const SIGQUIT: u32 = abi::SIGQUIT as u32;
+pub const SIGKILL: u32 = abi::SIGKILL as u32;
+pub const SIGUSR1: u32 = abi::SIGUSR1 as u32;
+pub const SIGSEGV: u32 = abi::SIGSEGV as u32;
+pub const SIGUSR2: u32 = abi::SIGUSR2 as u32;
+pub const SIGPIPE: u32 = abi::SIGPIPE as u32;
+pub const SIGALRM: u32 = abi::SIGALRM as u32;
+pub const SIGTERM: u32 = abi::SIGTERM as u32;
+pub const SIGCHLD: u32 = abi::SIGCHLD as u32;
+pub const SIGCONT: u32 = abi::SIGCONT as u32;
+pub const SIGSTOP: u32 = abi::SIGSTOP as u32;
+pub const SIGTSTP: u32 = abi::SIGTSTP as u32;
+
+/// ユーザーが復帰時に変更してよいRFLAGSのビット（CF/PF/AF/ZF/SF/DF/OF）
+const USER_RFLAGS_MASK: u64 = 0xCD5;
+/// RFLAGSのIFビット
+const RFLAGS_IF: u64 = 0x200;
+/// ハンドラのフレームを置く前に避けるレッドゾーンのサイズ
+const RED_ZONE: u64 = 128;
+/// FPU状態を置く境界（XSAVEの要求）
+const FPU_ALIGN: u64 = 64;
+
+/// シグナルに対する動作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigAction {
+    /// 既定の動作
+    Default,
+    /// 無視する
+    Ignore,
+    /// ユーザーのハンドラを呼ぶ
+    Handler {
+        /// ハンドラのアドレス（`handler(sig)`として呼ぶ）
+        entry: u64,
+        /// ハンドラから戻った先（`SigReturn`を呼ぶコード）
+        restorer: u64,
+    },
+}
+
+/// 既定の動作の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+fn default_action(sig: u32) -> DefaultAction {
+    match sig {
+        SIGCHLD => DefaultAction::Ignore,
+        SIGSTOP | SIGTSTP => DefaultAction::Stop,
+        SIGCONT => DefaultAction::Continue,
+        _ => DefaultAction::Terminate,
+    }
+}
+
+/// シグナルで終了したプロセスの終了コード（シェルの慣例に合わせて128+番号）
+pub fn exit_code_for(sig: u32) -> u64 {
+    128 + sig as u64
+}
+
+const fn bit(sig: u32) -> u32 {
+    1 << sig
+}
+
+/// プロセスごとのシグナルの状態
+#[derive(Debug, Clone)]
+pub struct SignalState {
+    /// 保留中のシグナル（ビットnがシグナルn）
+    pending: u32,
+    /// 配送を保留するシグナル（ハンドラ実行中のシグナル）
+    mask: u32,
+    /// シグナルごとの動作
+    actions: [SigAction; NSIG],
+}
+
+impl SignalState {
+    pub const fn new() -> Self {
+        Self {
+            pending: 0,
+            mask: 0,
+            actions: [SigAction::Default; NSIG],
+        }
+    }
+
+    /// シグナルの動作を取得
+    pub fn action(&self, sig: u32) -> SigAction {
+        self.actions[sig as usize]
+    }
+
+    /// 保留中で配送できる最小番号のシグナルを取り出す
+    fn take_deliverable(&mut self) -> Option<u32> {
+        let ready = self.pending & !self.mask;
+        if ready == 0 {
+            return None;
+        }
+        let sig = ready.trailing_zeros();
+        self.pending &= !bit(sig);
+        Some(sig)
+    }
+}
+
+impl Default for SignalState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// シグナル番号が有効か（0は存在確認用のため含めない）
+fn is_valid(sig: u32) -> bool {
+    sig > 0 && (sig as usize) < NSIG
+}
+
+/// シグナルの動作を設定し、以前の動作を返す
+pub fn set_action(pid: ProcessId, sig: u32, action: SigAction) -> Result<SigAction> {
+    if !is_valid(sig) || sig == SIGKILL || sig == SIGSTOP {
+        return Err(KernelError::InvalidParam);
+    }
+    if let SigAction::Handler { entry, restorer } = action {
+        if entry == 0 || entry >= USER_SPACE_END || restorer == 0 || restorer >= USER_SPACE_END {
+            return Err(KernelError::InvalidParam);
+        }
+    }
+
+    with_process_mut(pid, |p| {
+        let signals = p.signals_mut();
+        let old = core::mem::replace(&mut signals.actions[sig as usize], action);
+        // 無視に変えたシグナルは保留から外す
+        if action == SigAction::Ignore {
+            signals.pending &= !bit(sig);
+        }
+        old
+    })
+    .ok_or(KernelError::Process(ProcessError::ProcessNotFound))
+}
+
+/// 送信時の処理
+enum Disposition {
+    Drop,
+    Queue,
+    Terminate,
+    Stop,
+    Continue { queue: bool },
+}
+
+/// プロセスにシグナルを送る
+///
+/// `sig`が0の場合はプロセスの存在だけを確認する
+pub fn send_signal(pid: ProcessId, sig: u32) -> Result<()> {
+    if sig != 0 && !is_valid(sig) {
+        return Err(KernelError::InvalidParam);
+    }
+
+    let disposition = with_process_mut(pid, |p| {
+        if sig == 0 || p.state() == ProcessState::Zombie {
+            return Disposition::Drop;
+        }
+        let signals = p.signals_mut();
+        let action = if sig == SIGKILL || sig == SIGSTOP {
+            SigAction::Default
+        } else {
+            signals.action(sig)
+        };
+        let disposition = match (action, default_action(sig)) {
+            (SigAction::Ignore, DefaultAction::Continue) => Disposition::Continue { queue: false },
+            (SigAction::Ignore, _) => Disposition::Drop,
+            (SigAction::Handler { .. }, DefaultAction::Continue) => Disposition::Continue { queue: true },
+            (SigAction::Handler { .. }, _) => Disposition::Queue,
+            (SigAction::Default, DefaultAction::Terminate) => Disposition::Terminate,
+            (SigAction::Default, DefaultAction::Ignore) => Disposition::Drop,
+            (SigAction::Default, DefaultAction::Stop) => Disposition::Stop,
+            (SigAction::Default, DefaultAction::Continue) => Disposition::Continue { queue: false },
+        };
+        if matches!(disposition, Disposition::Queue | Disposition::Continue { queue: true }) {
+            signals.pending |= bit(sig);
+        }
+        disposition
+    })
+    .ok_or(KernelError::Process(ProcessError::ProcessNotFound))?;
+
+    match disposition {
+        Disposition::Drop | Disposition::Queue => {}
+        Disposition::Terminate => super::exit::exit_process(pid, exit_code_for(sig)),
+        Disposition::Stop => set_process_stopped(pid, true),
+        Disposition::Continue { .. } => set_process_stopped(pid, false),
+    }
+    Ok(())
+}
+
+/// プロセスのすべてのスレッドを停止または再開する
+///
+/// 実行中のスレッドは`before_user_return`で切り替える
+fn set_process_stopped(pid: ProcessId, stopped: bool) {
+    let changed = with_process_mut(pid, |p| {
+        let (from, to) = if stopped {
+            (ProcessState::Running, ProcessState::Stopped)
+        } else {
+            (ProcessState::Stopped, ProcessState::Running)
+        };
+        if p.state() != from {
+            return false;
+        }
+        p.set_state(to);
+        true
+    });
+    if changed != Some(true) {
+        return;
+    }
+
+    let mut queue = THREAD_QUEUE.lock();
+    let threads: Vec<ThreadId> = queue.iter_by_process(pid).map(|t| t.id()).collect();
+    for id in threads {
+        queue.set_stopped(id, stopped);
+    }
+}
+
+/// ハンドラ呼び出し時にユーザースタックへ積むフレーム
+///
+/// ハンドラの開始時、RSPは`restorer`を指す（関数呼び出し直後と同じ配置）。
+/// FPU状態はこの構造体の直上に`fpu_len`バイト置く
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SignalFrame {
+    /// ハンドラから`ret`で戻る先
+    restorer: u64,
+    /// シグナル番号
+    sig: u64,
+    /// ハンドラ呼び出し前のマスク
+    mask: u64,
+    /// FPU状態のアドレスとサイズ（FPU未使用なら0）
+    fpu_addr: u64,
+    fpu_len: u64,
+    /// 割り込まれた時点のコンテキスト
+    context: Context,
+}
+
+/// Ring3へ戻る直前の処理
+///
+/// 戻り先のスレッドが終了・停止していれば別のスレッドへ切り替え、
+/// 保留中のシグナルがあれば配送する。`frame`は戻り先のトラップフレーム
+pub fn before_user_return(frame: &mut Context) {
+    // 切り替え先のスレッドにも同じ処理が必要なため、戻り先が決まるまで繰り返す
+    while frame.is_user() {
+        let id = match current_thread_id() {
+            Some(id) => id,
+            None => return,
+        };
+        let (state, stopped, pid) = match with_thread(id, |t| (t.state(), t.is_stopped(), t.process_id())) {
+            Some(v) => v,
+            None => return,
+        };
+
+        if state == ThreadState::Terminated {
+            set_current_thread(None);
+            reschedule(frame);
+            if current_thread_id().is_none() {
+                return;
+            }
+            continue;
+        }
+        if stopped {
+            reschedule(frame);
+            if current_thread_id() == Some(id) {
+                // 他に実行できるスレッドがない
+                return;
+            }
+            continue;
+        }
+        if !deliver(pid, id, frame) {
+            return;
+        }
+    }
+}
+
+/// 保留中のシグナルを1つ処理する
+///
+/// # Returns
+/// 処理した結果、スレッドの切り替えが必要になった場合や続けて処理すべき場合はtrue
+fn deliver(pid: ProcessId, id: ThreadId, frame: &mut Context) -> bool {
+    let taken = with_process_mut(pid, |p| {
+        let signals = p.signals_mut();
+        let sig = signals.take_deliverable()?;
+        Some((sig, signals.action(sig), signals.mask))
+    })
+    .flatten();
+    let (sig, action, mask) = match taken {
+        Some(v) => v,
+        None => return false,
+    };
+
+    match action {
+        SigAction::Ignore => true,
+        SigAction::Default => {
+            match default_action(sig) {
+                DefaultAction::Terminate => super::exit::exit_process(pid, exit_code_for(sig)),
+                DefaultAction::Stop => set_process_stopped(pid, true),
+                DefaultAction::Ignore | DefaultAction::Continue => {}
+            }
+            true
+        }
+        SigAction::Handler { entry, restorer } => {
+            if let Err(e) = push_signal_frame(pid, id, frame, sig, mask, entry, restorer) {
+                crate::warn!("signal {} to process {:?} could not be delivered: {:?}", sig, pid, e);
+                super::exit::exit_process(pid, exit_code_for(SIGSEGV));
+                return true;
+            }
+            with_process_mut(pid, |p| p.signals_mut().mask = mask | bit(sig));
+            false
+        }
+    }
+}
+
+/// ユーザースタックにシグナルフレームを積み、`frame`をハンドラの呼び出しに書き換える
+fn push_signal_frame(
+    pid: ProcessId,
+    id: ThreadId,
+    frame: &mut Context,
+    sig: u32,
+    mask: u32,
+    entry: u64,
+    restorer: u64,
+) -> Result<()> {
+    let fpu = super::fpu::snapshot(id);
+    let fpu_len = fpu.as_ref().map_or(0, |f| f.len()) as u64;
+
+    let invalid = KernelError::Memory(crate::error::MemoryError::InvalidAddress);
+    let fpu_addr = frame
+        .rsp
+        .checked_sub(RED_ZONE + fpu_len)
+        .ok_or(invalid)?
+        & !(FPU_ALIGN - 1);
+    // ハンドラの開始時にRSP+8が16バイト境界になるようにする
+    let frame_addr = (fpu_addr.checked_sub(size_of::<SignalFrame>() as u64).ok_or(invalid)? & !0xF)
+        .checked_sub(8)
+        .ok_or(invalid)?;
+
+    let signal_frame = SignalFrame {
+        restorer,
+        sig: sig as u64,
+        mask: mask as u64,
+        fpu_addr: if fpu.is_some() { fpu_addr } else { 0 },
+        fpu_len,
+        context: *frame,
+    };
+    let bytes = unsafe {
+        core::slice::from_raw_parts(&signal_frame as *const SignalFrame as *const u8, size_of::<SignalFrame>())
+    };
+
+    with_process_mut(pid, |p| {
+        let space = p
+            .address_space_mut()
+            .ok_or(KernelError::Memory(crate::error::MemoryError::NotMapped))?;
+        let total = (fpu_addr + fpu_len - frame_addr) as usize;
+        space.check_user_range(frame_addr, total, true)?;
+        space.write_bytes(frame_addr, bytes)?;
+        if let Some(fpu) = &fpu {
+            space.write_bytes(fpu_addr, fpu)?;
+        }
+        Ok(())
+    })
+    .ok_or(KernelError::Process(ProcessError::ProcessNotFound))??;
+
+    frame.rip = entry;
+    frame.rsp = frame_addr;
+    frame.rdi = sig as u64;
+    // ハンドラはDF=0で始める（呼び出し規約）
+    frame.rflags &= !0x400;
+    Ok(())
+}
+
+/// `SigReturn`：シグナルフレームから割り込まれた時点のコンテキストに戻る
+///
+/// restorerは`ret`でフレームの`restorer`を取り除いた直後のRSPのまま呼ぶこと。
+/// フレームが壊れている場合はプロセスをSIGSEGVで終了させる
+pub fn sigreturn(frame: &mut Context) {
+    let id = match current_thread_id() {
+        Some(id) => id,
+        None => return,
+    };
+    let pid = match with_thread(id, |t| t.process_id()) {
+        Some(pid) => pid,
+        None => return,
+    };
+
+    if let Err(e) = restore_signal_frame(pid, id, frame) {
+        crate::warn!("sigreturn: invalid signal frame in process {:?}: {:?}", pid, e);
+        super::exit::exit_process(pid, exit_code_for(SIGSEGV));
+    }
+}
+
+fn restore_signal_frame(pid: ProcessId, id: ThreadId, frame: &mut Context) -> Result<()> {
+    let invalid = KernelError::Memory(crate::error::MemoryError::InvalidAddress);
+    let frame_addr = frame.rsp.checked_sub(8).ok_or(invalid)?;
+
+    let mut signal_frame = core::mem::MaybeUninit::<SignalFrame>::uninit();
+    let buf = unsafe {
+        core::slice::from_raw_parts_mut(signal_frame.as_mut_ptr() as *mut u8, size_of::<SignalFrame>())
+    };
+    let fpu = with_process_mut(pid, |p| -> Result<Option<Vec<u8>>> {
+        let space = p
+            .address_space_mut()
+            .ok_or(KernelError::Memory(crate::error::MemoryError::NotMapped))?;
+        space.check_user_range(frame_addr, buf.len(), false)?;
+        space.read_bytes(frame_addr, buf)?;
+        let signal_frame = unsafe { signal_frame.assume_init_ref() };
+        if signal_frame.fpu_len == 0 {
+            return Ok(None);
+        }
+        let len = signal_frame.fpu_len as usize;
+        space.check_user_range(signal_frame.fpu_addr, len, false)?;
+        let mut data = Vec::new();
+        data.try_reserve_exact(len)
+            .map_err(|_| KernelError::Memory(crate::error::MemoryError::OutOfMemory))?;
+        data.resize(len, 0);
+        space.read_bytes(signal_frame.fpu_addr, &mut data)?;
+        Ok(Some(data))
+    })
+    .ok_or(KernelError::Process(ProcessError::ProcessNotFound))??;
+    let signal_frame = unsafe { signal_frame.assume_init() };
+
+    // ユーザーが書き換えたフレームでRing0に戻ったり、iretqで例外にならないよう検証する
+    let saved = signal_frame.context;
+    if saved.rip >= USER_SPACE_END || saved.rsp >= USER_SPACE_END {
+        return Err(invalid);
+    }
+    if let Some(fpu) = &fpu {
+        super::fpu::restore_snapshot(id, fpu)?;
+    }
+
+    let user = Context::user(saved.rip, saved.rsp);
+    *frame = Context {
+        cs: user.cs,
+        ss: user.ss,
+        rflags: (saved.rflags & USER_RFLAGS_MASK) | RFLAGS_IF,
+        vector: frame.vector,
+        error_code: 0,
+        ..saved
+    };
+
+    let mask = signal_frame.mask as u32 & !(bit(SIGKILL) | bit(SIGSTOP));
+    with_process_mut(pid, |p| p.signals_mut().mask = mask);
+    Ok(())
+}
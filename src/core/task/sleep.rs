@@ -0,0 +1,86 @@
+//! タイマーによるスリープとブロックの期限
+//!
+//! 起床時刻（ティック）順に並べたキューを持ち、タイマー割り込みのたびに
+//! 期限が来たスレッドを起床させる。
+
+use alloc::collections::BTreeSet;
+
+use crate::interrupt::spinlock::SpinLock;
+use crate::interrupt::timer::get_ticks;
+
+use super::ids::{ThreadId, ThreadState};
+use super::scheduler::{is_scheduler_enabled, wake_thread, yield_now};
+use super::thread::{current_thread_id, with_thread_mut};
+
+/// 起床待ちのスレッド（起床時刻, スレッドID）の昇順
+static SLEEP_QUEUE: SpinLock<BTreeSet<(u64, ThreadId)>> = SpinLock::new(BTreeSet::new());
+
+/// 現在のスレッドを指定ティック数スリープさせる
+///
+/// 0の場合は実行権を譲るだけ
+pub fn sleep_ticks(ticks: u64) {
+    if ticks == 0 {
+        yield_now();
+        return;
+    }
+    sleep_until(get_ticks().saturating_add(ticks));
+}
+
+/// 現在のスレッドを指定時刻（ティック）までスリープさせる
+pub fn sleep_until(deadline: u64) {
+    if deadline <= get_ticks() {
+        return;
+    }
+    park(ThreadState::Sleeping, Some(deadline));
+}
+
+/// 現在のスレッドをブロックする（期限を指定した場合はその時刻に起床）
+///
+/// 待ち合わせ先への登録は呼び出し側で先に済ませておくこと。
+/// 登録からブロックまでの間に起床処理が走らないよう、割り込み禁止の状態で呼ぶ
+/// （システムコールハンドラ内は割り込み禁止で動く）
+pub fn block_current_until(deadline: Option<u64>) {
+    park(ThreadState::Blocked, deadline);
+}
+
+/// 現在のスレッドを`state`にして実行権を譲り、起床するまで待つ
+fn park(state: ThreadState, deadline: Option<u64>) {
+    let id = match current_thread_id() {
+        Some(id) => id,
+        None => return,
+    };
+    if !is_scheduler_enabled() {
+        return;
+    }
+
+    // 登録と状態変更の間にタイマーが起床処理をしないよう割り込みを止める
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        if let Some(deadline) = deadline {
+            SLEEP_QUEUE.lock().insert((deadline, id));
+        }
+        with_thread_mut(id, |thread| thread.set_state(state));
+    });
+    yield_now();
+
+    // 期限前に他の要因で起床した場合はエントリを消す
+    if let Some(deadline) = deadline {
+        SLEEP_QUEUE.lock().remove(&(deadline, id));
+    }
+}
+
+/// 期限が来たスレッドを起床させる（タイマー割り込みから呼ぶ）
+pub fn wake_expired(now: u64) {
+    loop {
+        let id = {
+            let mut queue = SLEEP_QUEUE.lock();
+            match queue.first() {
+                Some(&(deadline, _)) if deadline <= now => queue.pop_first().map(|(_, id)| id),
+                _ => None,
+            }
+        };
+        match id {
+            Some(id) => wake_thread(id),
+            None => break,
+        }
+    }
+}
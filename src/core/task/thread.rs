@@ -1,7 +1,16 @@
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::error::{KernelError, ProcessError, Result};
 use crate::interrupt::spinlock::SpinLock;
+use crate::mem::kstack;
 
 use super::context::Context;
+use super::fpu::FpuState;
 use super::ids::{ProcessId, ThreadId, ThreadState};
+use super::scheduler::{AGING_TICKS, DEFAULT_PRIORITY, IDLE_PRIORITY, PRIORITY_LEVELS};
 
 /// スレッド終了時に呼ばれるハンドラ
 /// この関数から戻ることはない
@@ -23,15 +32,29 @@ pub struct Thread {
     /// 所属するプロセスID
     process_id: ProcessId,
     /// スレッド名
-    name: &'static str,
+    name: String,
     /// 現在の状態
     state: ThreadState,
+    /// 停止シグナルで停止中（Readyでも実行待ちキューに入れない）
+    stopped: bool,
+    /// 優先度（0が最高。所属プロセスの優先度を引き継ぐ）
+    priority: u8,
     /// CPUコンテキスト
     context: Context,
     /// カーネルスタックの開始アドレス
     kernel_stack: u64,
     /// カーネルスタックのサイズ
     kernel_stack_size: usize,
+    /// スレッドが所有するカーネルスタック（スレッドの削除時に解放）
+    owned_stack: Option<kstack::KernelStack>,
+    /// FPU/SIMD状態の保存領域（最初にFPUを使った時点で確保）
+    fpu: Option<FpuState>,
+    /// FSベース（TLSの基点。切り替え時にMSRへ設定する）
+    fs_base: u64,
+    /// 終了コード（終了時に設定）
+    exit_code: Option<u64>,
+    /// 終了を待っているスレッド
+    joiner: Option<ThreadId>,
 }
 
 impl Thread {
@@ -45,13 +68,11 @@ impl Thread {
     /// * `kernel_stack_size` - カーネルスタックのサイズ
     pub fn new(
         process_id: ProcessId,
-        name: &'static str,
+        name: &str,
         entry_point: fn() -> !,
         kernel_stack: u64,
         kernel_stack_size: usize,
     ) -> Self {
-        let mut context = Context::new();
-
         // スタックポインタをスタックの最後に設定（スタックは下に伸びる）
         // 16バイト境界に合わせる
         let stack_top = (kernel_stack + kernel_stack_size as u64) & !0xF;
@@ -66,15 +87,10 @@ impl Thread {
         }
 
         // rsp は「戻り先アドレスが置かれている位置」を指す
-        context.rsp = stack_ptr;
+        // RFLAGSの初期値は割り込み有効
+        let mut context = Context::kernel(entry_point as u64, stack_ptr);
         context.rbp = stack_top;
 
-        // エントリーポイントをripに設定
-        context.rip = entry_point as u64;
-
-        // RFLAGSの初期値（割り込み有効）
-        context.rflags = 0x202; // IF (Interrupt Flag) = 1
-
         crate::debug!(
             "Creating thread '{}': stack={:#x}, size={:#x}, rsp={:#x}, rip={:#x}",
             name,
@@ -87,11 +103,18 @@ impl Thread {
         Self {
             id: ThreadId::new(),
             process_id,
-            name,
+            name: String::from(name),
             state: ThreadState::Ready,
+            stopped: false,
+            priority: DEFAULT_PRIORITY,
             context,
             kernel_stack,
             kernel_stack_size,
+            owned_stack: None,
+            fpu: None,
+            fs_base: 0,
+            exit_code: None,
+            joiner: None,
         }
     }
 
@@ -106,8 +129,8 @@ impl Thread {
     }
 
     /// スレッド名を取得
-    pub fn name(&self) -> &'static str {
-        self.name
+    pub fn name(&self) -> &str {
+        &self.name
     }
 
     /// スレッドの状態を取得
@@ -120,6 +143,76 @@ impl Thread {
         self.state = state;
     }
 
+    /// 停止中かどうか
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// 優先度を取得
+    pub fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    /// 優先度を設定（最低優先度はアイドル用）
+    pub fn set_priority(&mut self, priority: u8) {
+        self.priority = priority.min(IDLE_PRIORITY);
+    }
+
+    /// カーネルスタックの上端（Ring3からの割り込み時のRSP0）
+    pub fn kernel_stack_top(&self) -> u64 {
+        (self.kernel_stack + self.kernel_stack_size as u64) & !0xF
+    }
+
+    /// 削除時に解放するカーネルスタックを設定
+    pub fn set_owned_stack(&mut self, stack: kstack::KernelStack) {
+        self.owned_stack = Some(stack);
+    }
+
+    /// FPU状態の保存領域を取得
+    pub fn fpu_state_mut(&mut self) -> Option<&mut FpuState> {
+        self.fpu.as_mut()
+    }
+
+    /// FPU状態の保存領域を設定
+    pub fn set_fpu_state(&mut self, state: FpuState) {
+        self.fpu = Some(state);
+    }
+
+    /// FSベースを取得
+    pub fn fs_base(&self) -> u64 {
+        self.fs_base
+    }
+
+    /// FSベースを設定
+    pub fn set_fs_base(&mut self, base: u64) {
+        self.fs_base = base;
+    }
+
+    /// 終了コードを取得
+    pub fn exit_code(&self) -> Option<u64> {
+        self.exit_code
+    }
+
+    /// 終了コードを設定
+    pub fn set_exit_code(&mut self, code: u64) {
+        self.exit_code = Some(code);
+    }
+
+    /// 終了を待っているスレッドを取得
+    pub fn joiner(&self) -> Option<ThreadId> {
+        self.joiner
+    }
+
+    /// 終了を待つスレッドを設定
+    pub fn set_joiner(&mut self, joiner: ThreadId) {
+        self.joiner = Some(joiner);
+    }
+
+    /// 終了を待っているスレッドを取り出す
+    pub fn take_joiner(&mut self) -> Option<ThreadId> {
+        self.joiner.take()
+    }
+
     /// コンテキストへの可変参照を取得
     pub fn context_mut(&mut self) -> &mut Context {
         &mut self.context
@@ -138,20 +231,32 @@ impl core::fmt::Debug for Thread {
             .field("process_id", &self.process_id)
             .field("name", &self.name)
             .field("state", &self.state)
+            .field("stopped", &self.stopped)
             .field("kernel_stack", &format_args!("{:#x}", self.kernel_stack))
             .field("kernel_stack_size", &self.kernel_stack_size)
             .finish()
     }
 }
 
+/// 実行待ちキューのエントリ
+#[derive(Debug, Clone, Copy)]
+struct RunEntry {
+    id: ThreadId,
+    /// キューに入った（またはエージングで昇格した）時刻（ティック）
+    since: u64,
+}
+
 /// スレッドキュー
 ///
-/// 実行可能なスレッドを管理するキュー
+/// 実行可能なスレッドを管理するキュー。
+/// コンテキストへのポインタがロック解放後も有効であるよう、各スレッドはBoxで保持する。
+/// Ready状態のスレッドは優先度ごとの実行待ちキューにも登録する。
 pub struct ThreadQueue {
-    /// スレッドの配列（最大容量）
-    threads: [Option<Thread>; Self::MAX_THREADS],
-    /// 現在のスレッド数
-    count: usize,
+    /// スレッドの一覧
+    #[allow(clippy::vec_box)]
+    threads: Vec<Box<Thread>>,
+    /// 優先度ごとの実行待ちキュー（添字が優先度）
+    run_queues: [VecDeque<RunEntry>; PRIORITY_LEVELS],
 }
 
 impl ThreadQueue {
@@ -160,10 +265,9 @@ impl ThreadQueue {
 
     /// 新しいスレッドキューを作成
     pub const fn new() -> Self {
-        const INIT: Option<Thread> = None;
         Self {
-            threads: [INIT; Self::MAX_THREADS],
-            count: 0,
+            threads: Vec::new(),
+            run_queues: [const { VecDeque::new() }; PRIORITY_LEVELS],
         }
     }
 
@@ -172,36 +276,121 @@ impl ThreadQueue {
     /// # Returns
     /// 成功時はスレッドIDを返す。キューが満杯の場合はNone
     pub fn push(&mut self, thread: Thread) -> Option<ThreadId> {
-        if self.count >= Self::MAX_THREADS {
+        if self.threads.len() >= Self::MAX_THREADS {
             return None;
         }
 
         let id = thread.id();
+        let ready = thread.state() == ThreadState::Ready;
+        let priority = thread.priority();
+        self.threads.push(Box::new(thread));
+        if ready {
+            self.enqueue(id, priority);
+        }
+        Some(id)
+    }
+
+    /// 実行待ちキューに登録（登録済みの場合は末尾へ移す）
+    fn enqueue(&mut self, id: ThreadId, priority: u8) {
+        for queue in self.run_queues.iter_mut() {
+            queue.retain(|e| e.id != id);
+        }
+        let since = crate::interrupt::timer::get_ticks();
+        self.run_queues[priority as usize].push_back(RunEntry { id, since });
+    }
 
-        // 空きスロットを探す
-        for slot in &mut self.threads {
-            if slot.is_none() {
-                *slot = Some(thread);
-                self.count += 1;
-                return Some(id);
+    /// スレッドをReady状態にして実行待ちキューに登録
+    ///
+    /// 停止中のスレッドはReady状態にするだけで、再開時に登録する
+    ///
+    /// # Returns
+    /// スレッドが存在しない、または終了済みの場合はfalse
+    pub fn make_ready(&mut self, id: ThreadId) -> bool {
+        let (priority, stopped) = match self.get_mut(id) {
+            Some(thread) if thread.state() != ThreadState::Terminated => {
+                thread.set_state(ThreadState::Ready);
+                (thread.priority(), thread.is_stopped())
             }
+            _ => return false,
+        };
+        if !stopped {
+            self.enqueue(id, priority);
         }
+        true
+    }
 
+    /// スレッドを停止または再開する
+    ///
+    /// 再開時、Ready状態のスレッドを実行待ちキューに戻す
+    pub fn set_stopped(&mut self, id: ThreadId, stopped: bool) {
+        let ready = match self.get_mut(id) {
+            Some(thread) => {
+                thread.stopped = stopped;
+                thread.state() == ThreadState::Ready
+            }
+            None => return,
+        };
+        if !stopped && ready {
+            self.make_ready(id);
+        }
+    }
+
+    /// 最も優先度の高い実行待ちスレッドをキューから取り出す
+    ///
+    /// 同じ優先度のスレッドはキューに入った順（ラウンドロビン）で選ぶ。
+    /// 登録後に状態が変わったスレッドのエントリは読み捨てる
+    pub fn pop_ready(&mut self) -> Option<&mut Thread> {
+        for level in 0..PRIORITY_LEVELS {
+            while let Some(entry) = self.run_queues[level].pop_front() {
+                if let Some(index) = self
+                    .threads
+                    .iter()
+                    .position(|t| t.id() == entry.id && t.state() == ThreadState::Ready && !t.is_stopped())
+                {
+                    return Some(&mut self.threads[index]);
+                }
+            }
+        }
         None
     }
 
+    /// 実行待ちのスレッドがある最も高い優先度
+    pub fn highest_ready_priority(&self) -> Option<u8> {
+        (0..PRIORITY_LEVELS)
+            .find(|&level| {
+                self.run_queues[level].iter().any(|e| {
+                    self.get(e.id)
+                        .is_some_and(|t| t.state() == ThreadState::Ready && !t.is_stopped())
+                })
+            })
+            .map(|level| level as u8)
+    }
+
+    /// エージング：長く待っているエントリを1段高い優先度のキューへ移す
+    ///
+    /// アイドル優先度のキューは対象外
+    pub fn age(&mut self, now: u64) {
+        for level in 1..IDLE_PRIORITY as usize {
+            let (upper, lower) = self.run_queues.split_at_mut(level);
+            let target = &mut upper[level - 1];
+            lower[0].retain(|e| {
+                if now.saturating_sub(e.since) < AGING_TICKS {
+                    return true;
+                }
+                target.push_back(RunEntry { id: e.id, since: now });
+                false
+            });
+        }
+    }
+
     /// スレッドIDでスレッドを取得
     pub fn get(&self, id: ThreadId) -> Option<&Thread> {
-        self.threads
-            .iter()
-            .find_map(|slot| slot.as_ref().filter(|t| t.id() == id))
+        self.iter().find(|t| t.id() == id)
     }
 
     /// スレッドIDでスレッドの可変参照を取得
     pub fn get_mut(&mut self, id: ThreadId) -> Option<&mut Thread> {
-        self.threads
-            .iter_mut()
-            .find_map(|slot| slot.as_mut().filter(|t| t.id() == id))
+        self.iter_mut().find(|t| t.id() == id)
     }
 
     /// スレッドを削除
@@ -209,15 +398,8 @@ impl ThreadQueue {
     /// # Returns
     /// 削除されたスレッドを返す。存在しない場合はNone
     pub fn remove(&mut self, id: ThreadId) -> Option<Thread> {
-        for slot in &mut self.threads {
-            if let Some(ref thread) = slot {
-                if thread.id() == id {
-                    self.count -= 1;
-                    return slot.take();
-                }
-            }
-        }
-        None
+        let index = self.threads.iter().position(|t| t.id() == id)?;
+        Some(*self.threads.remove(index))
     }
 
     /// 次に実行すべきスレッドを取得（削除せずに参照を返す）
@@ -225,104 +407,64 @@ impl ThreadQueue {
     /// Ready状態のスレッドを優先して返す
     pub fn peek_next(&self) -> Option<&Thread> {
         // Ready状態のスレッドを探す
-        self.threads
-            .iter()
-            .filter_map(|slot| slot.as_ref())
-            .find(|t| t.state() == ThreadState::Ready)
+        self.iter().find(|t| t.state() == ThreadState::Ready)
     }
 
     /// 次に実行すべきスレッドを取得（可変参照）
     pub fn peek_next_mut(&mut self) -> Option<&mut Thread> {
         // Ready状態のスレッドを探す
-        self.threads
-            .iter_mut()
-            .filter_map(|slot| slot.as_mut())
-            .find(|t| t.state() == ThreadState::Ready)
+        self.iter_mut().find(|t| t.state() == ThreadState::Ready)
     }
 
     /// 指定されたスレッドの次のReady状態のスレッドを取得（ラウンドロビン用）
     ///
-    /// current_idの次のスロットから検索を開始し、見つからなければ先頭から検索
+    /// current_idの次の位置から検索を開始し、見つからなければ先頭から検索
     pub fn peek_next_after(&mut self, current_id: Option<ThreadId>) -> Option<&mut Thread> {
-        if let Some(current) = current_id {
-            // 現在のスレッドのインデックスを探す
-            let mut current_index = None;
-            for (i, slot) in self.threads.iter().enumerate() {
-                if let Some(thread) = slot.as_ref() {
-                    if thread.id() == current {
-                        current_index = Some(i);
-                        break;
-                    }
-                }
-            }
-
-            if let Some(start_idx) = current_index {
-                // 現在のインデックスの次から検索
-                for i in (start_idx + 1)..Self::MAX_THREADS {
-                    if let Some(thread) = &self.threads[i] {
-                        if thread.state() == ThreadState::Ready {
-                            // インデックスを使って可変参照を返す
-                            return self.threads[i].as_mut();
-                        }
-                    }
-                }
-
-                // 見つからなければ先頭から現在のインデックスまで検索
-                for i in 0..=start_idx {
-                    if let Some(thread) = &self.threads[i] {
-                        if thread.state() == ThreadState::Ready {
-                            // インデックスを使って可変参照を返す
-                            return self.threads[i].as_mut();
-                        }
-                    }
-                }
-            }
-        }
-
-        // current_idがない場合は最初のReady状態のスレッドを返す
-        self.peek_next_mut()
+        let start = current_id
+            .and_then(|current| self.threads.iter().position(|t| t.id() == current))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+
+        let len = self.threads.len();
+        let index = (0..len)
+            .map(|i| (start + i) % len)
+            .find(|&i| self.threads[i].state() == ThreadState::Ready)?;
+        Some(&mut self.threads[index])
     }
 
     /// 指定された状態のスレッド数をカウント
     pub fn count_by_state(&self, state: ThreadState) -> usize {
-        self.threads
-            .iter()
-            .filter_map(|slot| slot.as_ref())
-            .filter(|t| t.state() == state)
-            .count()
+        self.iter().filter(|t| t.state() == state).count()
     }
 
     /// 指定されたプロセスに属するスレッドを反復処理
     pub fn iter_by_process(&self, process_id: ProcessId) -> impl Iterator<Item = &Thread> {
-        self.threads
-            .iter()
-            .filter_map(|slot| slot.as_ref())
-            .filter(move |t| t.process_id() == process_id)
+        self.iter().filter(move |t| t.process_id() == process_id)
     }
 
     /// すべてのスレッドを反復処理
     pub fn iter(&self) -> impl Iterator<Item = &Thread> {
-        self.threads.iter().filter_map(|slot| slot.as_ref())
+        self.threads.iter().map(|t| &**t)
     }
 
     /// すべてのスレッドを可変反復処理
     pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Thread> {
-        self.threads.iter_mut().filter_map(|slot| slot.as_mut())
+        self.threads.iter_mut().map(|t| &mut **t)
     }
 
     /// 現在のスレッド数を取得
     pub fn count(&self) -> usize {
-        self.count
+        self.threads.len()
     }
 
     /// スレッドキューが満杯かどうか
     pub fn is_full(&self) -> bool {
-        self.count >= Self::MAX_THREADS
+        self.threads.len() >= Self::MAX_THREADS
     }
 
     /// スレッドキューが空かどうか
     pub fn is_empty(&self) -> bool {
-        self.count == 0
+        self.threads.is_empty()
     }
 }
 
@@ -333,10 +475,53 @@ pub(super) static THREAD_QUEUE: SpinLock<ThreadQueue> = SpinLock::new(ThreadQueu
 pub(super) static CURRENT_THREAD: SpinLock<Option<ThreadId>> = SpinLock::new(None);
 
 /// スレッドキューにスレッドを追加
-pub fn add_thread(thread: Thread) -> Option<ThreadId> {
+pub fn add_thread(mut thread: Thread) -> Option<ThreadId> {
+    // 所属プロセスの優先度を引き継ぐ
+    if let Some(priority) = super::process::with_process(thread.process_id(), |p| p.priority()) {
+        thread.set_priority(priority);
+    }
     THREAD_QUEUE.lock().push(thread)
 }
 
+/// Ring0で動くスレッドをプロセスに追加
+///
+/// ガード付きのカーネルスタックを割り当て、`entry_point`から実行を始める
+pub fn add_kernel_thread(process_id: ProcessId, name: &str, entry_point: fn() -> !) -> Result<ThreadId> {
+    let kernel_stack = kstack::alloc()?;
+
+    let mut thread = Thread::new(process_id, name, entry_point, kernel_stack.base(), kernel_stack.size());
+    thread.set_owned_stack(kernel_stack);
+
+    match add_thread(thread) {
+        Some(id) => Ok(id),
+        None => {
+            unsafe { kstack::free(kernel_stack) };
+            Err(KernelError::Process(ProcessError::MaxProcessesReached))
+        }
+    }
+}
+
+/// Ring3で動くスレッドをプロセスに追加
+///
+/// ガードページ付きのカーネルスタック（Ring3からの割り込み時に使用）を割り当て、
+/// `context`から実行を始めるスレッドを作る
+pub fn add_user_thread(process_id: ProcessId, name: &str, context: Context) -> Result<ThreadId> {
+    let kernel_stack = kstack::alloc()?;
+
+    let entry_fn: fn() -> ! = unsafe { core::mem::transmute(context.rip) };
+    let mut thread = Thread::new(process_id, name, entry_fn, kernel_stack.base(), kernel_stack.size());
+    *thread.context_mut() = context;
+    thread.set_owned_stack(kernel_stack);
+
+    match add_thread(thread) {
+        Some(id) => Ok(id),
+        None => {
+            unsafe { kstack::free(kernel_stack) };
+            Err(KernelError::Process(ProcessError::MaxProcessesReached))
+        }
+    }
+}
+
 /// スレッドIDでスレッド情報を取得（読み取り専用操作）
 pub fn with_thread<F, R>(id: ThreadId, f: F) -> Option<R>
 where
@@ -355,9 +540,26 @@ where
     queue.get_mut(id).map(f)
 }
 
-/// スレッドを削除
+/// スレッドを削除し、スレッドが使っていた資源を解放する
+///
+/// FPUの所有者の記録、カーネルスタック、IPCのメールボックスを解放する。
+/// 削除するスレッドのカーネルスタック上から呼んではならない
 pub fn remove_thread(id: ThreadId) -> Option<Thread> {
-    THREAD_QUEUE.lock().remove(id)
+    let mut thread = THREAD_QUEUE.lock().remove(id)?;
+    super::fpu::release(id);
+
+    if let Some(stack) = thread.owned_stack.take() {
+        let rsp: u64;
+        unsafe { core::arch::asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack, preserves_flags)) };
+        if stack.contains(rsp) {
+            crate::warn!("remove_thread: thread {:?} is still on its kernel stack; leaking it", id);
+        } else {
+            unsafe { kstack::free(stack) };
+        }
+    }
+
+    crate::syscall::ipc::release_mailbox(id);
+    Some(thread)
 }
 
 /// 次に実行すべきスレッドIDを取得
@@ -391,6 +593,11 @@ pub fn current_thread_id() -> Option<ThreadId> {
     *CURRENT_THREAD.lock()
 }
 
+/// 現在実行中のスレッドが属するプロセスIDを取得
+pub fn current_process_id() -> Option<ProcessId> {
+    current_thread_id().and_then(|id| with_thread(id, |t| t.process_id()))
+}
+
 /// 現在実行中のスレッドIDを設定
 pub fn set_current_thread(id: Option<ThreadId>) {
     *CURRENT_THREAD.lock() = id;
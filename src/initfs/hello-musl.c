@@ -0,0 +1,25 @@
+/* Linux互換層の確認用（musl静的リンク）: printf / uname / getuid を使う */
+
+struct utsname {
+	char sysname[65];
+	char nodename[65];
+	char release[65];
+	char version[65];
+	char machine[65];
+	char domainname[65];
+};
+
+int printf(const char *fmt, ...);
+int uname(struct utsname *buf);
+unsigned int getuid(void);
+
+int main(int argc, char **argv)
+{
+	struct utsname u;
+
+	printf("Hello from musl! argc=%d argv[0]=%s\n", argc, argc > 0 ? argv[0] : "(null)");
+	if (uname(&u) == 0)
+		printf("uname: %s %s %s %s\n", u.sysname, u.nodename, u.release, u.machine);
+	printf("uid=%u\n", getuid());
+	return 0;
+}
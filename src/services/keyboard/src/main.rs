@@ -1,99 +1,64 @@
 #![no_std]
 #![no_main]
 
-use core::arch::asm;
 use core::panic::PanicInfo;
 
-const SYS_CONSOLE_WRITE: u64 = 5;
-const SYS_EXIT: u64 = 7;
-const SYS_KEYBOARD_READ: u64 = 8;
-const SYS_IPC_SEND: u64 = 3;
-const SYS_GET_THREAD_ID_BY_NAME: u64 = 10;
-const ENODATA: u64 = u64::MAX - 4;
-const EAGAIN: u64 = u64::MAX - 2;
-const ENOENT: u64 = u64::MAX - 3;
+use swiftcore_user as sys;
+
+/// Ctrl-Cの文字コード
+const CTRL_C: u8 = 0x03;
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _start() -> ! {
     write_str("keyboard service started\n");
 
     let shell_id = loop {
-        let id = syscall2(
-            SYS_GET_THREAD_ID_BY_NAME,
-            "core.service.shell".as_ptr() as u64,
-            "core.service.shell".len() as u64,
-        );
-        if id != ENOENT && id != EAGAIN {
+        let id = sys::thread_id_by_name("core.service.shell");
+        if !sys::Errno::is_error(id) {
             break id;
         }
-        unsafe { asm!("hlt", options(nomem, nostack, preserves_flags)); }
+        sys::sleep(1);
     };
 
+    // シェルから通知されるフォアグラウンドのプロセスID（0はなし）
+    let mut foreground = 0u64;
+
     loop {
-        let ch = syscall0(SYS_KEYBOARD_READ);
-        if ch == ENODATA {
-            unsafe { asm!("hlt", options(nomem, nostack, preserves_flags)); }
-            continue;
+        let mut sender = 0u64;
+        let message = sys::ipc_recv(Some(&mut sender), sys::IPC_NONBLOCK);
+        if message != sys::EAGAIN && sender == shell_id {
+            foreground = message;
         }
 
-        let ret = syscall2(SYS_IPC_SEND, shell_id, ch as u64);
-        if ret == EAGAIN {
-            unsafe { asm!("hlt", options(nomem, nostack, preserves_flags)); }
+        let ch = match sys::keyboard_read_char() {
+            Some(ch) => ch,
+            None => {
+                sys::sleep(1);
+                continue;
+            }
+        };
+
+        // Ctrl-Cはフォアグラウンドのプロセスへの割り込みにする
+        if ch == CTRL_C && foreground != 0 {
+            sys::kill(foreground, sys::SIGINT);
+            continue;
         }
+
+        // シェルがフォアグラウンドのプロセスを待っている間も止まらないよう、
+        // 受け取れない文字は捨てる
+        sys::ipc_send(shell_id, ch as u64, sys::IPC_NONBLOCK);
     }
 }
 
 fn write_str(s: &str) {
-    let _ = syscall2(SYS_CONSOLE_WRITE, s.as_ptr() as u64, s.len() as u64);
+    sys::console_write(s.as_bytes());
 }
 
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
     write_str("keyboard service panic\n");
-    let _ = syscall1(SYS_EXIT, 1);
+    sys::exit(1);
     loop {
-        unsafe { asm!("hlt", options(nomem, nostack, preserves_flags)); }
-    }
-}
-
-#[inline(always)]
-fn syscall1(num: u64, arg0: u64) -> u64 {
-    let ret: u64;
-    unsafe {
-        asm!(
-            "int 0x80",
-            inlateout("rax") num => ret,
-            in("rdi") arg0,
-            options(nostack, preserves_flags)
-        );
-    }
-    ret
-}
-
-#[inline(always)]
-fn syscall2(num: u64, arg0: u64, arg1: u64) -> u64 {
-    let ret: u64;
-    unsafe {
-        asm!(
-            "int 0x80",
-            inlateout("rax") num => ret,
-            in("rdi") arg0,
-            in("rsi") arg1,
-            options(nostack, preserves_flags)
-        );
-    }
-    ret
-}
-
-#[inline(always)]
-fn syscall0(num: u64) -> u64 {
-    let ret: u64;
-    unsafe {
-        asm!(
-            "int 0x80",
-            inlateout("rax") num => ret,
-            options(nostack, preserves_flags)
-        );
+        sys::sleep(1);
     }
-    ret
 }
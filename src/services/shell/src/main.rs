@@ -1,108 +1,119 @@
 #![no_std]
 #![no_main]
 
-use core::arch::asm;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
 use core::panic::PanicInfo;
 
-const SYS_CONSOLE_WRITE: u64 = 5;
-const SYS_INITFS_READ: u64 = 6;
-const SYS_EXIT: u64 = 7;
-const SYS_IPC_RECV: u64 = 4;
-const EAGAIN: u64 = u64::MAX - 2;
+use swiftcore_user as sys;
+
+/// /etc/motd を読み込む際のバッファサイズ
+const MOTD_BUF_SIZE: usize = 4096;
+/// Ctrl-Cの文字コード
+const CTRL_C: u8 = 0x03;
+/// シグナルで終了したプロセスの終了コードの下限（128+シグナル番号）
+const SIGNAL_EXIT_BASE: u64 = 128;
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _start() -> ! {
     write_str("SwiftCore shell\n");
     write_str("Type: (keyboard via IPC)\n");
 
-    let mut buf = [0u8; 128];
-    let read = syscall4(
-        SYS_INITFS_READ,
-        "/etc/motd".as_ptr() as u64,
-        "/etc/motd".len() as u64,
-        buf.as_mut_ptr() as u64,
-        buf.len() as u64,
-    );
+    let mut buf = vec![0u8; MOTD_BUF_SIZE];
+    let read = sys::initfs_read("/etc/motd", &mut buf);
 
     if read > 0 && read <= buf.len() as u64 {
-        if let Ok(text) = core::str::from_utf8(&buf[..read as usize]) {
-            write_str(text);
+        buf.truncate(read as usize);
+        if let Ok(text) = String::from_utf8(buf) {
+            write_str(&text);
             write_str("\n");
         }
     }
 
+    let mut line = String::new();
     loop {
         let mut sender = 0u64;
-        let ch = syscall1(SYS_IPC_RECV, &mut sender as *mut u64 as u64);
-        if ch == EAGAIN {
-            unsafe { asm!("hlt", options(nomem, nostack, preserves_flags)); }
+        let ch = sys::ipc_recv(Some(&mut sender), sys::IPC_WAIT_FOREVER);
+        if ch == sys::EAGAIN {
             continue;
         }
 
         let mut byte = ch as u8;
+        if byte == CTRL_C {
+            // 実行中のプロセスがない場合は入力中の行を破棄する
+            write_str("^C\n");
+            line.clear();
+            continue;
+        }
         if byte == b'\r' {
             byte = b'\n';
         }
-        let buf = [byte];
-        let _ = syscall2(SYS_CONSOLE_WRITE, buf.as_ptr() as u64, 1);
+        sys::console_write(&[byte]);
+
+        if byte == b'\n' {
+            run_command(&line);
+            line.clear();
+        } else {
+            line.push(byte as char);
+        }
     }
 }
 
-fn write_str(s: &str) {
-    let _ = syscall2(SYS_CONSOLE_WRITE, s.as_ptr() as u64, s.len() as u64);
-}
+/// 入力行を「パス 引数...」として解釈し、initfsのプログラムを起動する
+fn run_command(line: &str) {
+    let argv: Vec<&str> = line.split_whitespace().collect();
+    let path = match argv.first() {
+        Some(path) => *path,
+        None => return,
+    };
 
-#[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    write_str("shell panic\n");
-    let _ = syscall1(SYS_EXIT, 1);
-    loop {
-        unsafe { asm!("hlt", options(nomem, nostack, preserves_flags)); }
+    let pid = sys::spawn(path, &argv, &[], sys::Privilege::User);
+    if pid == sys::ENOENT {
+        write_str("command not found: ");
+        write_str(path);
+        write_str("\n");
+        return;
+    } else if sys::Errno::is_error(pid) {
+        write_str("failed to start: ");
+        write_str(path);
+        write_str("\n");
+        return;
     }
-}
 
-#[inline(always)]
-fn syscall1(num: u64, arg0: u64) -> u64 {
-    let ret: u64;
-    unsafe {
-        asm!(
-            "int 0x80",
-            inlateout("rax") num => ret,
-            in("rdi") arg0,
-            options(nostack, preserves_flags)
-        );
+    // 待っている間、Ctrl-Cをこのプロセスに送るようキーボードサービスに知らせる
+    let keyboard = sys::thread_id_by_name("core.service.keyboard");
+    let has_keyboard = !sys::Errno::is_error(keyboard);
+    if has_keyboard {
+        sys::ipc_send(keyboard, pid, sys::IPC_WAIT_FOREVER);
     }
-    ret
-}
 
-#[inline(always)]
-fn syscall2(num: u64, arg0: u64, arg1: u64) -> u64 {
-    let ret: u64;
-    unsafe {
-        asm!(
-            "int 0x80",
-            inlateout("rax") num => ret,
-            in("rdi") arg0,
-            in("rsi") arg1,
-            options(nostack, preserves_flags)
-        );
+    // 終了を待って回収する
+    let mut status = 0u64;
+    let reaped = sys::wait(pid, Some(&mut status), 0) == pid;
+
+    if has_keyboard {
+        sys::ipc_send(keyboard, 0, sys::IPC_WAIT_FOREVER);
     }
-    ret
+
+    if reaped && status == SIGNAL_EXIT_BASE + sys::SIGINT {
+        write_str("^C\n");
+    } else if reaped && status != 0 {
+        write_str("exited with non-zero status\n");
+    }
+}
+
+fn write_str(s: &str) {
+    sys::console_write(s.as_bytes());
 }
 
-#[inline(always)]
-fn syscall4(num: u64, arg0: u64, arg1: u64, arg2: u64, arg3: u64) -> u64 {
-    let ret: u64;
-    unsafe {
-        asm!(
-            "int 0x80",
-            inlateout("rax") num => ret,
-            in("rdi") arg0,
-            in("rsi") arg1,
-            in("rdx") arg2,
-            in("r10") arg3,
-            options(nostack, preserves_flags)
-        );
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    write_str("shell panic\n");
+    sys::exit(1);
+    loop {
+        sys::sleep(1);
     }
-    ret
 }
@@ -0,0 +1,186 @@
+//! ユーザー空間ヒープ
+//!
+//! プログラムブレーク（sbrk）で伸ばした領域を、アドレス順の空きブロックリストで管理する。
+//! 解放時は隣接する空きブロックと結合する。
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::memory::sbrk;
+
+/// ブロックの最小アラインメント
+const MIN_ALIGN: usize = 16;
+/// 一度にsbrkで伸ばす最小サイズ
+const GROW_SIZE: usize = 64 * 1024;
+
+/// 空きブロック（空き領域の先頭に埋め込む）
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+const BLOCK_SIZE: usize = core::mem::size_of::<FreeBlock>();
+
+struct Heap {
+    /// アドレス順の空きブロックリスト
+    head: *mut FreeBlock,
+}
+
+impl Heap {
+    /// 割り当て用のサイズとアラインメントに丸める
+    fn normalize(layout: &Layout) -> (usize, usize) {
+        let align = layout.align().max(MIN_ALIGN);
+        let size = layout.size().max(BLOCK_SIZE).next_multiple_of(MIN_ALIGN);
+        (size, align)
+    }
+
+    unsafe fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        let (size, align) = Self::normalize(&layout);
+        unsafe {
+            if let Some(ptr) = self.take(size, align) {
+                return ptr;
+            }
+            let grow = (size + align).max(GROW_SIZE).next_multiple_of(4096);
+            match sbrk(grow as i64) {
+                Some(start) => self.release(start as usize, grow),
+                None => return ptr::null_mut(),
+            }
+            self.take(size, align).unwrap_or(ptr::null_mut())
+        }
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = Self::normalize(&layout);
+        unsafe { self.release(ptr as usize, size) };
+    }
+
+    /// 条件を満たす最初の空きブロックから切り出す
+    unsafe fn take(&mut self, size: usize, align: usize) -> Option<*mut u8> {
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut cur = self.head;
+        unsafe {
+            while !cur.is_null() {
+                let block_start = cur as usize;
+                let block_end = block_start + (*cur).size;
+                let next = (*cur).next;
+
+                let mut start = block_start.next_multiple_of(align);
+                // 前方の余りが空きブロックとして使えない大きさなら、次の境界へずらす
+                if start != block_start && start - block_start < BLOCK_SIZE {
+                    start = (block_start + BLOCK_SIZE).next_multiple_of(align);
+                }
+                let end = start + size;
+
+                if end <= block_end {
+                    // ブロックをリストから外し、前後の余りを戻す
+                    self.unlink(prev, next);
+                    if start > block_start {
+                        self.release(block_start, start - block_start);
+                    }
+                    let tail = block_end - end;
+                    if tail >= BLOCK_SIZE {
+                        self.release(end, tail);
+                    }
+                    return Some(start as *mut u8);
+                }
+
+                prev = cur;
+                cur = next;
+            }
+        }
+        None
+    }
+
+    unsafe fn unlink(&mut self, prev: *mut FreeBlock, next: *mut FreeBlock) {
+        if prev.is_null() {
+            self.head = next;
+        } else {
+            unsafe { (*prev).next = next };
+        }
+    }
+
+    /// 領域を空きリストへ戻し、隣接ブロックと結合する
+    unsafe fn release(&mut self, addr: usize, size: usize) {
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut cur = self.head;
+        unsafe {
+            while !cur.is_null() && (cur as usize) < addr {
+                prev = cur;
+                cur = (*cur).next;
+            }
+
+            let block = addr as *mut FreeBlock;
+            block.write(FreeBlock { size, next: cur });
+
+            // 後ろのブロックと結合
+            if !cur.is_null() && addr + size == cur as usize {
+                (*block).size += (*cur).size;
+                (*block).next = (*cur).next;
+            }
+
+            // 前のブロックと結合
+            if !prev.is_null() && prev as usize + (*prev).size == addr {
+                (*prev).size += (*block).size;
+                (*prev).next = (*block).next;
+            } else if prev.is_null() {
+                self.head = block;
+            } else {
+                (*prev).next = block;
+            }
+        }
+    }
+}
+
+/// `#[global_allocator]`として登録するアロケータ
+pub struct UserHeapAllocator {
+    locked: AtomicBool,
+    heap: UnsafeCell<Heap>,
+}
+
+// ヒープへのアクセスはスピンロックで直列化する
+unsafe impl Sync for UserHeapAllocator {}
+
+impl UserHeapAllocator {
+    pub const fn new() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            heap: UnsafeCell::new(Heap {
+                head: ptr::null_mut(),
+            }),
+        }
+    }
+
+    fn with_heap<R>(&self, f: impl FnOnce(&mut Heap) -> R) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        let result = f(unsafe { &mut *self.heap.get() });
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+}
+
+impl Default for UserHeapAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for UserHeapAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.with_heap(|heap| unsafe { heap.alloc(layout) })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.with_heap(|heap| unsafe { heap.dealloc(ptr, layout) })
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: UserHeapAllocator = UserHeapAllocator::new();
@@ -1,16 +1,22 @@
 //! IPC 系システムコール（ユーザー側）
 
-use super::sys::{syscall1, syscall2, SyscallNumber};
+use super::sys::{syscall2, syscall3, SyscallNumber};
 
-/// IPC送信（宛先スレッドID, 値）
-pub fn ipc_send(dest_thread_id: u64, value: u64) -> u64 {
-    syscall2(SyscallNumber::IpcSend as u64, dest_thread_id, value)
+pub use swiftcore_abi::ipc::{IPC_NONBLOCK, IPC_WAIT_FOREVER};
+
+/// IPC送信（宛先スレッドID, 値, タイムアウトのティック数）
+///
+/// 宛先のメールボックスが満杯なら空くまで待つ。期限切れの場合はETIMEDOUT
+pub fn ipc_send(dest_thread_id: u64, value: u64, timeout: u64) -> u64 {
+    syscall3(SyscallNumber::IpcSend as u64, dest_thread_id, value, timeout)
 }
 
-/// IPC受信（送信元IDを受け取る場合はSome）
-pub fn ipc_recv(sender_out: Option<&mut u64>) -> u64 {
+/// IPC受信（送信元IDを受け取る場合はSome, タイムアウトのティック数）
+///
+/// メッセージが届くまで待つ。期限切れの場合はETIMEDOUT
+pub fn ipc_recv(sender_out: Option<&mut u64>, timeout: u64) -> u64 {
     let ptr = sender_out
         .map(|s| s as *mut u64 as u64)
         .unwrap_or(0);
-    syscall1(SyscallNumber::IpcRecv as u64, ptr)
+    syscall2(SyscallNumber::IpcRecv as u64, ptr, timeout)
 }
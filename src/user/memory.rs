@@ -0,0 +1,48 @@
+//! メモリ系システムコール（ユーザー側）
+
+use super::sys::{syscall1, syscall2, syscall3, syscall4, Errno, SyscallNumber};
+
+pub use swiftcore_abi::memory::{MAP_ANONYMOUS, MAP_FIXED, MAP_PRIVATE, PROT_EXEC, PROT_READ, PROT_WRITE};
+
+/// 匿名メモリをマップ（addrが0ならカーネルが配置先を選ぶ）
+pub fn mmap(addr: u64, len: u64, prot: u64, flags: u64) -> Option<*mut u8> {
+    let ret = syscall4(
+        SyscallNumber::Mmap as u64,
+        addr,
+        len,
+        prot,
+        flags | MAP_PRIVATE | MAP_ANONYMOUS,
+    );
+    if Errno::is_error(ret) {
+        None
+    } else {
+        Some(ret as *mut u8)
+    }
+}
+
+/// メモリのマップを解除
+pub fn munmap(addr: *mut u8, len: u64) -> u64 {
+    syscall2(SyscallNumber::Munmap as u64, addr as u64, len)
+}
+
+/// メモリの保護属性を変更
+pub fn mprotect(addr: *mut u8, len: u64, prot: u64) -> u64 {
+    syscall3(SyscallNumber::Mprotect as u64, addr as u64, len, prot)
+}
+
+/// プログラムブレークを設定（0なら現在値を取得）
+///
+/// 戻り値は設定後のブレーク。失敗した場合は変更前の値が返る
+pub fn brk(addr: u64) -> u64 {
+    syscall1(SyscallNumber::Brk as u64, addr)
+}
+
+/// プログラムブレークを増減し、変更前のブレークを返す
+pub fn sbrk(increment: i64) -> Option<*mut u8> {
+    let ret = syscall1(SyscallNumber::Sbrk as u64, increment as u64);
+    if Errno::is_error(ret) {
+        None
+    } else {
+        Some(ret as *mut u8)
+    }
+}
@@ -0,0 +1,61 @@
+//! プロセス系システムコール（ユーザー側）
+
+use alloc::vec::Vec;
+
+use swiftcore_abi::process::{StrRef, PRIVILEGE_SERVICE, PRIVILEGE_USER};
+
+use super::sys::{syscall2, syscall3, syscall5, SyscallNumber, ENOMEM};
+
+pub use swiftcore_abi::process::WNOHANG;
+
+/// 起動するプロセスの権限レベル
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Privilege {
+    /// システムサービス
+    Service = PRIVILEGE_SERVICE,
+    /// 一般アプリケーション
+    User = PRIVILEGE_USER,
+}
+
+/// 文字列配列を`StrRef`の配列にする（終端の要素で終わる）
+fn str_array(strings: &[&str]) -> Option<Vec<StrRef>> {
+    let mut array = Vec::new();
+    array.try_reserve_exact(strings.len() + 1).ok()?;
+    array.extend(strings.iter().map(|s| StrRef::new(s)));
+    array.push(StrRef::END);
+    Some(array)
+}
+
+/// initfsのELFを新しいプロセスとして起動し、プロセスIDを返す
+///
+/// `argv`が空の場合は`path`がargv[0]になる
+pub fn spawn(path: &str, argv: &[&str], envp: &[&str], privilege: Privilege) -> u64 {
+    let (argv, envp) = match (str_array(argv), str_array(envp)) {
+        (Some(argv), Some(envp)) => (argv, envp),
+        _ => return ENOMEM,
+    };
+    syscall5(
+        SyscallNumber::Spawn as u64,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        argv.as_ptr() as u64,
+        envp.as_ptr() as u64,
+        privilege as u64,
+    )
+}
+
+/// 子プロセスの終了を待って回収し、そのプロセスIDを返す
+///
+/// `pid`が0の場合はいずれかの子を待つ。終了コードを受け取る場合は`status`にSomeを渡す
+pub fn wait(pid: u64, status: Option<&mut u64>, options: u64) -> u64 {
+    let ptr = status.map(|s| s as *mut u64 as u64).unwrap_or(0);
+    syscall3(SyscallNumber::Wait as u64, pid, ptr, options)
+}
+
+/// システムコールのトレース（シリアルのログへの記録）を切り替え、以前の設定を返す
+///
+/// `pid`が0の場合は自プロセスが対象。Userの権限では`EPERM`になる
+pub fn trace(pid: u64, enable: bool) -> u64 {
+    syscall2(SyscallNumber::Trace as u64, pid, enable as u64)
+}
@@ -0,0 +1,55 @@
+//! シグナル系システムコール（ユーザー側）
+
+use core::arch::global_asm;
+
+use swiftcore_abi::signal::{SIG_DFL, SIG_IGN};
+
+use super::sys::{syscall2, syscall3, SyscallNumber};
+
+pub use swiftcore_abi::signal::{
+    SIGALRM, SIGCHLD, SIGCONT, SIGHUP, SIGINT, SIGKILL, SIGPIPE, SIGQUIT, SIGSEGV, SIGSTOP, SIGTERM, SIGTSTP,
+    SIGUSR1, SIGUSR2,
+};
+
+// ハンドラから`ret`で戻る先。カーネルが積んだシグナルフレームから元のコンテキストに戻る
+global_asm!(
+    r#"
+    .global __signal_restorer
+__signal_restorer:
+    mov rax, {sigreturn}
+    syscall
+    ud2
+"#,
+    sigreturn = const SyscallNumber::SigReturn as u64,
+);
+
+unsafe extern "C" {
+    fn __signal_restorer();
+}
+
+/// プロセスにシグナルを送る（`sig`が0の場合は送れるかどうかの確認のみ）
+pub fn kill(pid: u64, sig: u64) -> u64 {
+    syscall2(SyscallNumber::Kill as u64, pid, sig)
+}
+
+/// シグナルハンドラを登録し、以前のハンドラ（0=既定, 1=無視）を返す
+///
+/// ハンドラの実行中は同じシグナルの配送を保留する
+pub fn sigaction(sig: u64, handler: extern "C" fn(u64)) -> u64 {
+    syscall3(
+        SyscallNumber::SigAction as u64,
+        sig,
+        handler as usize as u64,
+        __signal_restorer as *const () as u64,
+    )
+}
+
+/// シグナルの動作を既定に戻す
+pub fn signal_default(sig: u64) -> u64 {
+    syscall3(SyscallNumber::SigAction as u64, sig, SIG_DFL, 0)
+}
+
+/// シグナルを無視する
+pub fn signal_ignore(sig: u64) -> u64 {
+    syscall3(SyscallNumber::SigAction as u64, sig, SIG_IGN, 0)
+}
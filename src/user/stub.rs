@@ -1,20 +1,34 @@
 //! ユーザー側システムコールスタブ
 //!
 //! 種類ごとのモジュールに分割し、ここから再エクスポートする。
+//! sbrkで伸ばすヒープを`#[global_allocator]`として登録するため、
+//! リンクしたプログラムは`alloc`クレートを使用できる。
+
+#![no_std]
+
+extern crate alloc;
 
 pub mod ipc;
 pub mod task;
+pub mod process;
+pub mod signal;
 pub mod time;
 pub mod console;
 pub mod fs;
 pub mod keyboard;
+pub mod memory;
+pub mod heap;
 
 mod sys;
 
-pub use sys::SyscallNumber;
-pub use ipc::{ipc_recv, ipc_send};
-pub use task::{yield_now, exit, current_thread_id, thread_id_by_name};
-pub use time::get_ticks;
+pub use swiftcore_abi as abi;
+pub use sys::{Errno, SyscallNumber, EAGAIN, EFAULT, EINVAL, ENODATA, ENOENT, ENOMEM, EPERM, ETIMEDOUT, ECHILD};
+pub use ipc::{ipc_recv, ipc_send, IPC_NONBLOCK, IPC_WAIT_FOREVER};
+pub use task::{yield_now, exit, current_thread_id, thread_id_by_name, thread_create, thread_join, thread_exit};
+pub use process::{spawn, trace, wait, Privilege, WNOHANG};
+pub use signal::{kill, sigaction, signal_default, signal_ignore, SIGINT, SIGKILL, SIGTERM};
+pub use time::{get_ticks, sleep, TICKS_PER_SECOND};
 pub use console::write as console_write;
 pub use fs::read as initfs_read;
 pub use keyboard::read_char as keyboard_read_char;
+pub use memory::{brk, mmap, mprotect, munmap, sbrk};
@@ -1,15 +1,15 @@
 //! タスク系システムコール（ユーザー側）
 
-use super::sys::{syscall0, syscall2, SyscallNumber};
+use super::sys::{syscall0, syscall1, syscall2, syscall3, SyscallNumber};
 
 /// スケジューラに実行権を譲る
 pub fn yield_now() {
     let _ = syscall0(SyscallNumber::Yield as u64);
 }
 
-/// 現在のスレッドを終了
+/// 現在のプロセスを終了（終了コードは親が`wait`で受け取る）
 pub fn exit(code: u64) -> u64 {
-    super::sys::syscall1(SyscallNumber::Exit as u64, code)
+    syscall1(SyscallNumber::Exit as u64, code)
 }
 
 /// 現在のスレッドIDを取得
@@ -25,3 +25,29 @@ pub fn thread_id_by_name(name: &str) -> u64 {
         name.len() as u64,
     )
 }
+
+/// 同じプロセスにスレッドを作成し、スレッドIDを返す
+///
+/// 新しいスレッドは`entry(arg)`から始まる。`stack_top`がNoneの場合は
+/// カーネルがユーザースタックを確保する。`entry`からは戻らず`thread_exit`で終えること
+pub fn thread_create(entry: extern "C" fn(u64) -> !, stack_top: Option<u64>, arg: u64) -> u64 {
+    syscall3(
+        SyscallNumber::ThreadCreate as u64,
+        entry as usize as u64,
+        stack_top.unwrap_or(0),
+        arg,
+    )
+}
+
+/// スレッドの終了を待ち、終了コードを返す
+pub fn thread_join(thread_id: u64) -> u64 {
+    syscall1(SyscallNumber::ThreadJoin as u64, thread_id)
+}
+
+/// 終了コードを指定して現在のスレッドを終了
+pub fn thread_exit(code: u64) -> ! {
+    syscall1(SyscallNumber::ThreadExit as u64, code);
+    loop {
+        yield_now();
+    }
+}
@@ -1,8 +1,15 @@
 //! 時刻系システムコール（ユーザー側）
 
-use super::sys::{syscall0, SyscallNumber};
+use super::sys::{syscall0, syscall1, SyscallNumber};
+
+pub use swiftcore_abi::TICKS_PER_SECOND;
 
 /// タイマーティック数を取得
 pub fn get_ticks() -> u64 {
     syscall0(SyscallNumber::GetTicks as u64)
 }
+
+/// 指定ティック数スリープ（0の場合は実行権を譲るだけ）
+pub fn sleep(ticks: u64) {
+    let _ = syscall1(SyscallNumber::Sleep as u64, ticks);
+}